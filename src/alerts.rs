@@ -0,0 +1,210 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::{
+    cache::Refreshed,
+    quirks,
+    upstream::{Priority, Transport, Upstream},
+};
+
+const SERVICE_ALERTS_URL: &str = "http://api.511.org/transit/servicealerts";
+
+/// The 511 service alerts feed, GTFS-realtime written as JSON.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Feed {
+    #[serde(default)]
+    entities: Vec<Entity>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Entity {
+    alert: Option<Alert>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Alert {
+    #[serde(default)]
+    active_periods: Vec<ActivePeriod>,
+    #[serde(default)]
+    informed_entities: Vec<InformedEntity>,
+    header_text: Option<TranslatedString>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ActivePeriod {
+    #[serde(default, deserialize_with = "quirks::opt_i64")]
+    start: Option<i64>,
+    #[serde(default, deserialize_with = "quirks::opt_i64")]
+    end: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct InformedEntity {
+    #[serde(default, deserialize_with = "quirks::opt_string")]
+    route_id: Option<String>,
+    #[serde(default, deserialize_with = "quirks::opt_string")]
+    stop_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct TranslatedString {
+    #[serde(default)]
+    translations: Vec<Translation>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Translation {
+    text: String,
+    language: Option<String>,
+}
+
+impl TranslatedString {
+    /// The English text, or the first translation if there's none marked
+    /// English.
+    fn english(&self) -> Option<&str> {
+        self.translations
+            .iter()
+            .find(|t| t.language.as_deref().is_some_and(|l| l.starts_with("en")))
+            .or(self.translations.first())
+            .map(|t| t.text.as_str())
+    }
+}
+
+/// A detour, delay or other disruption announced by the agency.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceAlert {
+    pub text: String,
+    /// Lines the alert affects.
+    pub lines: Vec<String>,
+    /// Stops the alert affects.
+    pub stops: Vec<String>,
+    active: Vec<(Option<i64>, Option<i64>)>,
+}
+
+impl ServiceAlert {
+    /// An alert in effect until it's withdrawn.
+    pub fn new(text: impl Into<String>, lines: Vec<String>, stops: Vec<String>) -> Self {
+        Self {
+            text: text.into(),
+            lines,
+            stops,
+            active: Vec::new(),
+        }
+    }
+
+    /// Whether the alert is in effect at `now`. Alerts without active
+    /// periods are in effect until they're withdrawn from the feed.
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        let now = now.timestamp();
+        self.active.is_empty()
+            || self.active.iter().any(|(start, end)| {
+                start.is_none_or(|start| start <= now) && end.is_none_or(|end| now < end)
+            })
+    }
+
+    /// Whether the alert affects any of `lines` or `stops`.
+    pub fn affects(&self, lines: &[&str], stops: &[&str]) -> bool {
+        self.lines.iter().any(|line| lines.contains(&line.as_str()))
+            || self.stops.iter().any(|stop| stops.contains(&stop.as_str()))
+    }
+}
+
+/// The alerts in effect at `now` that affect any of `lines` or `stops`.
+pub fn relevant(
+    alerts: Vec<ServiceAlert>,
+    lines: &[&str],
+    stops: &[&str],
+    now: DateTime<Utc>,
+) -> Vec<ServiceAlert> {
+    alerts
+        .into_iter()
+        .filter(|alert| alert.is_active(now) && alert.affects(lines, stops))
+        .collect()
+}
+
+/// Parse the 511 service alerts feed.
+pub fn parse(body: &str) -> eyre::Result<Vec<ServiceAlert>> {
+    let feed: Feed = quirks::from_json(body)?;
+
+    Ok(feed
+        .entities
+        .into_iter()
+        .filter_map(|entity| entity.alert)
+        .filter_map(|alert| {
+            let text = alert.header_text.as_ref()?.english()?.trim().to_owned();
+            Some(ServiceAlert {
+                text,
+                lines: alert
+                    .informed_entities
+                    .iter()
+                    .filter_map(|e| e.route_id.clone())
+                    .collect(),
+                stops: alert
+                    .informed_entities
+                    .iter()
+                    .filter_map(|e| e.stop_id.clone())
+                    .collect(),
+                active: alert
+                    .active_periods
+                    .iter()
+                    .map(|period| (period.start, period.end))
+                    .collect(),
+            })
+        })
+        .filter(|alert| !alert.text.is_empty())
+        .collect())
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertsConfig {
+    /// How long fetched alerts are reused before asking 511 again. Alerts
+    /// change slowly, and each fetch spends from the hourly request budget.
+    pub refresh_secs: u64,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self { refresh_secs: 600 }
+    }
+}
+
+/// Service alerts, refetched at most every `refresh_secs`.
+#[derive(Debug)]
+pub struct AlertsCache(Option<Refreshed<Vec<ServiceAlert>>>);
+
+impl AlertsCache {
+    /// A cache that does nothing if `config` is `None`.
+    pub fn new(config: Option<AlertsConfig>) -> Self {
+        Self(config.map(|config| Refreshed::new(Duration::from_secs(config.refresh_secs))))
+    }
+
+    /// Every alert in the feed, fetched again if the cached copy is stale.
+    pub async fn get(
+        &self,
+        client: &dyn Transport,
+        upstream: &Upstream,
+    ) -> eyre::Result<Vec<ServiceAlert>> {
+        let Some(cache) = &self.0 else {
+            return Ok(Vec::new());
+        };
+
+        cache
+            .get("service alerts", async {
+                let body = upstream
+                    .get(client, SERVICE_ALERTS_URL, Priority::Low)
+                    .await?;
+                parse(&body)
+            })
+            .await
+    }
+}
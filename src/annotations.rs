@@ -0,0 +1,76 @@
+use std::{cmp::Reverse, sync::Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use eyre::ensure;
+use serde::{Deserialize, Serialize};
+
+/// The longest an annotation may stay on the board.
+const MAX_EXPIRES_IN_SECS: i64 = 24 * 60 * 60;
+
+fn default_expires_in_secs() -> i64 {
+    10 * 60
+}
+
+/// Ordered from least to most severe.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    #[default]
+    Info,
+    Warning,
+    Critical,
+}
+
+/// A short message from an external system ("Laundry is done"), shown in the
+/// board's banner until it expires.
+#[derive(Serialize, Debug, Clone)]
+pub struct Annotation {
+    pub text: String,
+    pub severity: Severity,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NewAnnotation {
+    pub text: String,
+    #[serde(default)]
+    pub severity: Severity,
+    #[serde(default = "default_expires_in_secs")]
+    pub expires_in_secs: i64,
+}
+
+#[derive(Debug, Default)]
+pub struct AnnotationStore {
+    annotations: Mutex<Vec<Annotation>>,
+}
+
+impl AnnotationStore {
+    pub fn add(&self, new: NewAnnotation, now: DateTime<Utc>) -> eyre::Result<Annotation> {
+        ensure!(!new.text.trim().is_empty(), "annotation text is empty");
+        ensure!(
+            (1..=MAX_EXPIRES_IN_SECS).contains(&new.expires_in_secs),
+            "expires_in_secs must be between 1 and {MAX_EXPIRES_IN_SECS}"
+        );
+
+        let annotation = Annotation {
+            text: new.text.trim().to_owned(),
+            severity: new.severity,
+            expires_at: now + Duration::seconds(new.expires_in_secs),
+        };
+        self.annotations.lock().unwrap().push(annotation.clone());
+
+        Ok(annotation)
+    }
+
+    /// Unexpired annotations, most severe first, then newest first.
+    pub fn active(&self, now: DateTime<Utc>) -> Vec<Annotation> {
+        let mut annotations = self.annotations.lock().unwrap();
+        annotations.retain(|annotation| annotation.expires_at > now);
+
+        let mut active: Vec<_> = annotations.iter().rev().cloned().collect();
+        active.sort_by_key(|annotation| Reverse(annotation.severity));
+
+        active
+    }
+}
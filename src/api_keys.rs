@@ -0,0 +1,299 @@
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use reqwest::{header::HeaderMap, StatusCode};
+
+/// How long to rest a key that was rate limited without a `Retry-After`.
+const RATE_LIMIT_COOLDOWN: Duration = Duration::from_secs(10 * 60);
+
+/// How long to rest a key that was rejected outright. It may have been
+/// revoked, but a key that was only briefly misconfigured upstream will come
+/// back into rotation on its own.
+const REJECTED_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+/// A set of 511 API keys used in rotation, so several households can share a
+/// deployment without any one key running out of quota. Keys that get rate
+/// limited or rejected are rested and the request fails over to the next.
+#[derive(Debug)]
+pub struct KeyPool {
+    keys: Mutex<Vec<KeyState>>,
+    next: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct KeyState {
+    key: String,
+    requests: u64,
+    rate_limited: u64,
+    rejected: u64,
+    /// The quota left as of the last response, if upstream reported it.
+    remaining: Option<u64>,
+    cooling_down_until: Option<Instant>,
+}
+
+impl KeyState {
+    /// Enough of the key to tell keys apart in metrics without leaking them.
+    fn label(&self) -> String {
+        let tail: String = self.key.chars().rev().take(4).collect();
+        format!("…{}", tail.chars().rev().collect::<String>())
+    }
+
+    fn is_cooling_down(&self, now: Instant) -> bool {
+        self.cooling_down_until.is_some_and(|until| until > now)
+    }
+}
+
+impl KeyPool {
+    pub fn new(keys: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            keys: Mutex::new(
+                keys.into_iter()
+                    .map(|key| KeyState {
+                        key,
+                        requests: 0,
+                        rate_limited: 0,
+                        rejected: 0,
+                        remaining: None,
+                        cooling_down_until: None,
+                    })
+                    .collect(),
+            ),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// The keys to try for one request, as (index, key) pairs. The rotation
+    /// starts one further along each call; resting keys are left out unless
+    /// every key is resting, in which case they're all tried anyway.
+    pub fn rotation(&self) -> Vec<(usize, String)> {
+        self.rotation_at(Instant::now())
+    }
+
+    fn rotation_at(&self, now: Instant) -> Vec<(usize, String)> {
+        let keys = self.keys.lock().unwrap();
+        if keys.is_empty() {
+            return Vec::new();
+        }
+
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % keys.len();
+        let order = (0..keys.len()).map(|offset| (start + offset) % keys.len());
+
+        let available: Vec<_> = order
+            .clone()
+            .filter(|&i| !keys[i].is_cooling_down(now))
+            .map(|i| (i, keys[i].key.clone()))
+            .collect();
+
+        if available.is_empty() {
+            order.map(|i| (i, keys[i].key.clone())).collect()
+        } else {
+            available
+        }
+    }
+
+    /// Record upstream's response to a request made with key `index`.
+    pub fn record(&self, index: usize, status: StatusCode, headers: &HeaderMap) {
+        self.record_at(index, status, headers, Instant::now());
+    }
+
+    fn record_at(&self, index: usize, status: StatusCode, headers: &HeaderMap, now: Instant) {
+        let mut keys = self.keys.lock().unwrap();
+        let key = &mut keys[index];
+
+        key.requests += 1;
+        if let Some(remaining) = header_number(headers, "RateLimit-Remaining") {
+            key.remaining = Some(remaining);
+        }
+
+        match status {
+            StatusCode::TOO_MANY_REQUESTS => {
+                key.rate_limited += 1;
+                key.remaining = Some(0);
+                let cooldown = header_number(headers, "Retry-After")
+                    .map(Duration::from_secs)
+                    .unwrap_or(RATE_LIMIT_COOLDOWN);
+                key.cooling_down_until = Some(now + cooldown);
+            }
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                key.rejected += 1;
+                key.cooling_down_until = Some(now + REJECTED_COOLDOWN);
+            }
+            _ => key.cooling_down_until = None,
+        }
+    }
+
+    /// Per-key usage in the Prometheus text exposition format.
+    pub fn metrics(&self) -> String {
+        let keys = self.keys.lock().unwrap();
+        let now = Instant::now();
+        let mut metrics = String::new();
+
+        let mut family =
+            |name: &str, kind: &str, help: &str, value: &dyn Fn(&KeyState) -> Option<u64>| {
+                let _ = writeln!(metrics, "# HELP {name} {help}");
+                let _ = writeln!(metrics, "# TYPE {name} {kind}");
+                for key in keys.iter() {
+                    if let Some(value) = value(key) {
+                        let _ = writeln!(metrics, "{name}{{key=\"{}\"}} {value}", key.label());
+                    }
+                }
+            };
+
+        family(
+            "transit_kindle_upstream_requests_total",
+            "counter",
+            "Requests sent to the 511 API with each key.",
+            &|key| Some(key.requests),
+        );
+        family(
+            "transit_kindle_upstream_rate_limited_total",
+            "counter",
+            "Requests the 511 API rate limited, per key.",
+            &|key| Some(key.rate_limited),
+        );
+        family(
+            "transit_kindle_upstream_rejected_total",
+            "counter",
+            "Requests the 511 API rejected as unauthorized, per key.",
+            &|key| Some(key.rejected),
+        );
+        family(
+            "transit_kindle_upstream_quota_remaining",
+            "gauge",
+            "Requests left in each key's quota, as last reported by the 511 API.",
+            &|key| key.remaining,
+        );
+        family(
+            "transit_kindle_upstream_key_resting",
+            "gauge",
+            "Whether each key is out of rotation after being rate limited or rejected.",
+            &|key| Some(key.is_cooling_down(now) as u64),
+        );
+
+        metrics
+    }
+}
+
+fn header_number(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn pool(keys: &[&str]) -> KeyPool {
+        KeyPool::new(keys.iter().map(|key| key.to_string()))
+    }
+
+    fn keys(rotation: Vec<(usize, String)>) -> Vec<String> {
+        rotation.into_iter().map(|(_, key)| key).collect()
+    }
+
+    fn retry_after(secs: u64) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("Retry-After", HeaderValue::from(secs));
+        headers
+    }
+
+    #[test]
+    fn rotation_starts_one_further_each_time() {
+        let pool = pool(&["a", "b", "c"]);
+
+        assert_eq!(keys(pool.rotation()), ["a", "b", "c"]);
+        assert_eq!(keys(pool.rotation()), ["b", "c", "a"]);
+        assert_eq!(keys(pool.rotation()), ["c", "a", "b"]);
+        assert_eq!(keys(pool.rotation()), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn rate_limited_key_is_skipped() {
+        let pool = pool(&["a", "b", "c"]);
+        let now = Instant::now();
+        pool.record_at(0, StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new(), now);
+
+        assert_eq!(keys(pool.rotation_at(now)), ["b", "c"]);
+        assert_eq!(keys(pool.rotation_at(now)), ["b", "c"]);
+        assert_eq!(keys(pool.rotation_at(now)), ["c", "b"]);
+    }
+
+    #[test]
+    fn rate_limited_key_returns_after_retry_after() {
+        let pool = pool(&["a", "b"]);
+        let now = Instant::now();
+        pool.record_at(0, StatusCode::TOO_MANY_REQUESTS, &retry_after(30), now);
+
+        let resting = keys(pool.rotation_at(now + Duration::from_secs(29)));
+        let rested = keys(pool.rotation_at(now + Duration::from_secs(30)));
+
+        assert_eq!(resting, ["b"]);
+        assert_eq!(rested, ["b", "a"]);
+    }
+
+    #[test]
+    fn rate_limited_key_without_retry_after_rests_the_default() {
+        let pool = pool(&["a", "b"]);
+        let now = Instant::now();
+        pool.record_at(0, StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new(), now);
+
+        let just_before = now + RATE_LIMIT_COOLDOWN - Duration::from_secs(1);
+        assert!(!keys(pool.rotation_at(just_before)).contains(&"a".to_owned()));
+        assert!(keys(pool.rotation_at(now + RATE_LIMIT_COOLDOWN)).contains(&"a".to_owned()));
+    }
+
+    #[test]
+    fn rejected_key_rests_longer() {
+        let pool = pool(&["a", "b"]);
+        let now = Instant::now();
+        pool.record_at(0, StatusCode::FORBIDDEN, &HeaderMap::new(), now);
+
+        let after_rate_limit = now + RATE_LIMIT_COOLDOWN;
+        assert!(!keys(pool.rotation_at(after_rate_limit)).contains(&"a".to_owned()));
+        assert!(keys(pool.rotation_at(now + REJECTED_COOLDOWN)).contains(&"a".to_owned()));
+    }
+
+    #[test]
+    fn success_ends_a_rest_early() {
+        let pool = pool(&["a", "b"]);
+        let now = Instant::now();
+        pool.record_at(0, StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new(), now);
+        pool.record_at(0, StatusCode::OK, &HeaderMap::new(), now);
+
+        assert_eq!(keys(pool.rotation_at(now)).len(), 2);
+    }
+
+    #[test]
+    fn every_key_is_tried_when_all_are_resting() {
+        let pool = pool(&["a", "b"]);
+        let now = Instant::now();
+        pool.record_at(0, StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new(), now);
+        pool.record_at(1, StatusCode::UNAUTHORIZED, &HeaderMap::new(), now);
+
+        assert_eq!(keys(pool.rotation_at(now)), ["a", "b"]);
+    }
+
+    #[test]
+    fn no_keys_means_nothing_to_try() {
+        assert!(pool(&[]).rotation().is_empty());
+    }
+
+    #[test]
+    fn quota_is_read_from_responses() {
+        let pool = pool(&["abcdef"]);
+        let mut headers = HeaderMap::new();
+        headers.insert("RateLimit-Remaining", HeaderValue::from(42));
+        pool.record(0, StatusCode::OK, &headers);
+
+        assert!(pool
+            .metrics()
+            .contains("transit_kindle_upstream_quota_remaining{key=\"…cdef\"} 42"));
+    }
+}
@@ -0,0 +1,159 @@
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::cache::Refreshed;
+
+/// Bay Wheels' GBFS station status feed.
+const BAY_WHEELS_STATUS_URL: &str = "https://gbfs.lyft.com/gbfs/2.3/bay/en/station_status.json";
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BikeStation {
+    /// The `station_id` in the GBFS feed.
+    pub id: String,
+    /// What the station is called on the board.
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BikesConfig {
+    pub stations: Vec<BikeStation>,
+    /// A GBFS `station_status.json`, for systems other than Bay Wheels.
+    pub status_url: String,
+    /// How long station status is reused before asking again.
+    pub refresh_secs: u64,
+}
+
+impl Default for BikesConfig {
+    fn default() -> Self {
+        Self {
+            stations: Vec::new(),
+            status_url: BAY_WHEELS_STATUS_URL.to_owned(),
+            refresh_secs: 60,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct StatusFeed {
+    data: StatusData,
+}
+
+#[derive(Deserialize, Debug)]
+struct StatusData {
+    stations: Vec<Status>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Status {
+    station_id: String,
+    #[serde(default)]
+    num_bikes_available: u32,
+    #[serde(default)]
+    num_ebikes_available: u32,
+    #[serde(default)]
+    num_docks_available: u32,
+    #[serde(default = "default_true")]
+    is_renting: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// What's available at one configured station.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Availability {
+    pub name: String,
+    /// Classic bikes, not counting e-bikes.
+    pub bikes: u32,
+    pub ebikes: u32,
+    pub docks: u32,
+    pub renting: bool,
+}
+
+impl Availability {
+    /// e.g. "Castro: 4 bikes · 2 e-bikes · 9 docks".
+    pub fn label(&self) -> String {
+        if !self.renting {
+            return format!("{}: closed", self.name);
+        }
+
+        format!(
+            "{}: {} bikes · {} e-bikes · {} docks",
+            self.name, self.bikes, self.ebikes, self.docks
+        )
+    }
+}
+
+/// Availability at each of `stations`, in config order. Stations missing from
+/// the feed are left out.
+pub fn parse(body: &str, stations: &[BikeStation]) -> eyre::Result<Vec<Availability>> {
+    let feed: StatusFeed = serde_json::from_str(body)?;
+
+    Ok(stations
+        .iter()
+        .filter_map(|station| {
+            let status = feed
+                .data
+                .stations
+                .iter()
+                .find(|status| status.station_id == station.id)?;
+
+            Some(Availability {
+                name: station.name.clone(),
+                bikes: status
+                    .num_bikes_available
+                    .saturating_sub(status.num_ebikes_available),
+                ebikes: status.num_ebikes_available,
+                docks: status.num_docks_available,
+                renting: status.is_renting,
+            })
+        })
+        .collect())
+}
+
+/// Bike-share availability at the configured stations, refetched at most
+/// every `refresh_secs`.
+#[derive(Debug)]
+pub struct BikeShare {
+    config: Option<BikesConfig>,
+    cache: Refreshed<Vec<Availability>>,
+}
+
+impl BikeShare {
+    /// Reports nothing if `config` is `None`.
+    pub fn new(config: Option<BikesConfig>) -> Self {
+        let refresh_secs = config.as_ref().map_or(0, |config| config.refresh_secs);
+
+        Self {
+            config,
+            cache: Refreshed::new(Duration::from_secs(refresh_secs)),
+        }
+    }
+
+    /// Availability at the configured stations, or `None` if bike share isn't
+    /// configured.
+    pub async fn get(&self, client: &Client) -> Option<eyre::Result<Vec<Availability>>> {
+        let config = self.config.as_ref()?;
+
+        let availability = self
+            .cache
+            .get("bike share", async {
+                let body = client
+                    .get(&config.status_url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await?;
+                parse(&body, &config.stations)
+            })
+            .await;
+
+        Some(availability)
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+use crate::{
+    alerts::ServiceAlert,
+    annotations::Annotation,
+    bikes::Availability,
+    departures::{LinesDestinationsToJourneys, WalkTimes},
+    routes::Routes,
+    shapes::Shapes,
+    vehicles::Vehicle,
+    weather::Weather,
+};
+
+/// Everything a frame is drawn from.
+#[derive(Debug, Default)]
+pub struct Board {
+    /// Departures by direction.
+    pub departures: HashMap<String, LinesDestinationsToJourneys>,
+    pub annotations: Vec<Annotation>,
+    /// Service alerts affecting the board's lines or stops.
+    pub alerts: Vec<ServiceAlert>,
+    /// Configured stations with an elevator out of service, if outages are
+    /// being tracked.
+    pub elevator_outages: Option<Vec<String>>,
+    pub weather: Option<Weather>,
+    /// Availability at the configured bike-share stations, if configured.
+    pub bikes: Option<Vec<Availability>>,
+    /// Why a data source couldn't be fetched, by source name (e.g.
+    /// `departures`). Panels fed by a failed source draw a placeholder.
+    pub errors: BTreeMap<String, String>,
+    /// Which page of rows to show in panels with more rows than fit. Counts
+    /// up with each refresh and wraps around each panel's page count.
+    pub page: usize,
+    pub walk_times: WalkTimes,
+    /// Lines listed first in departures panels, in this order.
+    pub line_order: Vec<String>,
+    /// Route colors and modes from the configured GTFS routes.
+    pub routes: Routes,
+    /// Route paths for map panels.
+    pub shapes: Arc<Shapes>,
+    /// Where tracked vehicles are, if vehicle positions are configured.
+    pub vehicles: Vec<Vehicle>,
+    /// Timezone that times on the board are shown in. UTC if unset.
+    pub timezone: Option<Tz>,
+    /// The moment the board is drawn as of. The current time if unset; tests
+    /// pin it so the same departures always draw the same frame.
+    pub now: Option<DateTime<Utc>>,
+}
+
+impl Board {
+    /// `instant` in the board's timezone.
+    pub fn local(&self, instant: DateTime<Utc>) -> DateTime<Tz> {
+        instant.with_timezone(&self.timezone.unwrap_or(Tz::UTC))
+    }
+
+    /// Every line with departures on the board.
+    pub fn lines(&self) -> Vec<&str> {
+        let mut lines: Vec<&str> = self
+            .departures
+            .values()
+            .flat_map(|by_line| by_line.keys())
+            .map(|(line, _)| line.as_str())
+            .collect();
+        lines.sort_unstable();
+        lines.dedup();
+
+        lines
+    }
+}
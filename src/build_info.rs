@@ -0,0 +1,33 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::config::Config;
+
+/// What exactly a deployment is running, for debugging it remotely.
+#[derive(Serialize, Debug)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_commit: &'static str,
+    pub build_date: Option<DateTime<Utc>>,
+    pub features: Vec<&'static str>,
+    /// SHA-256 of the loaded config file, or `None` when running on defaults.
+    pub config_hash: Option<String>,
+}
+
+impl BuildInfo {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_commit: env!("BUILD_GIT_COMMIT"),
+            build_date: env!("BUILD_TIMESTAMP")
+                .parse()
+                .ok()
+                .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0)),
+            features: env!("BUILD_FEATURES")
+                .split(',')
+                .filter(|feature| !feature.is_empty())
+                .collect(),
+            config_hash: config.hash.clone(),
+        }
+    }
+}
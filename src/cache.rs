@@ -0,0 +1,63 @@
+use std::{
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tracing::warn;
+
+/// A value fetched from a slow-changing feed, reused until it's `max_age`
+/// old so polling the board doesn't spend the request budget on it.
+#[derive(Debug)]
+pub struct Refreshed<T> {
+    max_age: Duration,
+    latest: Mutex<Option<(Instant, T)>>,
+}
+
+impl<T: Clone> Refreshed<T> {
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            max_age,
+            latest: Mutex::new(None),
+        }
+    }
+
+    /// The cached value, or the result of `fetch` if it's stale. A failed
+    /// fetch falls back to the stale value, if there is one.
+    pub async fn get(
+        &self,
+        what: &str,
+        fetch: impl Future<Output = eyre::Result<T>>,
+    ) -> eyre::Result<T> {
+        let fresh = self
+            .latest
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|(fetched, _)| fetched.elapsed() < self.max_age)
+            .map(|(_, value)| value.clone());
+        if let Some(value) = fresh {
+            return Ok(value);
+        }
+
+        let fetched = fetch.await;
+
+        let mut latest = self.latest.lock().unwrap();
+        match fetched {
+            Ok(value) => {
+                *latest = Some((Instant::now(), value.clone()));
+                Ok(value)
+            }
+            Err(e) => match latest.take() {
+                Some((_, value)) => {
+                    warn!("couldn't refresh {what}, keeping the last copy: {e:#}");
+                    // Wait out another interval before retrying, rather than
+                    // spending the budget on every refresh while it's down.
+                    *latest = Some((Instant::now(), value.clone()));
+                    Ok(value)
+                }
+                None => Err(e),
+            },
+        }
+    }
+}
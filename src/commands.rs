@@ -0,0 +1,440 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::Arc,
+};
+
+use eyre::{ensure, eyre};
+use reqwest::Client;
+use serde::Serialize;
+
+use crate::{
+    board::Board,
+    config::Config,
+    departures::{self, LinesDestinationsToJourneys, Stop},
+    layout::{Layout, Layouts, Orientation, Panel},
+    render::{self, Output},
+    siri,
+    upstream::{Tape, Upstream, DEFAULT_AGENCY},
+};
+
+/// The outcome of a one-shot subcommand.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Ok,
+    /// The config file or a layout template couldn't be loaded.
+    Config,
+    /// The upstream transit API couldn't be reached or returned bad data.
+    Upstream,
+    /// The board couldn't be drawn, encoded, or written out.
+    Render,
+}
+
+impl Status {
+    /// The process exit code for this status. These are documented in the
+    /// README for deployment scripts to rely on, so don't renumber them.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Status::Ok => 0,
+            Status::Config => 2,
+            Status::Upstream => 3,
+            Status::Render => 4,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+}
+
+/// Every check a subcommand ran, and the status of the first that failed.
+#[derive(Serialize, Debug)]
+pub struct Report {
+    pub command: &'static str,
+    pub status: Status,
+    pub exit_code: u8,
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    fn new(command: &'static str) -> Self {
+        Self {
+            command,
+            status: Status::Ok,
+            exit_code: Status::Ok.exit_code(),
+            checks: Vec::new(),
+        }
+    }
+
+    /// Record the outcome of one check, returning its value if it passed.
+    fn record<T>(
+        &mut self,
+        name: &'static str,
+        failure: Status,
+        result: eyre::Result<T>,
+        describe: impl FnOnce(&T) -> String,
+    ) -> Option<T> {
+        let (status, detail, value) = match result {
+            Ok(value) => (Status::Ok, describe(&value), Some(value)),
+            Err(e) => (failure, format!("{e:#}"), None),
+        };
+
+        if self.status == Status::Ok && status != Status::Ok {
+            self.status = status;
+            self.exit_code = status.exit_code();
+        }
+
+        self.checks.push(Check {
+            name,
+            status,
+            detail,
+        });
+
+        value
+    }
+
+    fn load_config(&mut self) -> Option<(Config, Layouts)> {
+        self.load_config_from(&Config::path())
+    }
+
+    fn load_config_from(&mut self, path: &Path) -> Option<(Config, Layouts)> {
+        let config = self.record(
+            "config",
+            Status::Config,
+            Config::load_from(path),
+            |config| format!("timezone {}", config.timezone),
+        )?;
+
+        let layouts = self.record(
+            "layouts",
+            Status::Config,
+            Layouts::load(&config),
+            |layouts| {
+                format!(
+                    "landscape {}x{}, portrait {}x{}",
+                    layouts.landscape.width,
+                    layouts.landscape.height,
+                    layouts.portrait.width,
+                    layouts.portrait.height
+                )
+            },
+        )?;
+
+        if config.experimental.layout.is_some() || config.experimental.portrait_layout.is_some() {
+            self.record(
+                "experimental",
+                Status::Config,
+                Layouts::load_experimental(&config),
+                |_| "experimental layouts load".to_owned(),
+            )?;
+        }
+
+        if config.gtfs_routes.is_some() {
+            self.record("gtfs_routes", Status::Config, config.routes(), |routes| {
+                format!(
+                    "{} lines shaded, {} with modes",
+                    routes.shades.len(),
+                    routes.modes.len()
+                )
+            })?;
+        }
+
+        if config.gtfs_shapes.is_some() {
+            self.record("gtfs_shapes", Status::Config, config.shapes(), |shapes| {
+                format!("{} shapes", shapes.0.len())
+            })?;
+        }
+
+        if config.gtfs_trips.is_some() {
+            self.record("gtfs_trips", Status::Config, config.trips(), |trips| {
+                format!("{} trips with directions", trips.0.len())
+            })?;
+        }
+
+        if let Some(lobby) = &config.lobby {
+            self.record("lobby", Status::Config, lobby.check(), |_| {
+                format!(
+                    "{} boards in {}x{}",
+                    lobby.boards.len(),
+                    lobby.width,
+                    lobby.height
+                )
+            })?;
+        }
+
+        Some((config, layouts))
+    }
+}
+
+/// Load the config file and layout templates without touching the network.
+pub fn check_config() -> Report {
+    let mut report = Report::new("check-config");
+    report.load_config();
+
+    report
+}
+
+/// Check everything the server needs: config, fonts and rendering, and the
+/// upstream API.
+pub async fn selftest(tape: Option<Tape>) -> Report {
+    let mut report = Report::new("selftest");
+    let Some((config, layouts)) = report.load_config() else {
+        return report;
+    };
+
+    let layout = layouts.get(config.orientation);
+    report.record(
+        "render",
+        Status::Render,
+        render::draw_image(layout, &Board::default(), &Output::from_config(&config)),
+        |png| format!("{} byte empty board", png.len()),
+    );
+
+    report.record(
+        "upstream",
+        Status::Upstream,
+        departures::fetch(
+            &Client::new(),
+            &Upstream::new(&config).with_tape(tape),
+            &config.stops,
+            &config.grouping,
+            // Already checked by `load_config`.
+            &config.trips().unwrap_or_default(),
+        )
+        .await,
+        |directions| format!("{} directions with departures", directions.len()),
+    );
+
+    report
+}
+
+/// Fetch departures and write the board to `path` as a PNG.
+pub async fn render(path: &Path, orientation: Option<Orientation>, tape: Option<Tape>) -> Report {
+    let mut report = Report::new("render");
+    let Some((config, layouts)) = report.load_config() else {
+        return report;
+    };
+
+    let Some(directions) = report.record(
+        "upstream",
+        Status::Upstream,
+        departures::fetch(
+            &Client::new(),
+            &Upstream::new(&config).with_tape(tape),
+            &config.stops,
+            &config.grouping,
+            // Already checked by `load_config`.
+            &config.trips().unwrap_or_default(),
+        )
+        .await,
+        |directions| format!("{} directions with departures", directions.len()),
+    ) else {
+        return report;
+    };
+
+    let layout = layouts.get(orientation.unwrap_or(config.orientation));
+    let mut board = Board {
+        walk_times: config.walk_times(),
+        line_order: config.grouping.line_order.clone(),
+        // Already checked by `load_config`.
+        routes: config.routes().unwrap_or_default(),
+        shapes: Arc::new(config.shapes().unwrap_or_default()),
+        timezone: Some(config.timezone),
+        ..Board::default()
+    };
+    board
+        .routes
+        .fill_modes(departures::agency_modes(&directions, &config.stops));
+    board.departures = directions;
+    let written =
+        render::draw_image(layout, &board, &Output::from_config(&config)).and_then(|png| {
+            std::fs::write(path, png)?;
+            Ok(())
+        });
+    report.record("render", Status::Render, written, |_| {
+        format!("wrote {}", path.display())
+    });
+
+    report
+}
+
+/// Check the config file at `path` without starting the server: that it and
+/// everything it names loads, that the API key works and 511 knows every
+/// configured stop, and what each departures panel would list right now.
+pub async fn validate(path: &Path, tape: Option<Tape>) -> Report {
+    let mut report = Report::new("validate");
+    if !path.is_file() {
+        report.record::<()>(
+            "config",
+            Status::Config,
+            Err(eyre!("{} doesn't exist", path.display())),
+            |_| String::new(),
+        );
+        return report;
+    }
+    let Some((config, layouts)) = report.load_config_from(path) else {
+        return report;
+    };
+
+    if !config.displays.is_empty() {
+        report.record(
+            "displays",
+            Status::Config,
+            check_displays(&config),
+            |count| format!("{count} displays valid"),
+        );
+    }
+
+    let client = Client::new();
+    let upstream = Upstream::new(&config).with_tape(tape);
+    let Some(known) = report.record(
+        "api_key",
+        Status::Upstream,
+        known_stops(&client, &upstream, &config).await,
+        |known| format!("511 lists {} stops", known.len()),
+    ) else {
+        return report;
+    };
+    report.record(
+        "stops",
+        Status::Config,
+        check_stops(&config, &known),
+        |count| format!("all {count} stop codes exist"),
+    );
+
+    let Some(directions) = report.record(
+        "upstream",
+        Status::Upstream,
+        departures::fetch(
+            &client,
+            &upstream,
+            &config.stops,
+            &config.grouping,
+            // Already checked by `load_config`.
+            &config.trips().unwrap_or_default(),
+        )
+        .await,
+        |directions| format!("{} directions with departures", directions.len()),
+    ) else {
+        return report;
+    };
+
+    let layout = layouts.get(config.orientation);
+    let sections = describe_sections(layout, &directions, &config.grouping.line_order);
+    report.record("sections", Status::Config, Ok(sections), Clone::clone);
+
+    report
+}
+
+/// How many displays there are, if they're all valid.
+fn check_displays(config: &Config) -> eyre::Result<usize> {
+    let problems: Vec<_> = config
+        .displays
+        .iter()
+        .flat_map(|(name, display)| {
+            display
+                .validate()
+                .into_iter()
+                .map(move |invalid| match invalid.field {
+                    Some(field) => format!("{name}.{field}: {}", invalid.message),
+                    None => format!("{name}: {}", invalid.message),
+                })
+        })
+        .collect();
+    ensure!(problems.is_empty(), "{}", problems.join("; "));
+
+    Ok(config.displays.len())
+}
+
+/// Every stop configured anywhere: on the main board, displays, and lobby.
+fn all_stops(config: &Config) -> impl Iterator<Item = &Stop> {
+    config
+        .stops
+        .iter()
+        .chain(config.displays.values().flat_map(|display| &display.stops))
+        .chain(
+            config
+                .lobby
+                .iter()
+                .flat_map(|lobby| &lobby.boards)
+                .flat_map(|board| &board.stops),
+        )
+}
+
+/// The stop codes 511 lists for every agency the config uses.
+async fn known_stops(
+    client: &Client,
+    upstream: &Upstream,
+    config: &Config,
+) -> eyre::Result<Vec<String>> {
+    let mut agencies: Vec<&str> = all_stops(config).map(Stop::agency).collect();
+    agencies.push(DEFAULT_AGENCY);
+    agencies.sort_unstable();
+    agencies.dedup();
+
+    let mut known = Vec::new();
+    for agency in agencies {
+        known.extend(siri::fetch_stop_codes(client, upstream, agency).await?);
+    }
+
+    Ok(known)
+}
+
+/// How many stops are configured, if 511 knows them all. Stops of the
+/// displays and the lobby count too.
+fn check_stops(config: &Config, known: &[String]) -> eyre::Result<usize> {
+    let known: HashSet<&str> = known.iter().map(String::as_str).collect();
+    let mut codes: Vec<&str> = all_stops(config).map(|stop| stop.code.as_str()).collect();
+    codes.sort_unstable();
+    codes.dedup();
+
+    let unknown: Vec<_> = codes.iter().filter(|code| !known.contains(*code)).collect();
+    ensure!(
+        unknown.is_empty(),
+        "511 doesn't know stops {}",
+        unknown
+            .iter()
+            .map(|code| code.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    Ok(codes.len())
+}
+
+/// Each panel of `layout` in drawing order, with the rows departures panels
+/// would show from `directions`.
+fn describe_sections(
+    layout: &Layout,
+    directions: &HashMap<String, LinesDestinationsToJourneys>,
+    line_order: &[String],
+) -> String {
+    layout
+        .panels
+        .iter()
+        .map(|panel| match panel {
+            Panel::Departures { direction, .. } => {
+                let mut rows: Vec<_> = directions
+                    .get(direction)
+                    .map(|rows| rows.keys().collect())
+                    .unwrap_or_default();
+                rows.sort_by_key(|row| departures::row_key(row, line_order));
+                let rows: Vec<_> = rows
+                    .iter()
+                    .map(|(line, destination)| format!("{line} {destination}"))
+                    .collect();
+
+                match rows.is_empty() {
+                    true => format!("departures {direction} (nothing now)"),
+                    false => format!("departures {direction} ({})", rows.join(", ")),
+                }
+            }
+            panel => panel.kind().to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
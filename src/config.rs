@@ -0,0 +1,409 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use eyre::eyre;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use toml_edit::DocumentMut;
+
+use crate::{
+    alerts::AlertsConfig,
+    bikes::BikesConfig,
+    departures::{self, Grouping, Stop, TooSoon, WalkTimes},
+    display::Display,
+    elevators::ElevatorsConfig,
+    geocode::GeocodingConfig,
+    layout::{Emphasis, Orientation, Title},
+    lobby::LobbyConfig,
+    render::{frame::PngOptions, Theme, Tone},
+    routes::Routes,
+    shapes::Shapes,
+    siri::SiriFormat,
+    store::DatabaseConfig,
+    time,
+    trips::Trips,
+    vehicles::VehiclesConfig,
+    weather::WeatherConfig,
+    webhook::WebhookConfig,
+};
+
+/// Where the config file is read from when `TRANSIT_KINDLE_CONFIG` is unset.
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// 511.org API keys, used in rotation. Keys that get rate limited or
+    /// rejected are rested while the others carry on.
+    pub api_keys: Vec<String>,
+
+    /// Bearer token required by the annotations and admin APIs, which are
+    /// disabled when this is unset.
+    pub api_token: Option<String>,
+
+    /// Called when the board keeps failing to fetch or draw.
+    pub failure_webhook: Option<WebhookConfig>,
+
+    /// Shown on every view during maintenance.
+    pub maintenance_message: String,
+
+    /// Stops whose departures are shown, each with an optional walking time.
+    pub stops: Vec<Stop>,
+
+    /// How destinations are tidied up before departures are grouped into rows.
+    pub grouping: Grouping,
+
+    /// Outbound requests allowed per hour across every feature. Defaults to
+    /// 511's limit of 60 per key.
+    pub requests_per_hour: Option<u32>,
+
+    /// Whether to ask the API for SIRI as JSON or XML.
+    pub siri_format: SiriFormat,
+
+    /// Fetch each agency's whole StopMonitoring feed in one request rather
+    /// than one request per stop. Fewer requests for boards with many stops,
+    /// at the cost of far larger responses. Unset, agencies with more than
+    /// one stop on a board get the whole feed.
+    pub whole_agency_feed: Option<bool>,
+
+    /// How long to wait for 511 and the other feeds before giving up on a
+    /// request.
+    pub upstream_timeout_secs: u64,
+
+    /// How long a response from 511 is reused before the same request is
+    /// made again, so frequent polling doesn't spend the hourly budget.
+    pub upstream_cache_secs: u64,
+
+    /// How long any request to the server may take before it's answered
+    /// with an error, so a device isn't left waiting on a hung fetch.
+    pub request_timeout_secs: u64,
+
+    /// Layout template (TOML or JSON) describing the board. The built-in
+    /// two-column layout is used when this is unset.
+    pub layout: Option<PathBuf>,
+
+    /// Layout template used for portrait boards. The built-in stacked layout
+    /// is used when this is unset.
+    pub portrait_layout: Option<PathBuf>,
+
+    /// Layouts under trial, served at `/views/default/canary.png` so they can
+    /// be previewed before replacing `layout` and `portrait_layout`.
+    pub experimental: ExperimentalLayouts,
+
+    /// Fetch 511 service alerts for the board's lines and stops, shown in the
+    /// banner when there are no annotations. Disabled when this is unset.
+    pub alerts: Option<AlertsConfig>,
+
+    /// BART stations whose elevator outages the `elevators` panel lists.
+    pub elevators: Option<ElevatorsConfig>,
+
+    /// Where the `weather` panel's forecast is for.
+    pub weather: Option<WeatherConfig>,
+
+    /// Track vehicle positions, to show how many stops away each one is.
+    pub vehicles: Option<VehiclesConfig>,
+
+    /// Bike-share stations whose availability the `bikes` panel shows.
+    pub bikes: Option<BikesConfig>,
+
+    /// Several stop groups drawn into one frame at `/lobby.png`, which is
+    /// disabled when this is unset.
+    pub lobby: Option<LobbyConfig>,
+
+    /// Further boards by name, each served at `/displays/<name>.png` with
+    /// its own stops and layout.
+    pub displays: BTreeMap<String, Display>,
+
+    /// Where displays added through the API and the history of fetched
+    /// predictions are kept. Without it, the API edits `displays` in this file
+    /// and no history is kept.
+    pub database: Option<DatabaseConfig>,
+
+    /// Which service turns addresses into coordinates, and how politely.
+    pub geocoding: GeocodingConfig,
+
+    /// Text for the layout's named `text` panels, by name. The built-in
+    /// layouts name their direction headers `IB` and `OB`.
+    pub titles: HashMap<String, Title>,
+
+    /// A GTFS `routes.txt` whose route colors shade the line bubbles, so
+    /// lines stay distinct in grayscale, and whose route types pick each
+    /// line's mode icon.
+    pub gtfs_routes: Option<PathBuf>,
+
+    /// A GTFS `shapes.txt` whose route paths `map` panels draw.
+    pub gtfs_shapes: Option<PathBuf>,
+
+    /// A GTFS `trips.txt` whose `direction_id`s place journeys that the
+    /// realtime feed gives no direction.
+    pub gtfs_trips: Option<PathBuf>,
+
+    /// Where the server can be reached from a phone, e.g.
+    /// "http://kindle-board.local:3001/", for `qr_code` panels.
+    pub public_url: Option<String>,
+
+    /// Orientation of the board when a request doesn't ask for one.
+    pub orientation: Orientation,
+
+    /// IANA timezone that wall-clock settings are interpreted in.
+    pub timezone: Tz,
+
+    /// Local time at which one service day ends and the next begins. Owl
+    /// trips between midnight and this time belong to the previous day.
+    pub service_day_start: NaiveTime,
+
+    /// Local hours during which nothing is fetched and the board shows only
+    /// the time, e.g. overnight. Disabled when this is unset.
+    pub quiet_hours: Option<QuietHours>,
+
+    /// Overrides the default font of every layout.
+    pub font: FontOverride,
+
+    /// How many upcoming times to list for each line, overriding the
+    /// layouts' `upcoming_times`.
+    pub upcoming_times: Option<usize>,
+
+    /// Departures sooner than this many minutes are too soon to catch,
+    /// overriding the layouts' `min_minutes`.
+    pub min_minutes: Option<i64>,
+
+    /// Whether too-soon departures are dropped or dimmed, overriding the
+    /// layouts' `too_soon`.
+    pub too_soon: Option<TooSoon>,
+
+    /// Whether to show minutes until arrival alongside minutes until you
+    /// need to leave, overriding the layouts' `show_arrival_time`.
+    pub show_arrival_time: Option<bool>,
+
+    /// Departures you need to leave for within this many minutes stand out,
+    /// overriding the layouts' `imminent_minutes`.
+    pub imminent_minutes: Option<i64>,
+
+    /// How imminent departures stand out, overriding the layouts'
+    /// `imminent_style`.
+    pub imminent_style: Option<Emphasis>,
+
+    /// Whether late departures show how far behind the timetable they are,
+    /// overriding the layouts' `show_delay`.
+    pub show_delay: Option<bool>,
+
+    /// Whether departures show how crowded the vehicle is, overriding the
+    /// layouts' `show_occupancy`.
+    pub show_occupancy: Option<bool>,
+
+    /// Whether rows draw how far their next vehicle is from the stop,
+    /// overriding the layouts' `show_progress`.
+    pub show_progress: Option<bool>,
+
+    /// Gamma and contrast applied to every gray before drawing, to tune the
+    /// board for e-ink panels.
+    pub tone: Tone,
+
+    /// `"light"` (black on white) or `"dark"` (white on black). Requests can
+    /// pick one with `?theme=`.
+    pub theme: Theme,
+
+    /// How PNGs are compressed and packed, to shrink frames for devices on
+    /// weak Wi-Fi.
+    pub png: PngOptions,
+
+    /// How many frames may be drawn at once. Further requests wait their
+    /// turn, so a burst can't exhaust memory on a small board computer.
+    pub max_concurrent_renders: usize,
+
+    /// SHA-256 of the config file, or `None` when running on defaults.
+    #[serde(skip)]
+    pub hash: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExperimentalLayouts {
+    pub layout: Option<PathBuf>,
+    pub portrait_layout: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QuietHours {
+    /// Local time quiet hours begin, e.g. "23:00:00".
+    pub start: NaiveTime,
+    /// Local time normal operation resumes. Before `start` means the next
+    /// morning.
+    pub end: NaiveTime,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct FontOverride {
+    pub family: Option<String>,
+    pub size: Option<f32>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            api_keys: Vec::new(),
+            api_token: None,
+            failure_webhook: None,
+            maintenance_message: "Display under maintenance".to_owned(),
+            stops: departures::STOPS
+                .iter()
+                .map(|code| Stop {
+                    code: (*code).to_owned(),
+                    walk_minutes: 0,
+                    agency: None,
+                })
+                .collect(),
+            grouping: Grouping::default(),
+            requests_per_hour: None,
+            siri_format: SiriFormat::default(),
+            whole_agency_feed: None,
+            upstream_timeout_secs: 10,
+            upstream_cache_secs: 30,
+            request_timeout_secs: 30,
+            layout: None,
+            portrait_layout: None,
+            experimental: ExperimentalLayouts::default(),
+            alerts: None,
+            elevators: None,
+            weather: None,
+            vehicles: None,
+            bikes: None,
+            lobby: None,
+            displays: BTreeMap::new(),
+            database: None,
+            geocoding: GeocodingConfig::default(),
+            titles: HashMap::new(),
+            gtfs_routes: None,
+            gtfs_shapes: None,
+            gtfs_trips: None,
+            public_url: None,
+            orientation: Orientation::Landscape,
+            timezone: chrono_tz::America::Los_Angeles,
+            service_day_start: NaiveTime::from_hms_opt(4, 0, 0).unwrap(),
+            quiet_hours: None,
+            font: FontOverride::default(),
+            upcoming_times: None,
+            min_minutes: None,
+            too_soon: None,
+            show_arrival_time: None,
+            imminent_minutes: None,
+            imminent_style: None,
+            show_delay: None,
+            show_occupancy: None,
+            show_progress: None,
+            tone: Tone::default(),
+            theme: Theme::default(),
+            png: PngOptions::default(),
+            max_concurrent_renders: 2,
+            hash: None,
+        }
+    }
+}
+
+impl Config {
+    /// The service day that `instant` belongs to.
+    pub fn service_day(&self, instant: DateTime<Utc>) -> NaiveDate {
+        time::service_day(instant, self.timezone, self.service_day_start)
+    }
+
+    /// Whether `instant` falls in the configured quiet hours.
+    pub fn is_quiet(&self, instant: DateTime<Utc>) -> bool {
+        self.quiet_hours.as_ref().is_some_and(|quiet| {
+            let local = instant.with_timezone(&self.timezone).time();
+            time::within(local, quiet.start, quiet.end)
+        })
+    }
+
+    /// The routes in `gtfs_routes`, or none if it's unset.
+    pub fn routes(&self) -> eyre::Result<Routes> {
+        match &self.gtfs_routes {
+            Some(path) => Routes::load(path),
+            None => Ok(Routes::default()),
+        }
+    }
+
+    /// The shapes in `gtfs_shapes`, or none if it's unset.
+    pub fn shapes(&self) -> eyre::Result<Shapes> {
+        match &self.gtfs_shapes {
+            Some(path) => Shapes::load(path),
+            None => Ok(Shapes::default()),
+        }
+    }
+
+    /// The trips in `gtfs_trips`, or none if it's unset.
+    pub fn trips(&self) -> eyre::Result<Trips> {
+        match &self.gtfs_trips {
+            Some(path) => Trips::load(path),
+            None => Ok(Trips::default()),
+        }
+    }
+
+    /// The codes of the configured stops.
+    pub fn stop_codes(&self) -> Vec<&str> {
+        self.stops.iter().map(|stop| stop.code.as_str()).collect()
+    }
+
+    pub fn walk_times(&self) -> WalkTimes {
+        WalkTimes::new(&self.stops)
+    }
+
+    /// Where the config file is read from.
+    pub fn path() -> PathBuf {
+        std::env::var_os("TRANSIT_KINDLE_CONFIG")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH))
+    }
+
+    /// Change the config file at `path` with `edit`, leaving the rest of it,
+    /// comments included, as it was. Nothing is written if the result
+    /// wouldn't load.
+    pub fn edit(
+        path: &Path,
+        edit: impl FnOnce(&mut DocumentMut) -> eyre::Result<()>,
+    ) -> eyre::Result<()> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut document: DocumentMut = contents
+            .parse()
+            .map_err(|e| eyre!("couldn't parse {}: {e}", path.display()))?;
+
+        edit(&mut document)?;
+
+        let contents = document.to_string();
+        toml::from_str::<Self>(&contents)
+            .map_err(|e| eyre!("the edited config wouldn't load: {e}"))?;
+        std::fs::write(path, contents)?;
+
+        Ok(())
+    }
+
+    /// Load the config file, falling back to defaults if it doesn't exist.
+    pub fn load() -> eyre::Result<Self> {
+        Self::load_from(&Self::path())
+    }
+
+    /// Load the config file at `path`, falling back to defaults if it doesn't
+    /// exist.
+    pub fn load_from(path: &Path) -> eyre::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                let mut config: Self = toml::from_str(&contents)?;
+                config.hash = Some(format!("{:x}", Sha256::digest(contents.as_bytes())));
+
+                Ok(config)
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,1600 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::Mutex,
+};
+
+use chrono::{DateTime, Utc};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    routes::Mode,
+    siri::{
+        self, MonitoredVehicleJourney, ServiceDelivery, StopMonitoringDelivery,
+        StopMonitoringResponse,
+    },
+    time,
+    trips::Trips,
+    upstream::{Transport, Upstream, DEFAULT_AGENCY},
+};
+
+/// The stop codes whose departures are shown on the board when none are
+/// configured.
+pub const STOPS: [&str; 4] = ["15419", "16996", "15692", "15696"];
+
+/// How many 511 requests one refresh has in flight at once, so a board with
+/// many agencies stays quick without bursting through the rate limit.
+pub const MAX_CONCURRENT_FETCHES: usize = 4;
+
+/// A stop whose departures are shown on the board.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Stop {
+    pub code: String,
+    /// Minutes it takes to walk to the stop. Arrivals there are shown as
+    /// minutes until you need to leave.
+    #[serde(default)]
+    pub walk_minutes: i64,
+    /// The 511 operator serving the stop, e.g. "CT" for Caltrain or "SB" for
+    /// SF Bay Ferry. Muni ("SF") if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agency: Option<String>,
+}
+
+impl Stop {
+    pub fn agency(&self) -> &str {
+        self.agency.as_deref().unwrap_or(DEFAULT_AGENCY)
+    }
+}
+
+/// Walking time to each stop, by stop code.
+#[derive(Debug, Clone, Default)]
+pub struct WalkTimes(HashMap<String, i64>);
+
+impl WalkTimes {
+    pub fn new(stops: &[Stop]) -> Self {
+        Self(
+            stops
+                .iter()
+                .map(|stop| (stop.code.clone(), stop.walk_minutes))
+                .collect(),
+        )
+    }
+
+    /// Minutes to walk to `stop`, or 0 if it isn't known.
+    pub fn get(&self, stop: &str) -> i64 {
+        self.0.get(stop).copied().unwrap_or(0)
+    }
+}
+
+/// How raw journeys are tidied up before they're grouped into rows.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Grouping {
+    /// Short names for destinations, e.g. "FERRY PLAZA VIA MARKET ST" to
+    /// "Ferry Plaza", matched exactly. Destinations that end up with the same
+    /// name share a row.
+    pub destination_aliases: HashMap<String, String>,
+    /// Regex replacements applied in order after the aliases, for the
+    /// formatting an agency is inconsistent about.
+    pub rewrites: Vec<Rewrite>,
+    /// Title-case destinations written in all capitals, e.g. "CASTRO" to
+    /// "Castro".
+    pub title_case: bool,
+    /// Only show these lines, if any are given.
+    pub lines: Vec<String>,
+    /// Never show these lines.
+    pub exclude_lines: Vec<String>,
+    /// Never show journeys to these destinations, e.g. short turns that end
+    /// before your stop. Matched against the name after the aliases and
+    /// rewrites.
+    pub exclude_destinations: Vec<String>,
+    /// Lines listed first, in this order. The rest follow by line number.
+    pub line_order: Vec<String>,
+    /// Whether each row is a line and destination, or a whole line with its
+    /// destinations merged.
+    pub rows: Rows,
+}
+
+/// What each row of a departures panel stands for.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Rows {
+    /// One row per line and destination.
+    #[default]
+    LineDestination,
+    /// One row per line, its times mixing every destination.
+    Line,
+}
+
+/// Which part of a journey a [`Rewrite`] applies to.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    #[default]
+    Destination,
+    Line,
+}
+
+/// Replace every match of `pattern` in a journey's destination or line.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Rewrite {
+    #[serde(default)]
+    pub field: Field,
+    #[serde(
+        deserialize_with = "deserialize_regex",
+        serialize_with = "serialize_regex"
+    )]
+    pub pattern: Regex,
+    /// The replacement, which may refer to groups as `$1` or `${name}`.
+    /// Matches are removed when this is unset.
+    #[serde(default)]
+    pub replace: String,
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+fn serialize_regex<S: Serializer>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(regex.as_str())
+}
+
+impl Grouping {
+    /// Rewrite `journey`'s destination and line to the names they're shown
+    /// under.
+    fn normalize(&self, journey: &mut MonitoredVehicleJourney) {
+        let destination = &mut journey.monitored_call.destination_display;
+        if let Some(alias) = destination
+            .as_deref()
+            .and_then(|name| self.destination_aliases.get(name))
+        {
+            *destination = Some(alias.clone());
+        }
+
+        for rewrite in &self.rewrites {
+            let field = match rewrite.field {
+                Field::Destination => &mut journey.monitored_call.destination_display,
+                Field::Line => &mut journey.line_ref,
+            };
+            if let Some(value) = field {
+                let rewritten = rewrite
+                    .pattern
+                    .replace_all(value, rewrite.replace.as_str())
+                    .trim()
+                    .to_owned();
+                *value = rewritten;
+            }
+        }
+
+        if self.title_case {
+            if let Some(destination) = &mut journey.monitored_call.destination_display {
+                if !destination.chars().any(char::is_lowercase) {
+                    *destination = title_case(destination);
+                }
+            }
+        }
+    }
+
+    /// Whether `journey` belongs on the board, judged by its normalized line
+    /// and destination.
+    fn keeps(&self, journey: &MonitoredVehicleJourney) -> bool {
+        let line_kept = journey.line_ref.as_ref().is_none_or(|line| {
+            (self.lines.is_empty() || self.lines.contains(line))
+                && !self.exclude_lines.contains(line)
+        });
+        let destination_kept = journey
+            .monitored_call
+            .destination_display
+            .as_ref()
+            .is_none_or(|destination| !self.exclude_destinations.contains(destination));
+
+        line_kept && destination_kept
+    }
+}
+
+/// `text` with the first letter of each word capitalized and the rest
+/// lowercase.
+fn title_case(text: &str) -> String {
+    text.split(' ')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first
+                    .to_uppercase()
+                    .chain(chars.flat_map(char::to_lowercase))
+                    .collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A piece of a line name for natural sorting, so "5" comes before "14".
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NaturalChunk<'a> {
+    Number(u64),
+    Text(&'a str),
+}
+
+fn natural_chunks(text: &str) -> Vec<NaturalChunk<'_>> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while let Some(first) = rest.chars().next() {
+        let is_digit = first.is_ascii_digit();
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != is_digit)
+            .unwrap_or(rest.len());
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(match chunk.parse() {
+            Ok(number) if is_digit => NaturalChunk::Number(number),
+            _ => NaturalChunk::Text(chunk),
+        });
+        rest = tail;
+    }
+
+    chunks
+}
+
+/// Sort key putting rows in board order: lines in `line_order` first, in that
+/// order, then the rest by line number, then by destination. Rows keep their
+/// places from one refresh to the next, which matters on e-ink.
+pub fn row_key<'a>(
+    (line, destination): &'a (String, String),
+    line_order: &[String],
+) -> (usize, Vec<NaturalChunk<'a>>, &'a str) {
+    let priority = line_order
+        .iter()
+        .position(|ordered| ordered == line)
+        .unwrap_or(line_order.len());
+
+    (priority, natural_chunks(line), destination.as_str())
+}
+
+/// Journeys keyed by (line, destination).
+pub type LinesDestinationsToJourneys = HashMap<(String, String), Vec<MonitoredVehicleJourney>>;
+
+/// Pick out the journeys calling at `stops`, normalize them per `grouping`,
+/// and group them by direction, then by (line, destination), each group
+/// sorted soonest first. Journeys without a direction get one from
+/// [`infer_directions`], using the GTFS directions in `trips`; journeys
+/// still missing any of those fields can't be placed on the board and are
+/// dropped.
+pub fn group(
+    response: StopMonitoringResponse,
+    stops: &[&str],
+    grouping: &Grouping,
+    trips: &Trips,
+) -> HashMap<String, LinesDestinationsToJourneys> {
+    let mut journeys_i_care_about = Vec::new();
+
+    for stop_visit in response
+        .service_delivery
+        .stop_monitoring_delivery
+        .monitored_stop_visit
+    {
+        let stop = &stop_visit
+            .monitored_vehicle_journey
+            .monitored_call
+            .stop_point_ref;
+        if stops.contains(&stop.as_ref()) {
+            let mut journey = stop_visit.monitored_vehicle_journey;
+            grouping.normalize(&mut journey);
+            if grouping.keeps(&journey) {
+                let recorded_at = stop_visit
+                    .recorded_at_time
+                    .as_deref()
+                    .and_then(time::parse_instant);
+                journeys_i_care_about.push((recorded_at, journey));
+            }
+        }
+    }
+
+    let mut journeys_i_care_about = dedupe(journeys_i_care_about);
+    infer_directions(&mut journeys_i_care_about, trips);
+
+    let mut directions_to_lines_destinations_to_journeys = HashMap::new();
+    for journey in journeys_i_care_about {
+        let Some(line) = journey.line_ref.clone() else {
+            continue;
+        };
+        let Some(direction) = journey.direction_ref.clone() else {
+            continue;
+        };
+        let Some(destination) = journey.monitored_call.destination_display.clone() else {
+            continue;
+        };
+
+        directions_to_lines_destinations_to_journeys
+            .entry(direction)
+            .or_insert(HashMap::new())
+            .entry((line, destination))
+            .or_insert(Vec::new())
+            .push(journey);
+    }
+
+    for lines_destinations_to_journeys in directions_to_lines_destinations_to_journeys.values_mut()
+    {
+        if grouping.rows == Rows::Line {
+            *lines_destinations_to_journeys =
+                merge_destinations(std::mem::take(lines_destinations_to_journeys));
+        }
+        for journeys in lines_destinations_to_journeys.values_mut() {
+            journeys.sort_by_key(|j| j.arrival_time().and_then(time::parse_instant));
+        }
+    }
+
+    directions_to_lines_destinations_to_journeys
+}
+
+/// Drop repeats of the same trip, which turn up when overlapping stop codes
+/// are configured, keeping the most recently recorded prediction of each.
+/// Journeys that can't be identified are all kept.
+fn dedupe(
+    journeys: Vec<(Option<DateTime<Utc>>, MonitoredVehicleJourney)>,
+) -> Vec<MonitoredVehicleJourney> {
+    let mut kept: Vec<(Option<DateTime<Utc>>, MonitoredVehicleJourney)> = Vec::new();
+    let mut seen = HashMap::new();
+
+    for (recorded_at, journey) in journeys {
+        let key = journey.trip().map(|trip| {
+            (
+                journey.line_ref.clone(),
+                journey.monitored_call.destination_display.clone(),
+                trip,
+            )
+        });
+
+        let existing = key.and_then(|key| match seen.entry(key) {
+            Entry::Occupied(entry) => Some(*entry.get()),
+            Entry::Vacant(entry) => {
+                entry.insert(kept.len());
+                None
+            }
+        });
+
+        match existing {
+            Some(index) if recorded_at > kept[index].0 => kept[index] = (recorded_at, journey),
+            Some(_) => {}
+            None => kept.push((recorded_at, journey)),
+        }
+    }
+
+    kept.into_iter().map(|(_, journey)| journey).collect()
+}
+
+/// One row per line, keyed by its destinations joined in alphabetical order,
+/// e.g. "Castro / Embarcadero".
+fn merge_destinations(rows: LinesDestinationsToJourneys) -> LinesDestinationsToJourneys {
+    let mut by_line: HashMap<String, Vec<MonitoredVehicleJourney>> = HashMap::new();
+    for ((line, _), journeys) in rows {
+        by_line.entry(line).or_default().extend(journeys);
+    }
+
+    by_line
+        .into_iter()
+        .map(|(line, journeys)| {
+            let mut destinations: Vec<&str> = journeys
+                .iter()
+                .filter_map(|j| j.monitored_call.destination_display.as_deref())
+                .collect();
+            destinations.sort_unstable();
+            destinations.dedup();
+            let destination = destinations.join(" / ");
+
+            ((line, destination), journeys)
+        })
+        .collect()
+}
+
+/// The direction counted most often, ties going to the last by name.
+fn commonest<'a>(counts: &HashMap<&'a str, usize>) -> Option<&'a str> {
+    counts
+        .iter()
+        .max_by_key(|(direction, count)| (**count, **direction))
+        .map(|(direction, _)| *direction)
+}
+
+/// The GTFS `direction_id` of `journey`'s trip, if `trips` lists it.
+fn gtfs_direction<'a>(trips: &'a Trips, journey: &MonitoredVehicleJourney) -> Option<&'a str> {
+    let framed = journey.framed_vehicle_journey_ref.as_ref()?;
+    trips.direction(framed.dated_vehicle_journey_ref.as_deref()?)
+}
+
+/// Fill in a missing `DirectionRef` from journeys that do have one. A trip
+/// whose GTFS direction is known from `trips` takes the `DirectionRef` of
+/// journeys in the same GTFS direction: on the same line if there are any,
+/// otherwise on any line, since an agency numbers its directions the same way
+/// throughout. Failing that, the guesses are in turn: the same line to the
+/// same destination, the same line at the same stop, then any line at the
+/// same stop (a stop pole generally serves a single direction). Journeys that
+/// match none of these are left alone.
+fn infer_directions(journeys: &mut [MonitoredVehicleJourney], trips: &Trips) {
+    let mut by_gtfs_line = HashMap::new();
+    let mut by_gtfs: HashMap<&str, HashMap<&str, usize>> = HashMap::new();
+    let mut by_destination = HashMap::new();
+    let mut by_line_stop = HashMap::new();
+    let mut by_stop: HashMap<&str, HashMap<&str, usize>> = HashMap::new();
+
+    for journey in journeys.iter() {
+        let Some(direction) = journey.direction_ref.as_deref() else {
+            continue;
+        };
+        let stop = journey.monitored_call.stop_point_ref.as_str();
+
+        if let Some(gtfs) = gtfs_direction(trips, journey) {
+            if let Some(line) = journey.line_ref.as_deref() {
+                by_gtfs_line.insert((line, gtfs), direction);
+            }
+            *by_gtfs
+                .entry(gtfs)
+                .or_default()
+                .entry(direction)
+                .or_default() += 1;
+        }
+
+        if let Some(line) = journey.line_ref.as_deref() {
+            if let Some(destination) = journey.monitored_call.destination_display.as_deref() {
+                by_destination.insert((line, destination), direction);
+            }
+            by_line_stop.insert((line, stop), direction);
+        }
+        *by_stop
+            .entry(stop)
+            .or_default()
+            .entry(direction)
+            .or_default() += 1;
+    }
+
+    let inferred: Vec<Option<String>> = journeys
+        .iter()
+        .map(|journey| {
+            if journey.direction_ref.is_some() {
+                return None;
+            }
+            let line = journey.line_ref.as_deref()?;
+            let stop = journey.monitored_call.stop_point_ref.as_str();
+
+            gtfs_direction(trips, journey)
+                .and_then(|gtfs| {
+                    by_gtfs_line
+                        .get(&(line, gtfs))
+                        .copied()
+                        .or_else(|| commonest(by_gtfs.get(gtfs)?))
+                })
+                .or_else(|| {
+                    journey
+                        .monitored_call
+                        .destination_display
+                        .as_deref()
+                        .and_then(|destination| by_destination.get(&(line, destination)))
+                        .copied()
+                })
+                .or_else(|| by_line_stop.get(&(line, stop)).copied())
+                .or_else(|| commonest(by_stop.get(stop)?))
+                .map(str::to_owned)
+        })
+        .collect();
+
+    for (journey, direction) in journeys.iter_mut().zip(inferred) {
+        if direction.is_some() {
+            journey.direction_ref = direction;
+        }
+    }
+}
+
+/// Every visit to `stops`, in one response. Each stop is asked for on its
+/// own, or with a whole agency feed each agency once; see
+/// [`Upstream::whole_agency_feed`]. The requests run concurrently, at most
+/// [`MAX_CONCURRENT_FETCHES`] at a time.
+pub async fn fetch_visits(
+    client: &dyn Transport,
+    upstream: &Upstream,
+    stops: &[Stop],
+) -> eyre::Result<StopMonitoringResponse> {
+    let mut per_stop: Vec<(&str, &str)> = stops
+        .iter()
+        .map(|stop| (stop.agency(), stop.code.as_str()))
+        .collect();
+    per_stop.sort_unstable();
+    per_stop.dedup();
+
+    // Owned, so the futures below don't borrow from this frame in a way that
+    // keeps handlers awaiting them from being `Send`.
+    let mut requests: Vec<(String, Option<String>)> = per_stop
+        .iter()
+        .map(|&(agency, code)| {
+            let wanted = per_stop
+                .iter()
+                .filter(|(other, _)| *other == agency)
+                .count();
+            let code = Some(code).filter(|_| !upstream.whole_agency_feed(wanted));
+            (agency.to_owned(), code.map(str::to_owned))
+        })
+        .collect();
+    requests.dedup();
+
+    let responses: Vec<StopMonitoringResponse> = stream::iter(requests)
+        .map(|(agency, code)| async move {
+            siri::fetch_stop_monitoring(client, upstream, &agency, code.as_deref()).await
+        })
+        .buffered(MAX_CONCURRENT_FETCHES)
+        .try_collect()
+        .await?;
+    let visits = responses
+        .into_iter()
+        .flat_map(|response| {
+            response
+                .service_delivery
+                .stop_monitoring_delivery
+                .monitored_stop_visit
+        })
+        .collect();
+
+    Ok(StopMonitoringResponse {
+        service_delivery: ServiceDelivery {
+            stop_monitoring_delivery: StopMonitoringDelivery {
+                monitored_stop_visit: visits,
+            },
+        },
+    })
+}
+
+/// Fetch the current predictions at `stops` and group them for the board.
+pub async fn fetch(
+    client: &dyn Transport,
+    upstream: &Upstream,
+    stops: &[Stop],
+    grouping: &Grouping,
+    trips: &Trips,
+) -> eyre::Result<HashMap<String, LinesDestinationsToJourneys>> {
+    let response = fetch_visits(client, upstream, stops).await?;
+    let codes: Vec<&str> = stops.iter().map(|stop| stop.code.as_str()).collect();
+
+    Ok(group(response, &codes, grouping, trips))
+}
+
+/// The mode of each line calling at one of `stops` whose agency runs a single
+/// mode, e.g. ferry for SF Bay Ferry.
+pub fn agency_modes(
+    directions: &HashMap<String, LinesDestinationsToJourneys>,
+    stops: &[Stop],
+) -> Vec<(String, Mode)> {
+    directions
+        .values()
+        .flat_map(|lines_destinations_to_journeys| lines_destinations_to_journeys.values())
+        .flatten()
+        .filter_map(|journey| {
+            let stop = stops
+                .iter()
+                .find(|stop| stop.code == journey.monitored_call.stop_point_ref)?;
+            let mode = Mode::for_agency(stop.agency())?;
+            Some((journey.line_ref.clone()?, mode))
+        })
+        .collect()
+}
+
+/// The platform of the soonest of `journeys` that has one.
+pub fn platform(journeys: &[MonitoredVehicleJourney]) -> Option<&str> {
+    journeys
+        .iter()
+        .filter(|journey| !journey.is_cancelled())
+        .find_map(|journey| journey.monitored_call.arrival_platform_name.as_deref())
+        .filter(|platform| !platform.is_empty())
+}
+
+/// How far an arrival prediction can be trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Uncertainty {
+    /// A live prediction, shown as is.
+    Exact,
+    /// A timetable estimate with no live vehicle behind it, shown as "~12".
+    Approximate,
+    /// A prediction the producer has flagged as inaccurate, or that has
+    /// wandered between refreshes, shown as a range of this many minutes
+    /// either side, e.g. "10–14".
+    Spread(i64),
+}
+
+impl Uncertainty {
+    /// The uncertainty of `journey` arriving `minutes` from now.
+    pub fn of(journey: &MonitoredVehicleJourney, minutes: i64) -> Self {
+        let wandered = journey.prediction_spread.unwrap_or(0);
+        if journey.prediction_inaccurate == Some(true) {
+            // Predictions drift more the further out they are.
+            Uncertainty::Spread((minutes * 15 / 100).max(2).max(wandered))
+        } else if wandered > 0 {
+            Uncertainty::Spread(wandered)
+        } else if journey.monitored == Some(false) {
+            Uncertainty::Approximate
+        } else {
+            Uncertainty::Exact
+        }
+    }
+
+    /// `minutes`, written with this much uncertainty.
+    pub fn label(self, minutes: i64) -> String {
+        match self {
+            Uncertainty::Exact => minutes.to_string(),
+            Uncertainty::Approximate => format!("~{minutes}"),
+            Uncertainty::Spread(spread) => {
+                format!("{}–{}", (minutes - spread).max(0), minutes + spread)
+            }
+        }
+    }
+}
+
+/// How long a trip's predictions are remembered, and so how far back their
+/// spread is measured.
+const PREDICTION_WINDOW_SECS: i64 = 10 * 60;
+
+/// Predictions sampled for a trip less often than this count once, so a
+/// burst of renders from the same fetch doesn't look like a steady trip.
+const PREDICTION_SAMPLE_SECS: i64 = 30;
+
+/// Fewest samples a trip needs before its spread is judged.
+const MIN_PREDICTION_SAMPLES: usize = 3;
+
+/// A standard deviation in predicted arrival below this many seconds is
+/// ordinary jitter and left unmarked.
+const STEADY_PREDICTION_SECS: f64 = 90.0;
+
+/// When each of a trip's predictions was seen, and the arrival it predicted.
+type Samples = Vec<(DateTime<Utc>, DateTime<Utc>)>;
+
+/// Each trip's recent arrival predictions at each stop, kept across refreshes
+/// to tell trips whose predictions jump around from ones that hold steady.
+#[derive(Debug, Default)]
+pub struct Predictions(Mutex<HashMap<(String, String), Samples>>);
+
+impl Predictions {
+    /// Note the predictions in `departures` made at `now`, and mark each
+    /// journey whose recent predictions have spread out with how many
+    /// minutes either side of the latest it could fall.
+    pub fn track(
+        &self,
+        departures: &mut HashMap<String, LinesDestinationsToJourneys>,
+        now: DateTime<Utc>,
+    ) {
+        let mut seen = self.0.lock().unwrap();
+        seen.retain(|_, samples| {
+            samples.retain(|(sampled, _)| (now - *sampled).num_seconds() < PREDICTION_WINDOW_SECS);
+            !samples.is_empty()
+        });
+
+        let journeys = departures
+            .values_mut()
+            .flat_map(|by_line| by_line.values_mut())
+            .flatten();
+        for journey in journeys {
+            let (Some(trip), Some(expected)) = (
+                journey.trip(),
+                journey.arrival_time().and_then(time::parse_instant),
+            ) else {
+                continue;
+            };
+            let stop = journey.monitored_call.stop_point_ref.clone();
+
+            let samples = seen.entry((trip, stop)).or_default();
+            if samples
+                .last()
+                .is_none_or(|(sampled, _)| (now - *sampled).num_seconds() >= PREDICTION_SAMPLE_SECS)
+            {
+                samples.push((now, expected));
+            }
+            journey.prediction_spread = spread(samples);
+        }
+    }
+}
+
+/// How many minutes either side a trip could arrive, judged from the
+/// standard deviation of its predicted arrival times, or `None` while they
+/// hold steady.
+fn spread(samples: &[(DateTime<Utc>, DateTime<Utc>)]) -> Option<i64> {
+    if samples.len() < MIN_PREDICTION_SAMPLES {
+        return None;
+    }
+
+    let latest = samples.last()?.1;
+    let offsets: Vec<f64> = samples
+        .iter()
+        .map(|(_, expected)| (*expected - latest).num_seconds() as f64)
+        .collect();
+    let mean = offsets.iter().sum::<f64>() / offsets.len() as f64;
+    let variance = offsets
+        .iter()
+        .map(|offset| (offset - mean).powi(2))
+        .sum::<f64>()
+        / offsets.len() as f64;
+    let deviation = variance.sqrt();
+
+    (deviation >= STEADY_PREDICTION_SECS).then(|| (deviation / 60.0).ceil() as i64)
+}
+
+/// What to do with departures too soon to catch.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TooSoon {
+    /// Leave them off the board.
+    #[default]
+    Drop,
+    /// Show them in gray.
+    Dim,
+}
+
+/// Which arrivals a row lists.
+#[derive(Debug, Clone, Copy)]
+pub struct Upcoming {
+    pub count: usize,
+    /// Arrivals you'd need to leave for sooner than this many minutes from now
+    /// are too soon to catch.
+    pub min_minutes: i64,
+    pub too_soon: TooSoon,
+    /// Whether to follow the minutes until leaving for a stop with some
+    /// walking time with the minutes until arrival, e.g. "3 (8)".
+    pub show_arrival_time: bool,
+    /// Arrivals you need to leave for within this many minutes are imminent.
+    pub imminent_minutes: Option<i64>,
+    /// Whether to mark late arrivals with how late they're running, e.g.
+    /// "12 +4".
+    pub show_delay: bool,
+    /// Whether to report how crowded vehicles are, where the agency says.
+    pub show_occupancy: bool,
+    /// Whether to draw how far the next vehicle is from the stop.
+    pub show_progress: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Arrival {
+    /// Minutes until you need to leave to catch it: the time until it
+    /// arrives, less the walk to the stop.
+    pub minutes: i64,
+    /// Minutes until it arrives at the stop.
+    pub arrival_minutes: i64,
+    pub uncertainty: Uncertainty,
+    pub too_soon: bool,
+    /// Catchable, but only just: worth drawing attention to.
+    pub imminent: bool,
+    /// Minutes behind the timetable, if running late and delays are shown.
+    pub delay_minutes: Option<i64>,
+    /// The run was cut; `minutes` are until its scheduled time.
+    pub cancelled: bool,
+    /// How crowded the vehicle is, from 1 (plenty of seats) to 3 (full), if
+    /// known and shown.
+    pub crowding: Option<u8>,
+    /// How many stops away the vehicle is, if its position is tracked.
+    pub stops_away: Option<u32>,
+    show_arrival_time: bool,
+}
+
+impl Arrival {
+    /// The minutes to show, e.g. "~3 (8) +2". A dimmed arrival you'd already
+    /// have needed to leave for reads 0 rather than a negative number.
+    pub fn label(&self) -> String {
+        let mut label = self.uncertainty.label(self.minutes.max(0));
+        if self.show_arrival_time && self.arrival_minutes != self.minutes {
+            label.push_str(&format!(" ({})", self.arrival_minutes));
+        }
+        if let Some(delay) = self.delay_minutes {
+            label.push_str(&format!(" +{delay}"));
+        }
+        match self.stops_away {
+            Some(1) => label.push_str(" (1 stop)"),
+            Some(stops) => label.push_str(&format!(" ({stops} stops)")),
+            None => {}
+        }
+
+        label
+    }
+}
+
+/// An occupancy status, as either SIRI or GTFS-RT names it, on a scale from
+/// 1 (plenty of seats) to 3 (full).
+fn crowding(occupancy: &str) -> Option<u8> {
+    match occupancy.to_ascii_lowercase().replace('_', "").as_str() {
+        "empty" | "manyseatsavailable" | "seatsavailable" => Some(1),
+        "fewseatsavailable" | "standingavailable" | "standingroomonly" => Some(2),
+        "crushedstandingroomonly" | "full" | "notacceptingpassengers" => Some(3),
+        _ => None,
+    }
+}
+
+/// The next arrivals from `now`, soonest first, as chosen by `upcoming`.
+pub fn arrivals(
+    journeys: &[MonitoredVehicleJourney],
+    now: DateTime<Utc>,
+    upcoming: &Upcoming,
+    walk_times: &WalkTimes,
+) -> Vec<Arrival> {
+    journeys
+        .iter()
+        .filter_map(|journey| {
+            let call = &journey.monitored_call;
+            let time = time::parse_instant(journey.arrival_time()?)?;
+            let cancelled = journey.is_cancelled();
+            let arrival_minutes = time::minutes_until(now, time)?;
+            let minutes = arrival_minutes - walk_times.get(&call.stop_point_ref);
+            let delay_minutes = call
+                .aimed_arrival_time
+                .as_deref()
+                .and_then(time::parse_instant)
+                .map(|aimed| (time - aimed).num_minutes())
+                .filter(|delay| upcoming.show_delay && !cancelled && *delay > 0);
+
+            Some(Arrival {
+                minutes,
+                arrival_minutes,
+                uncertainty: if cancelled {
+                    Uncertainty::Exact
+                } else {
+                    Uncertainty::of(journey, arrival_minutes)
+                },
+                too_soon: minutes < upcoming.min_minutes,
+                imminent: !cancelled
+                    && minutes >= upcoming.min_minutes
+                    && upcoming
+                        .imminent_minutes
+                        .is_some_and(|imminent| minutes <= imminent),
+                delay_minutes,
+                cancelled,
+                crowding: journey
+                    .occupancy
+                    .as_deref()
+                    .and_then(crowding)
+                    .filter(|_| upcoming.show_occupancy && !cancelled),
+                stops_away: journey.stops_away.filter(|_| !cancelled),
+                show_arrival_time: upcoming.show_arrival_time,
+            })
+        })
+        .filter(|arrival| !(arrival.too_soon && upcoming.too_soon == TooSoon::Drop))
+        .take(upcoming.count)
+        .collect()
+}
+
+/// The soonest arrival on the board that can still be caught, with the line
+/// and destination it's for.
+pub fn next_catchable<'a>(
+    directions: &'a HashMap<String, LinesDestinationsToJourneys>,
+    now: DateTime<Utc>,
+    upcoming: &Upcoming,
+    walk_times: &WalkTimes,
+) -> Option<(&'a str, &'a str, Arrival)> {
+    let upcoming = Upcoming {
+        count: usize::MAX,
+        too_soon: TooSoon::Drop,
+        ..*upcoming
+    };
+
+    directions
+        .values()
+        .flatten()
+        .filter_map(|((line, destination), journeys)| {
+            let arrival = arrivals(journeys, now, &upcoming, walk_times)
+                .into_iter()
+                .find(|arrival| !arrival.cancelled)?;
+            Some((line.as_str(), destination.as_str(), arrival))
+        })
+        .min_by_key(|(line, destination, arrival)| (arrival.minutes, *line, *destination))
+}
+
+/// Arrivals written out for a row, e.g. "3, ~12, 20–26 min".
+pub fn times_str(arrivals: &[Arrival]) -> String {
+    let labels: Vec<_> = arrivals.iter().map(Arrival::label).collect();
+
+    format!("{} min", labels.join(", "))
+}
+
+/// One departure as served at `/stops.json`, for consumers that want the
+/// board's data rather than its picture.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Departure {
+    pub line: String,
+    /// After the configured aliases and rewrites.
+    pub destination: String,
+    pub direction: String,
+    pub stop: String,
+    /// Minutes until it arrives at the stop.
+    pub minutes: i64,
+    /// Minutes until you need to leave for it, allowing for the walk.
+    pub leave_minutes: i64,
+    pub expected_arrival_time: Option<DateTime<Utc>>,
+    pub aimed_arrival_time: Option<DateTime<Utc>>,
+    pub cancelled: bool,
+}
+
+/// Every departure on the board that hasn't left yet, soonest first.
+pub fn list(
+    directions: &HashMap<String, LinesDestinationsToJourneys>,
+    now: DateTime<Utc>,
+    walk_times: &WalkTimes,
+) -> Vec<Departure> {
+    let mut departures: Vec<Departure> = directions
+        .iter()
+        .flat_map(|(direction, by_line)| {
+            by_line
+                .iter()
+                .flat_map(move |((line, destination), journeys)| {
+                    journeys.iter().filter_map(move |journey| {
+                        let call = &journey.monitored_call;
+                        let time = time::parse_instant(journey.arrival_time()?)?;
+                        let minutes = time::minutes_until(now, time)?;
+
+                        Some(Departure {
+                            line: line.clone(),
+                            destination: destination.clone(),
+                            direction: direction.clone(),
+                            stop: call.stop_point_ref.clone(),
+                            minutes,
+                            leave_minutes: minutes - walk_times.get(&call.stop_point_ref),
+                            expected_arrival_time: call
+                                .expected_arrival_time
+                                .as_deref()
+                                .and_then(time::parse_instant),
+                            aimed_arrival_time: call
+                                .aimed_arrival_time
+                                .as_deref()
+                                .and_then(time::parse_instant),
+                            cancelled: journey.is_cancelled(),
+                        })
+                    })
+                })
+        })
+        .collect();
+    departures.sort_by(|a, b| {
+        (a.minutes, &a.line, &a.destination).cmp(&(b.minutes, &b.line, &b.destination))
+    });
+
+    departures
+}
+
+/// One line and destination's departure times, for the printable timetable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimetableRow {
+    pub line: String,
+    pub destination: String,
+    /// Timetabled arrivals, soonest first. Journeys without a timetabled
+    /// time fall back to their expected time.
+    pub times: Vec<DateTime<Utc>>,
+}
+
+/// The departures on the board from `from` until `until`, one row per line
+/// and destination in board order. These are the journeys the realtime feed
+/// currently predicts, at their timetabled times, not the full GTFS
+/// schedule: hours beyond how far ahead the feed predicts, usually an hour or
+/// two, come out empty.
+pub fn timetable(
+    directions: &HashMap<String, LinesDestinationsToJourneys>,
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+    line_order: &[String],
+) -> Vec<TimetableRow> {
+    let mut by_row: HashMap<&(String, String), Vec<DateTime<Utc>>> = HashMap::new();
+    for (key, journeys) in directions.values().flatten() {
+        let times = journeys
+            .iter()
+            .filter(|journey| !journey.is_cancelled())
+            .filter_map(|journey| {
+                let call = &journey.monitored_call;
+                let time = call
+                    .aimed_arrival_time
+                    .as_deref()
+                    .or(call.expected_arrival_time.as_deref())?;
+                time::parse_instant(time)
+            })
+            .filter(|time| (from..until).contains(time));
+        by_row.entry(key).or_default().extend(times);
+    }
+
+    let mut rows: Vec<_> = by_row
+        .into_iter()
+        .filter(|(_, times)| !times.is_empty())
+        .collect();
+    rows.sort_by(|(a, _), (b, _)| row_key(a, line_order).cmp(&row_key(b, line_order)));
+
+    rows.into_iter()
+        .map(|((line, destination), mut times)| {
+            times.sort_unstable();
+            times.dedup();
+            TimetableRow {
+                line: line.clone(),
+                destination: destination.clone(),
+                times,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// A journey on `line` to `destination`, calling at stop 15419.
+    fn journey(line: &str, destination: &str) -> MonitoredVehicleJourney {
+        arriving(line, destination, "18:10")
+    }
+
+    /// A journey on `line` to `destination`, expected at stop 15419 at `at`
+    /// UTC on 2024-02-01.
+    fn arriving(line: &str, destination: &str, at: &str) -> MonitoredVehicleJourney {
+        serde_json::from_value(json!({
+            "LineRef": line,
+            "DirectionRef": "IB",
+            "MonitoredCall": {
+                "StopPointRef": "15419",
+                "DestinationDisplay": destination,
+                "AimedArrivalTime": null,
+                "ExpectedArrivalTime": format!("2024-02-01T{at}:00Z"),
+            },
+        }))
+        .unwrap()
+    }
+
+    /// A response with a visit for each of `journeys`, all recorded at once.
+    fn response(journeys: Vec<MonitoredVehicleJourney>) -> StopMonitoringResponse {
+        StopMonitoringResponse {
+            service_delivery: ServiceDelivery {
+                stop_monitoring_delivery: StopMonitoringDelivery {
+                    monitored_stop_visit: journeys
+                        .into_iter()
+                        .map(|journey| siri::MonitoredStopVisit {
+                            recorded_at_time: None,
+                            monitored_vehicle_journey: journey,
+                        })
+                        .collect(),
+                },
+            },
+        }
+    }
+
+    /// Each (line, destination) row of the inbound direction, with its
+    /// arrival times.
+    fn rows(
+        grouping: &Grouping,
+        journeys: Vec<MonitoredVehicleJourney>,
+    ) -> Vec<(String, String, Vec<String>)> {
+        let mut rows: Vec<_> = group(response(journeys), &["15419"], grouping, &Trips::default())
+            .remove("IB")
+            .unwrap_or_default()
+            .into_iter()
+            .map(|((line, destination), journeys)| {
+                let times = journeys
+                    .iter()
+                    .map(|journey| journey.arrival_time().unwrap()[11..16].to_owned())
+                    .collect();
+                (line, destination, times)
+            })
+            .collect();
+        rows.sort();
+        rows
+    }
+
+    /// `journey`'s destination once `grouping` has normalized it.
+    fn normalized(grouping: &Grouping, destination: &str) -> String {
+        let mut journey = journey("N", destination);
+        grouping.normalize(&mut journey);
+        journey.monitored_call.destination_display.unwrap()
+    }
+
+    fn aliases(pairs: &[(&str, &str)]) -> Grouping {
+        Grouping {
+            destination_aliases: pairs
+                .iter()
+                .map(|(from, to)| (from.to_string(), to.to_string()))
+                .collect(),
+            ..Grouping::default()
+        }
+    }
+
+    #[test]
+    fn aliased_destination_is_renamed() {
+        let grouping = aliases(&[("FERRY PLAZA VIA MARKET ST", "Ferry Plaza")]);
+
+        assert_eq!(
+            normalized(&grouping, "FERRY PLAZA VIA MARKET ST"),
+            "Ferry Plaza"
+        );
+    }
+
+    #[test]
+    fn destination_without_alias_is_left_alone() {
+        let grouping = aliases(&[("FERRY PLAZA VIA MARKET ST", "Ferry Plaza")]);
+
+        assert_eq!(normalized(&grouping, "Ocean Beach"), "Ocean Beach");
+    }
+
+    #[test]
+    fn aliases_match_case_exactly() {
+        let grouping = aliases(&[("FERRY PLAZA", "Ferry Plaza")]);
+
+        assert_eq!(normalized(&grouping, "Ferry plaza"), "Ferry plaza");
+        assert_eq!(normalized(&grouping, "FERRY PLAZA "), "FERRY PLAZA ");
+    }
+
+    #[test]
+    fn aliases_match_before_title_casing() {
+        let mut grouping = aliases(&[("FERRY PLAZA", "Ferry Bldg")]);
+        grouping.title_case = true;
+
+        assert_eq!(normalized(&grouping, "FERRY PLAZA"), "Ferry Bldg");
+        assert_eq!(normalized(&grouping, "OCEAN BEACH"), "Ocean Beach");
+    }
+
+    fn rewrite(field: Field, pattern: &str, replace: &str) -> Rewrite {
+        Rewrite {
+            field,
+            pattern: Regex::new(pattern).unwrap(),
+            replace: replace.to_owned(),
+        }
+    }
+
+    #[test]
+    fn rewrites_apply_in_order() {
+        let forwards = Grouping {
+            rewrites: vec![
+                rewrite(Field::Destination, "ST$", "STREET"),
+                rewrite(Field::Destination, "STREET", "St"),
+            ],
+            ..Grouping::default()
+        };
+        let backwards = Grouping {
+            rewrites: forwards.rewrites.iter().rev().cloned().collect(),
+            ..Grouping::default()
+        };
+
+        assert_eq!(normalized(&forwards, "MARKET ST"), "MARKET St");
+        assert_eq!(normalized(&backwards, "MARKET ST"), "MARKET STREET");
+    }
+
+    #[test]
+    fn rewrites_trim_what_they_leave_and_can_target_the_line() {
+        let grouping = Grouping {
+            rewrites: vec![
+                rewrite(Field::Destination, "VIA .*", ""),
+                rewrite(Field::Line, "^(\\d+)R$", "${1} Rapid"),
+            ],
+            ..Grouping::default()
+        };
+        let mut journey = journey("38R", "FORT MASON VIA GEARY");
+        grouping.normalize(&mut journey);
+
+        assert_eq!(
+            journey.monitored_call.destination_display.as_deref(),
+            Some("FORT MASON")
+        );
+        assert_eq!(journey.line_ref.as_deref(), Some("38 Rapid"));
+    }
+
+    #[test]
+    fn title_case_runs_after_rewrites() {
+        let grouping = Grouping {
+            rewrites: vec![rewrite(Field::Destination, " ST$", " STREET")],
+            title_case: true,
+            ..Grouping::default()
+        };
+
+        assert_eq!(normalized(&grouping, "MARKET ST"), "Market Street");
+    }
+
+    #[test]
+    fn title_case_edge_cases() {
+        assert_eq!(title_case("3RD ST & KING"), "3rd St & King");
+        assert_eq!(title_case("FISHERMAN'S WHARF"), "Fisherman's Wharf");
+        assert_eq!(title_case("O'FARRELL"), "O'farrell");
+        assert_eq!(title_case("VAN NESS  AVE"), "Van Ness  Ave");
+        assert_eq!(title_case("MUNI-METRO"), "Muni-metro");
+    }
+
+    #[test]
+    fn title_case_leaves_mixed_case_alone() {
+        let grouping = Grouping {
+            title_case: true,
+            ..Grouping::default()
+        };
+
+        assert_eq!(normalized(&grouping, "McAllister"), "McAllister");
+        assert_eq!(normalized(&grouping, "SFO/Millbrae"), "SFO/Millbrae");
+    }
+
+    fn strings(strs: &[&str]) -> Vec<String> {
+        strs.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// Which of `shown` lines `grouping` keeps.
+    fn kept<'a>(grouping: &Grouping, shown: &[&'a str]) -> Vec<&'a str> {
+        shown
+            .iter()
+            .copied()
+            .filter(|line| grouping.keeps(&journey(line, "Downtown")))
+            .collect()
+    }
+
+    #[test]
+    fn only_listed_lines_are_kept() {
+        let grouping = Grouping {
+            lines: strings(&["N", "J"]),
+            ..Grouping::default()
+        };
+
+        assert_eq!(kept(&grouping, &["N", "J", "KT", "7"]), ["N", "J"]);
+    }
+
+    #[test]
+    fn excluded_lines_are_dropped() {
+        let grouping = Grouping {
+            exclude_lines: strings(&["NBUS"]),
+            ..Grouping::default()
+        };
+
+        assert_eq!(kept(&grouping, &["N", "NBUS", "7"]), ["N", "7"]);
+    }
+
+    #[test]
+    fn exclusion_wins_over_inclusion() {
+        let grouping = Grouping {
+            lines: strings(&["N", "J"]),
+            exclude_lines: strings(&["J"]),
+            ..Grouping::default()
+        };
+
+        assert_eq!(kept(&grouping, &["N", "J", "7"]), ["N"]);
+    }
+
+    #[test]
+    fn lines_match_whole_names_exactly() {
+        let grouping = Grouping {
+            lines: strings(&["7"]),
+            ..Grouping::default()
+        };
+
+        assert_eq!(kept(&grouping, &["7", "7X", "17", "n"]), ["7"]);
+    }
+
+    #[test]
+    fn lines_match_after_rewrites() {
+        let grouping = Grouping {
+            rewrites: vec![rewrite(Field::Line, "^(\\d+)R$", "${1}")],
+            lines: strings(&["38"]),
+            ..Grouping::default()
+        };
+        let mut rapid = journey("38R", "Downtown");
+        grouping.normalize(&mut rapid);
+
+        assert!(grouping.keeps(&rapid));
+    }
+
+    #[test]
+    fn excluded_destinations_are_dropped_and_the_rest_kept() {
+        let grouping = Grouping {
+            exclude_destinations: strings(&["Sunnydale"]),
+            ..Grouping::default()
+        };
+        let journeys = vec![
+            arriving("T", "Sunnydale", "18:08"),
+            arriving("T", "Chinatown", "18:12"),
+            arriving("T", "Sunnydale", "18:15"),
+            arriving("T", "Chinatown", "18:20"),
+        ];
+
+        assert_eq!(
+            rows(&grouping, journeys),
+            [(
+                "T".to_owned(),
+                "Chinatown".to_owned(),
+                vec!["18:12".to_owned(), "18:20".to_owned()]
+            )]
+        );
+    }
+
+    #[test]
+    fn excluded_destinations_match_after_aliases() {
+        let mut grouping = aliases(&[("SUNNYDALE VIA 3RD ST", "Sunnydale")]);
+        grouping.exclude_destinations = strings(&["Sunnydale"]);
+
+        let mut journey = journey("T", "SUNNYDALE VIA 3RD ST");
+        grouping.normalize(&mut journey);
+
+        assert!(!grouping.keeps(&journey));
+    }
+
+    /// `lines` in board order, given `line_order`.
+    fn sorted(lines: &[&str], line_order: &[&str]) -> Vec<String> {
+        let line_order = strings(line_order);
+        let mut keys: Vec<(String, String)> = lines
+            .iter()
+            .map(|line| (line.to_string(), String::new()))
+            .collect();
+        keys.sort_by(|a, b| row_key(a, &line_order).cmp(&row_key(b, &line_order)));
+
+        keys.into_iter().map(|(line, _)| line).collect()
+    }
+
+    #[test]
+    fn lines_sort_naturally() {
+        assert_eq!(
+            sorted(&["10X", "KT", "2", "J", "10", "1", "N OWL", "N"], &[]),
+            ["1", "2", "10", "10X", "J", "KT", "N", "N OWL"]
+        );
+    }
+
+    #[test]
+    fn digits_inside_names_sort_by_value() {
+        assert_eq!(
+            sorted(&["L12", "L2", "5R", "5", "14R", "14"], &[]),
+            ["5", "5R", "14", "14R", "L2", "L12"]
+        );
+    }
+
+    #[test]
+    fn line_order_comes_first() {
+        assert_eq!(
+            sorted(&["2", "N", "J", "10"], &["N", "10"]),
+            ["N", "10", "2", "J"]
+        );
+    }
+
+    #[test]
+    fn rows_of_one_line_sort_by_destination() {
+        let (a, b) = (
+            ("N".to_owned(), "Ocean Beach".to_owned()),
+            ("N".to_owned(), "Caltrain".to_owned()),
+        );
+
+        assert!(row_key(&b, &[]) < row_key(&a, &[]));
+    }
+
+    #[test]
+    fn line_rows_merge_destinations_with_times_in_order() {
+        let grouping = Grouping {
+            rows: Rows::Line,
+            ..Grouping::default()
+        };
+        let journeys = vec![
+            arriving("K", "Embarcadero", "18:20"),
+            arriving("K", "Castro", "18:07"),
+            arriving("K", "Embarcadero", "18:03"),
+            arriving("K", "Castro", "18:14"),
+            arriving("N", "Caltrain", "18:05"),
+        ];
+
+        assert_eq!(
+            rows(&grouping, journeys),
+            [
+                (
+                    "K".to_owned(),
+                    "Castro / Embarcadero".to_owned(),
+                    strings(&["18:03", "18:07", "18:14", "18:20"])
+                ),
+                ("N".to_owned(), "Caltrain".to_owned(), strings(&["18:05"])),
+            ]
+        );
+    }
+
+    #[test]
+    fn line_rows_name_a_single_destination_plainly() {
+        let grouping = Grouping {
+            rows: Rows::Line,
+            ..Grouping::default()
+        };
+        let journeys = vec![
+            arriving("K", "Castro", "18:07"),
+            arriving("K", "Castro", "18:14"),
+        ];
+
+        assert_eq!(
+            rows(&grouping, journeys),
+            [(
+                "K".to_owned(),
+                "Castro".to_owned(),
+                strings(&["18:07", "18:14"])
+            )]
+        );
+    }
+
+    /// Trip `trip` on the N, expected at `at`, as recorded at `recorded_at`.
+    fn trip(
+        trip: &str,
+        at: &str,
+        recorded_at: &str,
+    ) -> (Option<DateTime<Utc>>, MonitoredVehicleJourney) {
+        let mut journey = arriving("N", "Ocean Beach", at);
+        journey.framed_vehicle_journey_ref = Some(siri::FramedVehicleJourneyRef {
+            data_frame_ref: Some("2024-02-01".to_owned()),
+            dated_vehicle_journey_ref: Some(trip.to_owned()),
+        });
+        let recorded_at = format!("2024-02-01T{recorded_at}:00Z");
+
+        (time::parse_instant(&recorded_at), journey)
+    }
+
+    fn expected_times(journeys: &[MonitoredVehicleJourney]) -> Vec<&str> {
+        journeys
+            .iter()
+            .map(|journey| journey.arrival_time().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn repeated_trips_keep_the_freshest_prediction() {
+        // Trip 1 is reported at two overlapping stops, the fresher prediction
+        // second; trip 2's fresher prediction comes first.
+        let journeys = dedupe(vec![
+            trip("1", "18:10", "18:00"),
+            trip("2", "18:20", "18:02"),
+            trip("1", "18:12", "18:01"),
+            trip("2", "18:18", "18:00"),
+        ]);
+
+        assert_eq!(
+            expected_times(&journeys),
+            ["2024-02-01T18:12:00Z", "2024-02-01T18:20:00Z"]
+        );
+    }
+
+    #[test]
+    fn journeys_without_a_trip_are_all_kept() {
+        let journeys = dedupe(vec![
+            (None, arriving("N", "Ocean Beach", "18:10")),
+            (None, arriving("N", "Ocean Beach", "18:10")),
+        ]);
+
+        assert_eq!(journeys.len(), 2);
+    }
+
+    #[test]
+    fn trips_are_told_apart_by_destination() {
+        let (recorded_at, mut short_turn) = trip("1", "18:10", "18:00");
+        short_turn.monitored_call.destination_display = Some("Duboce".to_owned());
+        let journeys = dedupe(vec![trip("1", "18:10", "18:00"), (recorded_at, short_turn)]);
+
+        assert_eq!(journeys.len(), 2);
+    }
+
+    /// A journey on `line` to `destination` on GTFS trip `trip`, heading in
+    /// `direction` if the feed says.
+    fn on_trip(
+        line: &str,
+        destination: &str,
+        trip: &str,
+        direction: Option<&str>,
+    ) -> MonitoredVehicleJourney {
+        let mut journey = journey(line, destination);
+        journey.direction_ref = direction.map(str::to_owned);
+        journey.framed_vehicle_journey_ref = Some(siri::FramedVehicleJourneyRef {
+            data_frame_ref: None,
+            dated_vehicle_journey_ref: Some(trip.to_owned()),
+        });
+        journey
+    }
+
+    fn trips(directions: &[(&str, &str)]) -> Trips {
+        Trips(
+            directions
+                .iter()
+                .map(|(trip, direction)| (trip.to_string(), direction.to_string()))
+                .collect(),
+        )
+    }
+
+    fn inferred(mut journeys: Vec<MonitoredVehicleJourney>, trips: &Trips) -> Vec<Option<String>> {
+        infer_directions(&mut journeys, trips);
+        journeys
+            .into_iter()
+            .map(|journey| journey.direction_ref)
+            .collect()
+    }
+
+    #[test]
+    fn gtfs_direction_beats_the_destination() {
+        // A short turn back the other way, to a destination also reached
+        // inbound.
+        let journeys = vec![
+            on_trip("N", "Ocean Beach", "1", Some("IB")),
+            on_trip("N", "Embarcadero", "2", Some("OB")),
+            on_trip("N", "Ocean Beach", "3", None),
+        ];
+        let trips = trips(&[("1", "1"), ("2", "0"), ("3", "0")]);
+
+        assert_eq!(inferred(journeys.clone(), &trips)[2].as_deref(), Some("OB"));
+        assert_eq!(
+            inferred(journeys, &Trips::default())[2].as_deref(),
+            Some("IB")
+        );
+    }
+
+    #[test]
+    fn gtfs_directions_carry_across_lines() {
+        let journeys = vec![
+            on_trip("N", "Ocean Beach", "1", Some("OB")),
+            on_trip("J", "Embarcadero", "2", None),
+        ];
+        let trips = trips(&[("1", "0"), ("2", "0")]);
+
+        assert_eq!(inferred(journeys, &trips)[1].as_deref(), Some("OB"));
+    }
+
+    #[test]
+    fn unknown_trips_fall_back_to_guessing() {
+        let journeys = vec![
+            on_trip("N", "Ocean Beach", "1", Some("IB")),
+            on_trip("N", "Ocean Beach", "2", None),
+        ];
+        let trips = trips(&[("1", "1")]);
+
+        assert_eq!(inferred(journeys, &trips)[1].as_deref(), Some("IB"));
+    }
+
+    /// Track trip 1 predicted at `at` as of `now` (both "HH:MM:SS" UTC on
+    /// 2024-02-01), returning the spread it's given.
+    fn track(predictions: &Predictions, at: &str, now: &str) -> Option<i64> {
+        let (_, mut journey) = trip("1", "18:00", "18:00");
+        journey.monitored_call.expected_arrival_time = Some(format!("2024-02-01T{at}Z"));
+        let mut departures = HashMap::from([(
+            "OB".to_owned(),
+            HashMap::from([(("N".to_owned(), "Ocean Beach".to_owned()), vec![journey])]),
+        )]);
+
+        let now = time::parse_instant(&format!("2024-02-01T{now}Z")).unwrap();
+        predictions.track(&mut departures, now);
+        departures["OB"][&("N".to_owned(), "Ocean Beach".to_owned())][0].prediction_spread
+    }
+
+    #[test]
+    fn steady_predictions_have_no_spread() {
+        let predictions = Predictions::default();
+
+        assert_eq!(track(&predictions, "18:10:00", "18:00:00"), None);
+        assert_eq!(track(&predictions, "18:10:30", "18:01:00"), None);
+        assert_eq!(track(&predictions, "18:09:40", "18:02:00"), None);
+        assert_eq!(track(&predictions, "18:10:10", "18:03:00"), None);
+    }
+
+    #[test]
+    fn wandering_predictions_are_spread() {
+        let predictions = Predictions::default();
+
+        assert_eq!(track(&predictions, "18:10:00", "18:00:00"), None);
+        assert_eq!(track(&predictions, "18:14:00", "18:01:00"), None);
+        // Deviation of about 3.3 minutes, so 4 either side.
+        assert_eq!(track(&predictions, "18:06:00", "18:02:00"), Some(4));
+    }
+
+    #[test]
+    fn renders_of_one_fetch_count_once() {
+        let predictions = Predictions::default();
+
+        assert_eq!(track(&predictions, "18:10:00", "18:00:00"), None);
+        assert_eq!(track(&predictions, "18:14:00", "18:00:10"), None);
+        assert_eq!(track(&predictions, "18:06:00", "18:00:20"), None);
+    }
+
+    #[test]
+    fn old_predictions_are_forgotten() {
+        let predictions = Predictions::default();
+
+        track(&predictions, "18:10:00", "18:00:00");
+        track(&predictions, "18:14:00", "18:01:00");
+        assert_eq!(track(&predictions, "18:06:00", "18:12:00"), None);
+    }
+
+    #[test]
+    fn spread_widens_the_uncertainty() {
+        let mut journey = journey("N", "Ocean Beach");
+        journey.prediction_spread = Some(3);
+        assert_eq!(Uncertainty::of(&journey, 10), Uncertainty::Spread(3));
+
+        // Flagged inaccurate, the wider of the two ranges wins.
+        journey.prediction_inaccurate = Some(true);
+        assert_eq!(Uncertainty::of(&journey, 10), Uncertainty::Spread(3));
+        assert_eq!(Uncertainty::of(&journey, 40), Uncertainty::Spread(6));
+
+        journey.prediction_spread = None;
+        journey.prediction_inaccurate = None;
+        assert_eq!(Uncertainty::of(&journey, 10), Uncertainty::Exact);
+    }
+}
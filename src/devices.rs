@@ -0,0 +1,116 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Serialize;
+
+/// How many days of frame counts are kept for each device.
+const DAYS_KEPT: usize = 7;
+
+/// How many devices are tracked at once. Devices name themselves with `?id=`,
+/// so without a limit any client could grow the registry without end.
+pub const MAX_DEVICES: usize = 64;
+
+#[derive(Debug)]
+struct DeviceStats {
+    frames_by_day: BTreeMap<NaiveDate, u32>,
+    last_seen: DateTime<Utc>,
+    /// Sum and count of the gaps between requests, for the mean.
+    interval_secs: f64,
+    intervals: u32,
+    requests: u32,
+    not_modified: u32,
+}
+
+/// How often a device has polled for frames, to tune its refresh interval
+/// against battery life.
+#[derive(Serialize, Debug)]
+pub struct DeviceSummary {
+    pub id: String,
+    pub last_seen: DateTime<Utc>,
+    /// Frames requested on each of the last few service days, oldest first,
+    /// so a board left on past midnight counts toward the evening before.
+    pub frames_by_day: BTreeMap<NaiveDate, u32>,
+    pub average_interval_secs: Option<f64>,
+    /// Share of requests answered with 304 Not Modified.
+    pub not_modified_ratio: f64,
+}
+
+/// Polling statistics for each device that requests frames, kept in memory.
+#[derive(Debug, Default)]
+pub struct DeviceRegistry {
+    devices: Mutex<HashMap<String, DeviceStats>>,
+}
+
+impl DeviceRegistry {
+    /// Count a frame request from `id` toward the service `day`.
+    pub fn record(&self, id: &str, now: DateTime<Utc>, day: NaiveDate, not_modified: bool) {
+        let mut devices = self.devices.lock().unwrap();
+        if !devices.contains_key(id) {
+            make_room(&mut devices, now);
+        }
+        let stats = devices.entry(id.to_owned()).or_insert_with(|| DeviceStats {
+            frames_by_day: BTreeMap::new(),
+            last_seen: now,
+            interval_secs: 0.0,
+            intervals: 0,
+            requests: 0,
+            not_modified: 0,
+        });
+
+        if stats.requests > 0 {
+            stats.interval_secs += (now - stats.last_seen).num_milliseconds() as f64 / 1000.0;
+            stats.intervals += 1;
+        }
+        stats.last_seen = now;
+        stats.requests += 1;
+        if not_modified {
+            stats.not_modified += 1;
+        }
+
+        *stats.frames_by_day.entry(day).or_default() += 1;
+        while stats.frames_by_day.len() > DAYS_KEPT {
+            stats.frames_by_day.pop_first();
+        }
+    }
+
+    /// Every device seen, most recently seen first.
+    pub fn summaries(&self) -> Vec<DeviceSummary> {
+        let devices = self.devices.lock().unwrap();
+        let mut summaries: Vec<_> = devices
+            .iter()
+            .map(|(id, stats)| DeviceSummary {
+                id: id.clone(),
+                last_seen: stats.last_seen,
+                frames_by_day: stats.frames_by_day.clone(),
+                average_interval_secs: (stats.intervals > 0)
+                    .then(|| stats.interval_secs / stats.intervals as f64),
+                not_modified_ratio: stats.not_modified as f64 / stats.requests as f64,
+            })
+            .collect();
+        summaries.sort_by_key(|summary| Reverse(summary.last_seen));
+
+        summaries
+    }
+}
+
+/// Forget devices that haven't polled for as long as their counts are kept,
+/// and if that isn't enough to fit another, the one seen longest ago.
+fn make_room(devices: &mut HashMap<String, DeviceStats>, now: DateTime<Utc>) {
+    let idle = Duration::days(DAYS_KEPT as i64);
+    devices.retain(|_, stats| now - stats.last_seen < idle);
+
+    while devices.len() >= MAX_DEVICES {
+        let Some(oldest) = devices
+            .iter()
+            .min_by_key(|(_, stats)| stats.last_seen)
+            .map(|(id, _)| id.clone())
+        else {
+            break;
+        };
+        devices.remove(&oldest);
+    }
+}
@@ -0,0 +1,156 @@
+use std::path::{Path, PathBuf};
+
+use eyre::eyre;
+use serde::{Deserialize, Serialize};
+use toml_edit::{DocumentMut, Item};
+
+use crate::{
+    config::Config,
+    departures::{Grouping, Stop, WalkTimes},
+    layout::{Layout, Orientation},
+    render::device::Device,
+};
+
+/// A board of its own, served at `/displays/<name>.png`, so one server can
+/// drive Kindles in several rooms, or a friend's, each with its own stops and
+/// layout. Anything left unset follows the top-level config.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Display {
+    pub stops: Vec<Stop>,
+    /// Replaces the top-level `grouping` for this display.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grouping: Option<Grouping>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub portrait_layout: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub orientation: Option<Orientation>,
+    /// Device preset used when a request doesn't name one, e.g. "kindle_pw3".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    /// Size used when a request doesn't give one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+}
+
+impl Display {
+    pub fn stop_codes(&self) -> Vec<&str> {
+        self.stops.iter().map(|stop| stop.code.as_str()).collect()
+    }
+
+    pub fn walk_times(&self) -> WalkTimes {
+        WalkTimes::new(&self.stops)
+    }
+}
+
+/// Something wrong with a display, for API clients to point out next to the
+/// field it's about.
+#[derive(Serialize, Debug, Clone)]
+pub struct Invalid {
+    /// The field at fault, e.g. "stops[1].code", or `None` for the display as
+    /// a whole.
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl Invalid {
+    pub fn new(field: Option<&str>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.map(str::to_owned),
+            message: message.into(),
+        }
+    }
+}
+
+/// Whether `name` works in a `/displays/<name>.png` path.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl Display {
+    /// Everything wrong with this display, or nothing if it's fit to serve.
+    pub fn validate(&self) -> Vec<Invalid> {
+        let mut invalid = Vec::new();
+
+        if self.stops.is_empty() {
+            invalid.push(Invalid::new(Some("stops"), "needs at least one stop"));
+        }
+        for (i, stop) in self.stops.iter().enumerate() {
+            if stop.code.trim().is_empty() {
+                let field = format!("stops[{i}].code");
+                invalid.push(Invalid::new(Some(&field), "can't be empty"));
+            }
+            if stop.walk_minutes < 0 {
+                let field = format!("stops[{i}].walk_minutes");
+                invalid.push(Invalid::new(Some(&field), "can't be negative"));
+            }
+        }
+
+        for (field, path) in [
+            ("layout", &self.layout),
+            ("portrait_layout", &self.portrait_layout),
+        ] {
+            if let Some(Err(e)) = path.as_deref().map(Layout::load) {
+                invalid.push(Invalid::new(Some(field), format!("{e:#}")));
+            }
+        }
+
+        if let Some(name) = &self.device {
+            if Device::find(name).is_none() {
+                invalid.push(Invalid::new(
+                    Some("device"),
+                    format!("unknown device {name:?}"),
+                ));
+            }
+        }
+        for (field, dimension) in [("width", self.width), ("height", self.height)] {
+            if dimension == Some(0) {
+                invalid.push(Invalid::new(Some(field), "must be at least 1"));
+            }
+        }
+
+        invalid
+    }
+
+    /// Write this display into the config file at `path` as `name`,
+    /// replacing any display already called that.
+    pub fn save(&self, path: &Path, name: &str) -> eyre::Result<()> {
+        let table: DocumentMut = toml::to_string(self)?
+            .parse()
+            .map_err(|e| eyre!("couldn't convert display to TOML: {e}"))?;
+
+        Config::edit(path, |document| {
+            let displays = document
+                .entry("displays")
+                .or_insert(toml_edit::table())
+                .as_table_mut()
+                .ok_or_else(|| eyre!("displays in the config file isn't a table"))?;
+            displays.set_implicit(true);
+            displays.insert(name, Item::Table(table.as_table().clone()));
+            Ok(())
+        })
+    }
+}
+
+/// Take the display called `name` out of the config file at `path`. Whether
+/// there was one to remove.
+pub fn remove(path: &Path, name: &str) -> eyre::Result<bool> {
+    let mut removed = false;
+    Config::edit(path, |document| {
+        removed = document
+            .get_mut("displays")
+            .and_then(Item::as_table_like_mut)
+            .and_then(|displays| displays.remove(name))
+            .is_some();
+        Ok(())
+    })?;
+
+    Ok(removed)
+}
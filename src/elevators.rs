@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::cache::Refreshed;
+
+const BART_ELEVATORS_URL: &str = "https://api.bart.gov/api/bsa.aspx";
+
+/// BART's published key for its public API.
+const BART_PUBLIC_KEY: &str = "MW9S-E7SL-26DU-VV8V";
+
+#[derive(Deserialize, Debug)]
+struct Response {
+    root: Root,
+}
+
+#[derive(Deserialize, Debug)]
+struct Root {
+    #[serde(default)]
+    bsa: Vec<Advisory>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Advisory {
+    description: Option<Description>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Description {
+    #[serde(rename = "#cdata-section")]
+    text: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ElevatorsConfig {
+    /// BART stations to report on, by name as BART writes them, e.g.
+    /// "Civic Center".
+    pub stations: Vec<String>,
+    /// BART API key. BART's public key is used when this is unset.
+    pub api_key: Option<String>,
+    /// How long fetched outages are reused before asking BART again.
+    pub refresh_secs: u64,
+}
+
+impl Default for ElevatorsConfig {
+    fn default() -> Self {
+        Self {
+            stations: Vec::new(),
+            api_key: None,
+            refresh_secs: 600,
+        }
+    }
+}
+
+/// The configured stations named in BART's elevator advisories, in config
+/// order. BART lists every outage in one sentence ("There are 2 elevators out
+/// of service at this time: Civic Center Platform and ..."), so a station is
+/// out if the advisory mentions it.
+pub fn parse(body: &str, stations: &[String]) -> eyre::Result<Vec<String>> {
+    let response: Response = serde_json::from_str(body.trim_start_matches('\u{feff}'))?;
+    let advisories = response
+        .root
+        .bsa
+        .iter()
+        .filter_map(|advisory| advisory.description.as_ref())
+        .map(|description| description.text.to_lowercase())
+        .collect::<Vec<_>>();
+
+    Ok(stations
+        .iter()
+        .filter(|station| {
+            let station = station.to_lowercase();
+            advisories
+                .iter()
+                .any(|advisory| advisory.contains(&station))
+        })
+        .cloned()
+        .collect())
+}
+
+/// Elevator outages at the configured stations, refetched at most every
+/// `refresh_secs`.
+#[derive(Debug)]
+pub struct ElevatorOutages {
+    config: Option<ElevatorsConfig>,
+    cache: Refreshed<Vec<String>>,
+}
+
+impl ElevatorOutages {
+    /// Reports no outages if `config` is `None`.
+    pub fn new(config: Option<ElevatorsConfig>) -> Self {
+        let refresh_secs = config.as_ref().map_or(0, |config| config.refresh_secs);
+
+        Self {
+            config,
+            cache: Refreshed::new(Duration::from_secs(refresh_secs)),
+        }
+    }
+
+    /// The configured stations with an elevator out of service, or `None` if
+    /// outages aren't being tracked.
+    pub async fn get(&self, client: &Client) -> Option<eyre::Result<Vec<String>>> {
+        let config = self.config.as_ref()?;
+
+        let outages = self
+            .cache
+            .get("elevator outages", async {
+                let key = config.api_key.as_deref().unwrap_or(BART_PUBLIC_KEY);
+                let body = client
+                    .get(BART_ELEVATORS_URL)
+                    .query(&[("cmd", "elev"), ("key", key), ("json", "y")])
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await?;
+                parse(&body, &config.stations)
+            })
+            .await;
+
+        Some(outages)
+    }
+}
@@ -0,0 +1,397 @@
+//! Turning addresses into coordinates, for finding stops near a place.
+//!
+//! Public geocoders are run by volunteers and ask that clients identify
+//! themselves and keep to about one request a second, so every provider goes
+//! through [`Etiquette`].
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use eyre::eyre;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{
+    cache::Refreshed,
+    siri::{self, StopPoint},
+    upstream::{Transport, Upstream},
+};
+
+const NOMINATIM_URL: &str = "https://nominatim.openstreetmap.org";
+const PHOTON_URL: &str = "https://photon.komoot.io";
+
+/// How many places a search returns at most.
+const SEARCH_LIMIT: u8 = 5;
+
+/// How many stops a stop search returns at most.
+const NEARBY_LIMIT: usize = 10;
+
+/// How long an agency's list of stops is reused. Stops rarely move, and the
+/// list is one of the largest 511 responses.
+const STOP_LIST_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Place {
+    pub name: String,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl Place {
+    /// Distance along the ground to `lat`, `lon`, in meters.
+    pub fn meters_to(&self, lat: f64, lon: f64) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), lat.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (lon - self.lon).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+
+        2.0 * EARTH_RADIUS_METERS * a.sqrt().asin()
+    }
+}
+
+pub trait Geocoder {
+    /// Places matching `query`, best match first.
+    fn search(&self, query: &str) -> impl Future<Output = eyre::Result<Vec<Place>>> + Send;
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Provider {
+    #[default]
+    Nominatim,
+    Photon,
+    /// No network access: queries must already be "lat, lon".
+    Offline,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeocodingConfig {
+    pub provider: Provider,
+    /// Base URL of a self-hosted instance, instead of the public one.
+    pub url: Option<String>,
+    /// Sent with every request, as the public instances require.
+    pub user_agent: String,
+    /// Contact address passed to Nominatim, so its operators can reach you
+    /// rather than block you.
+    pub email: Option<String>,
+    /// Least time between two requests.
+    pub min_interval_ms: u64,
+}
+
+impl Default for GeocodingConfig {
+    fn default() -> Self {
+        Self {
+            provider: Provider::default(),
+            url: None,
+            user_agent: concat!("transit-kindle/", env!("CARGO_PKG_VERSION")).to_owned(),
+            email: None,
+            min_interval_ms: 1000,
+        }
+    }
+}
+
+/// Identifies the client and spaces out requests to a geocoding service.
+#[derive(Debug)]
+pub struct Etiquette {
+    client: Client,
+    min_interval: Duration,
+    last_request: Mutex<Option<Instant>>,
+}
+
+impl Etiquette {
+    pub fn new(config: &GeocodingConfig) -> eyre::Result<Self> {
+        Ok(Self {
+            client: Client::builder().user_agent(&config.user_agent).build()?,
+            min_interval: Duration::from_millis(config.min_interval_ms),
+            last_request: Mutex::new(None),
+        })
+    }
+
+    /// The client to make a request with, once enough time has passed since
+    /// the last one.
+    async fn client(&self) -> &Client {
+        let mut last_request = self.last_request.lock().await;
+        if let Some(last) = *last_request {
+            tokio::time::sleep(self.min_interval.saturating_sub(last.elapsed())).await;
+        }
+        *last_request = Some(Instant::now());
+
+        &self.client
+    }
+}
+
+#[derive(Debug)]
+pub struct Nominatim {
+    url: String,
+    email: Option<String>,
+    etiquette: Etiquette,
+}
+
+#[derive(Deserialize)]
+struct NominatimPlace {
+    display_name: String,
+    lat: String,
+    lon: String,
+}
+
+impl Geocoder for Nominatim {
+    async fn search(&self, query: &str) -> eyre::Result<Vec<Place>> {
+        let limit = SEARCH_LIMIT.to_string();
+        let mut params = vec![
+            ("q", query),
+            ("format", "jsonv2"),
+            ("limit", limit.as_str()),
+        ];
+        if let Some(email) = &self.email {
+            params.push(("email", email.as_str()));
+        }
+
+        let places: Vec<NominatimPlace> = self
+            .etiquette
+            .client()
+            .await
+            .get(format!("{}/search", self.url))
+            .query(&params)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        places
+            .into_iter()
+            .map(|place| -> eyre::Result<Place> {
+                Ok(Place {
+                    name: place.display_name,
+                    lat: place.lat.parse()?,
+                    lon: place.lon.parse()?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct Photon {
+    url: String,
+    etiquette: Etiquette,
+}
+
+#[derive(Deserialize)]
+struct PhotonResponse {
+    features: Vec<PhotonFeature>,
+}
+
+#[derive(Deserialize)]
+struct PhotonFeature {
+    geometry: PhotonGeometry,
+    properties: PhotonProperties,
+}
+
+#[derive(Deserialize)]
+struct PhotonGeometry {
+    /// GeoJSON order: longitude, then latitude.
+    coordinates: (f64, f64),
+}
+
+#[derive(Deserialize)]
+struct PhotonProperties {
+    name: Option<String>,
+    street: Option<String>,
+    housenumber: Option<String>,
+    city: Option<String>,
+}
+
+impl PhotonProperties {
+    fn name(self) -> String {
+        let street = match (self.housenumber, self.street) {
+            (Some(number), Some(street)) => Some(format!("{number} {street}")),
+            (_, street) => street,
+        };
+
+        [self.name, street, self.city]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl Geocoder for Photon {
+    async fn search(&self, query: &str) -> eyre::Result<Vec<Place>> {
+        let limit = SEARCH_LIMIT.to_string();
+        let response: PhotonResponse = self
+            .etiquette
+            .client()
+            .await
+            .get(format!("{}/api/", self.url))
+            .query(&[("q", query), ("limit", limit.as_str())])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(response
+            .features
+            .into_iter()
+            .map(|feature| {
+                let (lon, lat) = feature.geometry.coordinates;
+                Place {
+                    name: feature.properties.name(),
+                    lat,
+                    lon,
+                }
+            })
+            .collect())
+    }
+}
+
+/// Accepts only coordinates, for setups without internet access or that
+/// would rather not send their address anywhere.
+#[derive(Debug, Default)]
+pub struct Offline;
+
+impl Geocoder for Offline {
+    async fn search(&self, query: &str) -> eyre::Result<Vec<Place>> {
+        let (lat, lon) = query
+            .split_once(',')
+            .ok_or_else(|| eyre!("offline geocoding only understands \"lat, lon\""))?;
+
+        Ok(vec![Place {
+            name: query.trim().to_owned(),
+            lat: lat.trim().parse()?,
+            lon: lon.trim().parse()?,
+        }])
+    }
+}
+
+/// Whichever geocoder is configured.
+#[derive(Debug)]
+pub enum AnyGeocoder {
+    Nominatim(Nominatim),
+    Photon(Photon),
+    Offline(Offline),
+}
+
+impl AnyGeocoder {
+    pub fn new(config: &GeocodingConfig) -> eyre::Result<Self> {
+        let url = |default: &str| {
+            config
+                .url
+                .as_deref()
+                .unwrap_or(default)
+                .trim_end_matches('/')
+                .to_owned()
+        };
+
+        Ok(match config.provider {
+            Provider::Nominatim => Self::Nominatim(Nominatim {
+                url: url(NOMINATIM_URL),
+                email: config.email.clone(),
+                etiquette: Etiquette::new(config)?,
+            }),
+            Provider::Photon => Self::Photon(Photon {
+                url: url(PHOTON_URL),
+                etiquette: Etiquette::new(config)?,
+            }),
+            Provider::Offline => Self::Offline(Offline),
+        })
+    }
+}
+
+impl Geocoder for AnyGeocoder {
+    async fn search(&self, query: &str) -> eyre::Result<Vec<Place>> {
+        // Coordinates never need a round trip, whatever the provider.
+        if let Ok(places) = Offline.search(query).await {
+            return Ok(places);
+        }
+
+        match self {
+            Self::Nominatim(geocoder) => geocoder.search(query).await,
+            Self::Photon(geocoder) => geocoder.search(query).await,
+            Self::Offline(geocoder) => geocoder.search(query).await,
+        }
+    }
+}
+
+/// A stop near a searched place.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct NearbyStop {
+    #[serde(flatten)]
+    pub stop: StopPoint,
+    pub meters: u32,
+}
+
+/// Finds the stops nearest an address, for choosing the `stops` of a board
+/// without looking their codes up on a map.
+#[derive(Debug)]
+pub struct StopSearch {
+    geocoder: AnyGeocoder,
+    stops: std::sync::Mutex<HashMap<String, Arc<Refreshed<Vec<StopPoint>>>>>,
+}
+
+impl StopSearch {
+    pub fn new(config: &GeocodingConfig) -> eyre::Result<Self> {
+        Ok(Self {
+            geocoder: AnyGeocoder::new(config)?,
+            stops: std::sync::Mutex::default(),
+        })
+    }
+
+    /// The best match for `query` and the stops of `agency` nearest it,
+    /// nearest first.
+    pub async fn search(
+        &self,
+        client: &dyn Transport,
+        upstream: &Upstream,
+        query: &str,
+        agency: &str,
+    ) -> eyre::Result<(Place, Vec<NearbyStop>)> {
+        let place = self
+            .geocoder
+            .search(query)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| eyre!("no place matches {query:?}"))?;
+
+        let cache = self
+            .stops
+            .lock()
+            .unwrap()
+            .entry(agency.to_owned())
+            .or_insert_with(|| Arc::new(Refreshed::new(STOP_LIST_MAX_AGE)))
+            .clone();
+        let stops = cache
+            .get(
+                "stop list",
+                siri::fetch_stop_points(client, upstream, agency),
+            )
+            .await?;
+
+        Ok((place.clone(), nearest(&place, stops)))
+    }
+}
+
+/// The `NEARBY_LIMIT` of `stops` nearest `place`, nearest first.
+pub fn nearest(place: &Place, stops: Vec<StopPoint>) -> Vec<NearbyStop> {
+    let mut nearby: Vec<NearbyStop> = stops
+        .into_iter()
+        .map(|stop| NearbyStop {
+            meters: place.meters_to(stop.lat, stop.lon).round() as u32,
+            stop,
+        })
+        .collect();
+    nearby.sort_by_key(|nearby| nearby.meters);
+    nearby.truncate(NEARBY_LIMIT);
+    nearby
+}
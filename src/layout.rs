@@ -0,0 +1,889 @@
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use eyre::ensure;
+use serde::{Deserialize, Serialize};
+use skia_safe::{utils::text_utils::Align, Rect};
+
+use crate::{
+    config::Config,
+    departures::{TooSoon, Upcoming},
+    display::Display,
+    render::frame::Dither,
+};
+
+/// The boards that are drawn when no layout template is configured.
+const LANDSCAPE_LAYOUT: &str = include_str!("../layouts/default.toml");
+const PORTRAIT_LAYOUT: &str = include_str!("../layouts/portrait.toml");
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Orientation {
+    #[default]
+    Landscape,
+    Portrait,
+}
+
+/// A declarative description of the board: a canvas size and a list of panels
+/// drawn in order, so later panels paint over earlier ones.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Layout {
+    pub width: f32,
+    pub height: f32,
+
+    /// Font used by any panel that doesn't specify its own.
+    #[serde(default)]
+    pub font: FontSpec,
+
+    /// How many upcoming times each departures row lists, unless its panel
+    /// says otherwise.
+    #[serde(default = "default_upcoming_times")]
+    pub upcoming_times: usize,
+
+    /// Departures sooner than this many minutes can't be caught.
+    #[serde(default)]
+    pub min_minutes: i64,
+
+    /// Whether departures sooner than `min_minutes` are dropped or dimmed.
+    #[serde(default)]
+    pub too_soon: TooSoon,
+
+    /// Whether arrivals at stops with a walking time also show the minutes
+    /// until the vehicle arrives, after the minutes until you need to leave.
+    #[serde(default)]
+    pub show_arrival_time: bool,
+
+    /// Departures you need to leave for within this many minutes are drawn
+    /// with `imminent_style`. Off when unset.
+    #[serde(default)]
+    pub imminent_minutes: Option<i64>,
+
+    #[serde(default)]
+    pub imminent_style: Emphasis,
+
+    /// Whether late departures show how far behind the timetable they are.
+    #[serde(default)]
+    pub show_delay: bool,
+
+    /// Whether departures show how crowded the vehicle is, where known.
+    #[serde(default = "default_show_occupancy")]
+    pub show_occupancy: bool,
+
+    /// Whether each row draws how far its next vehicle is from the stop, when
+    /// vehicle positions are tracked.
+    #[serde(default)]
+    pub show_progress: bool,
+
+    /// How separators, fills, and borders are drawn across the board.
+    #[serde(default)]
+    pub theme: Theme,
+
+    pub panels: Vec<Panel>,
+}
+
+/// How to make a departure stand out.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Emphasis {
+    #[default]
+    Bold,
+    /// White on black.
+    Inverted,
+}
+
+/// Replaces the text of a named `text` panel, e.g. a direction's header.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Title {
+    pub text: Option<String>,
+    pub align: Option<Alignment>,
+}
+
+/// What a departures panel does with rows that don't fit.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Overflow {
+    /// Cycle through pages of rows, one page per refresh.
+    #[default]
+    Page,
+    /// Show the rows departing soonest and count the rest.
+    Soonest,
+}
+
+/// The visual hierarchy elements of a board, so it can range from a dense
+/// data table to a sparse, airy layout without moving any panels.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// `line` panels.
+    pub divider: Stroke,
+    /// The rule under each row of a departures panel.
+    pub row_separator: Stroke,
+    /// A frame around each departures panel.
+    pub border: Stroke,
+    /// Whether `fill` panels, such as the header strip, are drawn.
+    pub fills: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            divider: Stroke::default(),
+            row_separator: Stroke::default(),
+            border: Stroke {
+                enabled: false,
+                ..Stroke::default()
+            },
+            fills: true,
+        }
+    }
+}
+
+impl Theme {
+    fn scaled(&self, scale: f32) -> Self {
+        Self {
+            divider: self.divider.scaled(scale),
+            row_separator: self.row_separator.scaled(scale),
+            border: self.border.scaled(scale),
+            fills: self.fills,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Stroke {
+    pub enabled: bool,
+    pub width: f32,
+    /// 0.0 black – 1.0 white.
+    pub shade: f32,
+    /// Alternating on and off lengths, e.g. `[4, 4]`. Solid when empty.
+    pub dash: Vec<f32>,
+}
+
+impl Default for Stroke {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            width: 1.0,
+            shade: 0.0,
+            dash: Vec::new(),
+        }
+    }
+}
+
+impl Stroke {
+    fn scaled(&self, scale: f32) -> Self {
+        Self {
+            enabled: self.enabled,
+            width: self.width * scale,
+            shade: self.shade,
+            dash: self.dash.iter().map(|length| length * scale).collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Panel {
+    /// A solid rectangle, e.g. a header strip.
+    Fill {
+        rect: Bounds,
+        #[serde(default = "default_fill_shade")]
+        shade: f32,
+    },
+
+    /// A one pixel black line.
+    Line { from: (f32, f32), to: (f32, f32) },
+
+    /// Static text, vertically centered in `rect`.
+    Text {
+        rect: Bounds,
+        text: String,
+        #[serde(default)]
+        align: Alignment,
+        font: Option<FontSpec>,
+        /// Lets the config's `titles` replace the text and alignment.
+        name: Option<String>,
+    },
+
+    /// One row per (line, destination) for the journeys heading in `direction`.
+    Departures {
+        rect: Bounds,
+        direction: String,
+        font: Option<FontSpec>,
+        #[serde(default = "default_row_height")]
+        row_height: f32,
+        #[serde(default = "default_fill_shade")]
+        bubble_shade: f32,
+        /// Rows too wide for the panel shrink their font, down to this size.
+        #[serde(default = "default_min_font_size")]
+        min_font_size: f32,
+        /// Overrides the layout's `upcoming_times` for this panel.
+        upcoming_times: Option<usize>,
+        #[serde(default)]
+        overflow: Overflow,
+    },
+
+    /// Annotations posted to `/api/annotations`, most severe first. Nothing
+    /// is drawn while there are none, so place it over space that can be
+    /// covered.
+    Banner {
+        rect: Bounds,
+        font: Option<FontSpec>,
+    },
+
+    /// When to head out for the soonest departure you can still catch.
+    LeaveNow {
+        rect: Bounds,
+        font: Option<FontSpec>,
+    },
+
+    /// The routes from `gtfs_shapes` within the given edges, in degrees,
+    /// with a dot for each tracked vehicle on `lines` (every line if empty).
+    Map {
+        rect: Bounds,
+        north: f64,
+        south: f64,
+        east: f64,
+        west: f64,
+        #[serde(default)]
+        lines: Vec<String>,
+    },
+
+    /// Configured BART stations with an elevator out of service.
+    Elevators {
+        rect: Bounds,
+        font: Option<FontSpec>,
+    },
+
+    /// The temperature and chance of rain over the next two hours, with an
+    /// icon for the sky.
+    Weather {
+        rect: Bounds,
+        font: Option<FontSpec>,
+    },
+
+    /// Bikes, e-bikes and docks free at the configured bike-share stations,
+    /// one station per line.
+    Bikes {
+        rect: Bounds,
+        font: Option<FontSpec>,
+    },
+
+    /// Air quality and today's sunrise and sunset, e.g. "AQI 42 · Sunrise
+    /// 7:02 · Sunset 18:15", for the location in the `weather` config.
+    Environment {
+        rect: Bounds,
+        font: Option<FontSpec>,
+    },
+
+    /// The current time in large digits, e.g. in the header strip.
+    Clock {
+        rect: Bounds,
+        #[serde(default = "default_clock_align")]
+        align: Alignment,
+        font: Option<FontSpec>,
+        /// "7:42 PM" instead of "19:42".
+        #[serde(default)]
+        twelve_hour: bool,
+    },
+
+    /// Today's date, "Tue Mar 4" unless `format` (strftime) says otherwise.
+    Date {
+        rect: Bounds,
+        #[serde(default = "default_clock_align")]
+        align: Alignment,
+        font: Option<FontSpec>,
+        #[serde(default = "default_date_format")]
+        format: String,
+    },
+
+    /// When the board was last updated and where its data comes from, e.g.
+    /// "Updated 07:42 · data: 511.org", so a stale display stands out.
+    Footer {
+        rect: Bounds,
+        #[serde(default = "default_footer_align")]
+        align: Alignment,
+        font: Option<FontSpec>,
+        #[serde(default = "default_footer_source")]
+        source: String,
+    },
+
+    /// A QR code for `url`, so a phone can pick up the board where the
+    /// display leaves off. Points at the config's `public_url` unless given
+    /// one; nothing is drawn if neither is set.
+    QrCode { rect: Bounds, url: Option<String> },
+
+    /// A PNG or SVG image, e.g. an agency or household logo, scaled to fit
+    /// and dithered down to `depth` bits of gray so it stays crisp on e-ink.
+    Logo {
+        rect: Bounds,
+        path: PathBuf,
+        #[serde(default = "default_logo_depth")]
+        depth: u8,
+        #[serde(default = "default_logo_dither")]
+        dither: Dither,
+    },
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct Bounds {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Bounds {
+    pub fn to_rect(self) -> Rect {
+        Rect::from_xywh(self.x, self.y, self.width, self.height)
+    }
+
+    fn scaled(self, sx: f32, sy: f32) -> Self {
+        Self {
+            x: self.x * sx,
+            y: self.y * sy,
+            width: self.width * sx,
+            height: self.height * sy,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Alignment {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl Alignment {
+    /// The x coordinate text should be anchored at to get this alignment
+    /// within `rect`.
+    pub fn anchor(self, rect: Rect) -> f32 {
+        match self {
+            Alignment::Left => rect.left,
+            Alignment::Center => rect.center_x(),
+            Alignment::Right => rect.right,
+        }
+    }
+}
+
+impl From<Alignment> for Align {
+    fn from(alignment: Alignment) -> Self {
+        match alignment {
+            Alignment::Left => Align::Left,
+            Alignment::Center => Align::Center,
+            Alignment::Right => Align::Right,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FontSpec {
+    pub family: String,
+    pub size: f32,
+}
+
+impl FontSpec {
+    fn scaled(&self, factor: f32) -> Self {
+        Self {
+            family: self.family.clone(),
+            size: self.size * factor,
+        }
+    }
+}
+
+impl Default for FontSpec {
+    fn default() -> Self {
+        Self {
+            family: "Arial".to_owned(),
+            size: 24.0,
+        }
+    }
+}
+
+fn default_fill_shade() -> f32 {
+    0.8
+}
+
+fn default_clock_align() -> Alignment {
+    Alignment::Center
+}
+
+fn default_date_format() -> String {
+    "%a %b %-d".to_owned()
+}
+
+fn default_footer_align() -> Alignment {
+    Alignment::Right
+}
+
+fn default_footer_source() -> String {
+    "511.org".to_owned()
+}
+
+/// Four grays: enough for a logo's shading without the muddy midtones of a
+/// straight downscale.
+fn default_logo_depth() -> u8 {
+    2
+}
+
+fn default_logo_dither() -> Dither {
+    Dither::FloydSteinberg
+}
+
+fn default_min_font_size() -> f32 {
+    14.0
+}
+
+fn default_row_height() -> f32 {
+    40.0
+}
+
+fn default_show_occupancy() -> bool {
+    true
+}
+
+fn default_upcoming_times() -> usize {
+    3
+}
+
+impl Layout {
+    /// Read a layout template, parsing it as JSON if the file has a `.json`
+    /// extension and as TOML otherwise.
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+
+        let layout = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&contents)?
+        } else {
+            toml::from_str(&contents)?
+        };
+        check(&layout)?;
+
+        Ok(layout)
+    }
+}
+
+/// Refuse panels whose settings can't be drawn, when the layout loads rather
+/// than on the first frame.
+fn check(layout: &Layout) -> eyre::Result<()> {
+    for panel in &layout.panels {
+        if let Panel::Map {
+            north,
+            south,
+            east,
+            west,
+            ..
+        } = panel
+        {
+            ensure!(
+                north > south,
+                "map north edge {north} must be above its south edge {south}"
+            );
+            ensure!(
+                east > west,
+                "map east edge {east} must be right of its west edge {west}"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+impl Layout {
+    /// Which arrivals each row of `panel` lists.
+    pub fn upcoming(&self, panel: &Panel) -> Upcoming {
+        let count = match panel {
+            Panel::Departures {
+                upcoming_times: Some(upcoming_times),
+                ..
+            } => *upcoming_times,
+            _ => self.upcoming_times,
+        };
+
+        Upcoming {
+            count,
+            min_minutes: self.min_minutes,
+            too_soon: self.too_soon,
+            show_arrival_time: self.show_arrival_time,
+            imminent_minutes: self.imminent_minutes,
+            show_delay: self.show_delay,
+            show_occupancy: self.show_occupancy,
+            show_progress: self.show_progress,
+        }
+    }
+
+    /// Stretch the layout to a `width` x `height` canvas. Positions and
+    /// spacing scale along their own axis, while fonts scale by the smaller
+    /// of the two factors so text never grows out of its panel or distorts.
+    pub fn scaled(&self, width: f32, height: f32) -> Self {
+        let sx = width / self.width;
+        let sy = height / self.height;
+        let font_scale = sx.min(sy);
+
+        Self {
+            width,
+            height,
+            font: self.font.scaled(font_scale),
+            upcoming_times: self.upcoming_times,
+            min_minutes: self.min_minutes,
+            too_soon: self.too_soon,
+            show_arrival_time: self.show_arrival_time,
+            imminent_minutes: self.imminent_minutes,
+            imminent_style: self.imminent_style,
+            show_delay: self.show_delay,
+            show_occupancy: self.show_occupancy,
+            show_progress: self.show_progress,
+            theme: self.theme.scaled(font_scale),
+            panels: self
+                .panels
+                .iter()
+                .map(|panel| panel.scaled(sx, sy, font_scale))
+                .collect(),
+        }
+    }
+}
+
+impl Panel {
+    /// The panel type, as written in layout templates.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Panel::Fill { .. } => "fill",
+            Panel::Line { .. } => "line",
+            Panel::Text { .. } => "text",
+            Panel::Departures { .. } => "departures",
+            Panel::Banner { .. } => "banner",
+            Panel::LeaveNow { .. } => "leave_now",
+            Panel::Map { .. } => "map",
+            Panel::Elevators { .. } => "elevators",
+            Panel::Weather { .. } => "weather",
+            Panel::Environment { .. } => "environment",
+            Panel::Bikes { .. } => "bikes",
+            Panel::Clock { .. } => "clock",
+            Panel::Date { .. } => "date",
+            Panel::Footer { .. } => "footer",
+            Panel::Logo { .. } => "logo",
+            Panel::QrCode { .. } => "qr_code",
+        }
+    }
+
+    /// The area the panel draws into, if it has one.
+    pub fn bounds(&self) -> Option<Bounds> {
+        match self {
+            Panel::Fill { rect, .. }
+            | Panel::Text { rect, .. }
+            | Panel::Departures { rect, .. }
+            | Panel::Banner { rect, .. }
+            | Panel::LeaveNow { rect, .. }
+            | Panel::Map { rect, .. }
+            | Panel::Elevators { rect, .. }
+            | Panel::Weather { rect, .. }
+            | Panel::Environment { rect, .. }
+            | Panel::Bikes { rect, .. }
+            | Panel::Clock { rect, .. }
+            | Panel::Date { rect, .. }
+            | Panel::Footer { rect, .. }
+            | Panel::Logo { rect, .. }
+            | Panel::QrCode { rect, .. } => Some(*rect),
+            Panel::Line { .. } => None,
+        }
+    }
+
+    fn scaled(&self, sx: f32, sy: f32, font_scale: f32) -> Self {
+        let scale_font = |font: &Option<FontSpec>| font.as_ref().map(|f| f.scaled(font_scale));
+
+        match self {
+            Panel::Fill { rect, shade } => Panel::Fill {
+                rect: rect.scaled(sx, sy),
+                shade: *shade,
+            },
+            Panel::Line { from, to } => Panel::Line {
+                from: (from.0 * sx, from.1 * sy),
+                to: (to.0 * sx, to.1 * sy),
+            },
+            Panel::Text {
+                rect,
+                text,
+                align,
+                font,
+                name,
+            } => Panel::Text {
+                rect: rect.scaled(sx, sy),
+                text: text.clone(),
+                align: *align,
+                font: scale_font(font),
+                name: name.clone(),
+            },
+            Panel::Departures {
+                rect,
+                direction,
+                font,
+                row_height,
+                bubble_shade,
+                min_font_size,
+                upcoming_times,
+                overflow,
+            } => Panel::Departures {
+                rect: rect.scaled(sx, sy),
+                direction: direction.clone(),
+                font: scale_font(font),
+                row_height: row_height * sy,
+                bubble_shade: *bubble_shade,
+                min_font_size: min_font_size * font_scale,
+                upcoming_times: *upcoming_times,
+                overflow: *overflow,
+            },
+            Panel::Banner { rect, font } => Panel::Banner {
+                rect: rect.scaled(sx, sy),
+                font: scale_font(font),
+            },
+            Panel::LeaveNow { rect, font } => Panel::LeaveNow {
+                rect: rect.scaled(sx, sy),
+                font: scale_font(font),
+            },
+            Panel::Map {
+                rect,
+                north,
+                south,
+                east,
+                west,
+                lines,
+            } => Panel::Map {
+                rect: rect.scaled(sx, sy),
+                north: *north,
+                south: *south,
+                east: *east,
+                west: *west,
+                lines: lines.clone(),
+            },
+            Panel::Elevators { rect, font } => Panel::Elevators {
+                rect: rect.scaled(sx, sy),
+                font: scale_font(font),
+            },
+            Panel::Weather { rect, font } => Panel::Weather {
+                rect: rect.scaled(sx, sy),
+                font: scale_font(font),
+            },
+            Panel::Environment { rect, font } => Panel::Environment {
+                rect: rect.scaled(sx, sy),
+                font: scale_font(font),
+            },
+            Panel::Bikes { rect, font } => Panel::Bikes {
+                rect: rect.scaled(sx, sy),
+                font: scale_font(font),
+            },
+            Panel::Clock {
+                rect,
+                align,
+                font,
+                twelve_hour,
+            } => Panel::Clock {
+                rect: rect.scaled(sx, sy),
+                align: *align,
+                font: scale_font(font),
+                twelve_hour: *twelve_hour,
+            },
+            Panel::Date {
+                rect,
+                align,
+                font,
+                format,
+            } => Panel::Date {
+                rect: rect.scaled(sx, sy),
+                align: *align,
+                font: scale_font(font),
+                format: format.clone(),
+            },
+            Panel::Footer {
+                rect,
+                align,
+                font,
+                source,
+            } => Panel::Footer {
+                rect: rect.scaled(sx, sy),
+                align: *align,
+                font: scale_font(font),
+                source: source.clone(),
+            },
+            Panel::Logo {
+                rect,
+                path,
+                depth,
+                dither,
+            } => Panel::Logo {
+                rect: rect.scaled(sx, sy),
+                path: path.clone(),
+                depth: *depth,
+                dither: *dither,
+            },
+            Panel::QrCode { rect, url } => Panel::QrCode {
+                rect: rect.scaled(sx, sy),
+                url: url.clone(),
+            },
+        }
+    }
+}
+
+impl Layout {
+    /// The built-in board for `orientation`.
+    pub fn builtin(orientation: Orientation) -> Self {
+        let template = match orientation {
+            Orientation::Landscape => LANDSCAPE_LAYOUT,
+            Orientation::Portrait => PORTRAIT_LAYOUT,
+        };
+
+        toml::from_str(template).expect("built-in layout is valid")
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        if self.height > self.width {
+            Orientation::Portrait
+        } else {
+            Orientation::Landscape
+        }
+    }
+}
+
+/// The layout to use for each orientation.
+#[derive(Debug, Clone)]
+pub struct Layouts {
+    pub landscape: Layout,
+    pub portrait: Layout,
+}
+
+impl Layouts {
+    /// Load the configured layout templates, using the built-in board for any
+    /// orientation without one, and apply the configured defaults on top.
+    pub fn load(config: &Config) -> eyre::Result<Self> {
+        Self::load_from(
+            config,
+            config.layout.as_ref(),
+            config.portrait_layout.as_ref(),
+        )
+    }
+
+    /// Load the layouts marked experimental in the config, for canary
+    /// rendering. Any orientation without an experimental layout uses the
+    /// regular one. `None` if no experimental layout is configured.
+    pub fn load_experimental(config: &Config) -> eyre::Result<Option<Self>> {
+        let experimental = &config.experimental;
+        if experimental.layout.is_none() && experimental.portrait_layout.is_none() {
+            return Ok(None);
+        }
+
+        let layouts = Self::load_from(
+            config,
+            experimental.layout.as_ref().or(config.layout.as_ref()),
+            experimental
+                .portrait_layout
+                .as_ref()
+                .or(config.portrait_layout.as_ref()),
+        )?;
+
+        Ok(Some(layouts))
+    }
+
+    /// Load the layouts for one of the configured displays, which fall back
+    /// to the regular ones.
+    pub fn load_display(config: &Config, display: &Display) -> eyre::Result<Self> {
+        Self::load_from(
+            config,
+            display.layout.as_ref().or(config.layout.as_ref()),
+            display
+                .portrait_layout
+                .as_ref()
+                .or(config.portrait_layout.as_ref()),
+        )
+    }
+
+    fn load_from(
+        config: &Config,
+        layout: Option<&PathBuf>,
+        portrait_layout: Option<&PathBuf>,
+    ) -> eyre::Result<Self> {
+        let load = |path: Option<&PathBuf>, orientation| match path {
+            Some(path) => Layout::load(path),
+            None => Ok(Layout::builtin(orientation)),
+        };
+
+        let mut layouts = Self {
+            landscape: load(layout, Orientation::Landscape)?,
+            portrait: load(portrait_layout, Orientation::Portrait)?,
+        };
+
+        for layout in [&mut layouts.landscape, &mut layouts.portrait] {
+            if let Some(family) = &config.font.family {
+                layout.font.family = family.clone();
+            }
+            if let Some(size) = config.font.size {
+                layout.font.size = size;
+            }
+            if let Some(upcoming_times) = config.upcoming_times {
+                layout.upcoming_times = upcoming_times;
+            }
+            if let Some(min_minutes) = config.min_minutes {
+                layout.min_minutes = min_minutes;
+            }
+            if let Some(too_soon) = config.too_soon {
+                layout.too_soon = too_soon;
+            }
+            if let Some(show_arrival_time) = config.show_arrival_time {
+                layout.show_arrival_time = show_arrival_time;
+            }
+            if let Some(imminent_minutes) = config.imminent_minutes {
+                layout.imminent_minutes = Some(imminent_minutes);
+            }
+            if let Some(imminent_style) = config.imminent_style {
+                layout.imminent_style = imminent_style;
+            }
+            if let Some(show_delay) = config.show_delay {
+                layout.show_delay = show_delay;
+            }
+            if let Some(show_occupancy) = config.show_occupancy {
+                layout.show_occupancy = show_occupancy;
+            }
+            if let Some(show_progress) = config.show_progress {
+                layout.show_progress = show_progress;
+            }
+            for panel in &mut layout.panels {
+                if let Panel::Text {
+                    text,
+                    align,
+                    name: Some(name),
+                    ..
+                } = panel
+                {
+                    let Some(title) = config.titles.get(name) else {
+                        continue;
+                    };
+                    if let Some(new_text) = &title.text {
+                        *text = new_text.clone();
+                    }
+                    if let Some(new_align) = title.align {
+                        *align = new_align;
+                    }
+                }
+                if let Panel::QrCode { url, .. } = panel {
+                    if url.is_none() {
+                        url.clone_from(&config.public_url);
+                    }
+                }
+            }
+        }
+
+        Ok(layouts)
+    }
+
+    pub fn get(&self, orientation: Orientation) -> &Layout {
+        match orientation {
+            Orientation::Landscape => &self.landscape,
+            Orientation::Portrait => &self.portrait,
+        }
+    }
+}
@@ -0,0 +1,33 @@
+pub mod alerts;
+pub mod annotations;
+pub mod api_keys;
+pub mod bikes;
+pub mod board;
+pub mod build_info;
+pub mod cache;
+pub mod commands;
+pub mod config;
+pub mod departures;
+pub mod devices;
+pub mod display;
+pub mod elevators;
+pub mod geocode;
+pub mod layout;
+pub mod lobby;
+pub mod maintenance;
+pub mod quirks;
+pub mod render;
+pub mod routes;
+pub mod scenarios;
+pub mod settings;
+pub mod shapes;
+pub mod siri;
+pub mod store;
+pub mod time;
+pub mod trips;
+pub mod updates;
+pub mod upstream;
+pub mod vehicles;
+pub mod watch;
+pub mod weather;
+pub mod webhook;
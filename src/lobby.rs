@@ -0,0 +1,89 @@
+use eyre::ensure;
+use serde::Deserialize;
+
+use crate::{
+    departures::{Grouping, Stop, WalkTimes},
+    render::MAX_IMAGE_DIMENSION,
+};
+
+/// How a lobby frame shows its boards.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LobbyMode {
+    /// Every board at once, one above the other.
+    #[default]
+    Stacked,
+    /// One board per refresh, taking turns.
+    Cycled,
+}
+
+/// One group of stops shown on the lobby view, e.g. a single corridor.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LobbyBoard {
+    /// Heading drawn above the board.
+    pub title: String,
+    pub stops: Vec<Stop>,
+    /// Replaces the top-level `grouping` for this board, e.g. to show only
+    /// the lines of one corridor.
+    pub grouping: Option<Grouping>,
+}
+
+impl LobbyBoard {
+    pub fn stop_codes(&self) -> Vec<&str> {
+        self.stops.iter().map(|stop| stop.code.as_str()).collect()
+    }
+
+    pub fn walk_times(&self) -> WalkTimes {
+        WalkTimes::new(&self.stops)
+    }
+}
+
+/// Several boards drawn into one large frame, for lobby TVs and big e-ink
+/// signs covering more than one corridor. Served at `/lobby.png`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LobbyConfig {
+    pub mode: LobbyMode,
+    pub width: f32,
+    pub height: f32,
+    pub boards: Vec<LobbyBoard>,
+}
+
+impl Default for LobbyConfig {
+    fn default() -> Self {
+        Self {
+            mode: LobbyMode::default(),
+            width: 1600.0,
+            height: 1200.0,
+            boards: Vec::new(),
+        }
+    }
+}
+
+impl LobbyConfig {
+    /// Refuse a frame size that can't be drawn, before a request tries to.
+    pub fn check(&self) -> eyre::Result<()> {
+        let max = MAX_IMAGE_DIMENSION as f32;
+        for (field, dimension) in [("width", self.width), ("height", self.height)] {
+            ensure!(
+                (1.0..=max).contains(&dimension),
+                "lobby {field} must be between 1 and {MAX_IMAGE_DIMENSION}, not {dimension}"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// The boards to draw on refresh number `refresh`.
+    pub fn showing(&self, refresh: usize) -> &[LobbyBoard] {
+        match self.mode {
+            LobbyMode::Stacked => &self.boards,
+            LobbyMode::Cycled if self.boards.is_empty() => &[],
+            LobbyMode::Cycled => {
+                let i = refresh % self.boards.len();
+                &self.boards[i..=i]
+            }
+        }
+    }
+}
@@ -1,257 +1,2410 @@
-use std::collections::HashMap;
+use std::{
+    collections::BTreeMap,
+    io,
+    net::SocketAddr,
+    path::PathBuf,
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
 
 use eyre::{ensure, eyre};
 
-use chrono::prelude::*;
+use chrono::{DateTime, Utc};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
 use reqwest::Client;
-use serde::Deserialize;
-use skia_safe::{
-    utils::text_utils::Align, AlphaType, Bitmap, Canvas, Color4f, ColorType, Font, FontMgr,
-    FontStyle, ImageInfo, Paint, Rect,
-};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use axum::{
     body::{Body, Bytes},
-    http::StatusCode,
-    response::Response,
-    routing::get,
-    Router,
+    extract::{
+        rejection::JsonRejection,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, Form, Path, Query, State,
+    },
+    http::{header, HeaderMap, HeaderName, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, IntoResponse, Response,
+    },
+    routing::{get, post},
+    Json, Router,
 };
-use tokio::net::TcpListener;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use futures_util::Stream;
+use tokio::{net::TcpListener, sync::Semaphore};
+use tower_http::timeout::TimeoutLayer;
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "PascalCase")]
-struct StopMonitoringResponse {
-    service_delivery: ServiceDelivery,
-}
+use transit_kindle_playground::{
+    alerts::{self, AlertsCache},
+    annotations::{Annotation, AnnotationStore, NewAnnotation},
+    bikes::BikeShare,
+    board::Board,
+    build_info::BuildInfo,
+    commands::{self, Status},
+    config::Config,
+    departures::{self, Grouping, Predictions, Stop, WalkTimes},
+    devices::{DeviceRegistry, DeviceSummary},
+    display::{self, Display, Invalid},
+    elevators::ElevatorOutages,
+    geocode::{NearbyStop, Place, StopSearch},
+    layout::{Layout, Layouts, Orientation},
+    lobby::LobbyConfig,
+    maintenance::{self, Maintenance},
+    render::{
+        self,
+        device::{Device, DEVICES},
+        frame::{Dither, Rotation},
+        Format, Output, Theme, MAX_IMAGE_DIMENSION,
+    },
+    routes::Routes,
+    scenarios::Scenario,
+    settings::{Settings, SettingsForm},
+    shapes::Shapes,
+    siri::StopMonitoringResponse,
+    store::Store,
+    time,
+    trips::Trips,
+    updates::{Update, Updates},
+    upstream::{Tape, Upstream, DEFAULT_AGENCY},
+    vehicles::{self, VehicleTracker},
+    watch,
+    weather::WeatherSource,
+    webhook::FailureWebhook,
+};
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "PascalCase")]
-struct ServiceDelivery {
-    stop_monitoring_delivery: StopMonitoringDelivery,
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    /// Print results of one-shot commands as JSON
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Save every raw 511 API response into this directory
+    #[arg(long, global = true, value_name = "DIR", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Answer 511 API requests from responses saved with --record instead
+    /// of calling the API
+    #[arg(long, global = true, value_name = "DIR")]
+    replay: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "PascalCase")]
-struct StopMonitoringDelivery {
-    monitored_stop_visit: Vec<MonitoredStopVisit>,
+impl Cli {
+    fn tape(&self) -> Option<Tape> {
+        match (&self.record, &self.replay) {
+            (Some(dir), _) => Some(Tape::Record(dir.clone())),
+            (None, Some(dir)) => Some(Tape::Replay(dir.clone())),
+            (None, None) => None,
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "PascalCase")]
-struct MonitoredStopVisit {
-    monitored_vehicle_journey: MonitoredVehicleJourney,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Serve board images over HTTP (the default)
+    Serve,
+
+    /// Show a live-updating board in the terminal
+    Watch {
+        /// Seconds between fetches from the upstream API
+        #[arg(long, default_value_t = 30)]
+        interval: u64,
+    },
+
+    /// Fetch departures once and write the board to a file
+    Render {
+        /// Where to write the PNG
+        #[arg(long, short, default_value = "stops.png")]
+        output: PathBuf,
+
+        /// Board orientation, overriding the config
+        #[arg(long, value_enum)]
+        orientation: Option<Orientation>,
+    },
+
+    /// Check that the config file and layout templates load
+    CheckConfig,
+
+    /// Check config, rendering, and the upstream API
+    Selftest,
+
+    /// Check a config file against the 511 API and print the board's
+    /// sections, without starting the server
+    Validate {
+        /// The config file to check
+        #[arg(long, default_value = "config.toml")]
+        config: PathBuf,
+    },
+
+    /// Print a shell completion script to stdout
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+
+    /// Print a man page to stdout
+    Man,
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "PascalCase")]
-struct MonitoredVehicleJourney {
-    line_ref: Option<String>,
-    direction_ref: Option<String>,
-    destination_name: Option<String>,
-    monitored_call: MonitoredCall,
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    tracing_subscriber::fmt()
+        .with_writer(io::stderr)
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+
+    let tape = cli.tape();
+    let report = match cli.command.unwrap_or(Command::Serve) {
+        Command::Render {
+            output,
+            orientation,
+        } => commands::render(&output, orientation, tape).await,
+        Command::CheckConfig => commands::check_config(),
+        Command::Selftest => commands::selftest(tape).await,
+        Command::Validate { config } => commands::validate(&config, tape).await,
+        command => {
+            return match run(command, tape).await {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("Error: {e:?}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+    };
+
+    if cli.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("report serializes")
+        );
+    } else {
+        for check in &report.checks {
+            let label = if check.status == Status::Ok {
+                "ok"
+            } else {
+                "FAIL"
+            };
+            eprintln!("{label:<4} {:<8} {}", check.name, check.detail);
+        }
+    }
+
+    ExitCode::from(report.exit_code)
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "PascalCase")]
-struct MonitoredCall {
-    expected_arrival_time: Option<String>,
-    stop_point_ref: String,
-    destination_display: Option<String>,
+/// Run a long-lived or output-generating command.
+async fn run(command: Command, tape: Option<Tape>) -> eyre::Result<()> {
+    match command {
+        Command::Completions { shell } => {
+            let mut cli = Cli::command();
+            let name = cli.get_name().to_owned();
+            clap_complete::generate(shell, &mut cli, name, &mut io::stdout());
+            return Ok(());
+        }
+        Command::Man => {
+            clap_mangen::Man::new(Cli::command()).render(&mut io::stdout())?;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let config = Config::load()?;
+
+    match command {
+        Command::Watch { interval } => {
+            let layouts = Layouts::load(&config)?;
+            let layout = layouts.get(config.orientation);
+            let upstream = Upstream::new(&config).with_tape(tape);
+            watch::run(&config, layout, &upstream, Duration::from_secs(interval)).await
+        }
+        _ => serve(config, tape).await,
+    }
 }
 
-#[tokio::main]
-async fn main() -> eyre::Result<()> {
-    let app = Router::new().route("/stops.png", get(handle_stops_png));
+async fn serve(config: Config, tape: Option<Tape>) -> eyre::Result<()> {
+    let store = config
+        .database
+        .as_ref()
+        .map(Store::open)
+        .transpose()?
+        .map(Arc::new);
+    let request_timeout = Duration::from_secs(config.request_timeout_secs);
+    let state = Arc::new(AppState {
+        http: Client::builder()
+            .timeout(Duration::from_secs(config.upstream_timeout_secs))
+            .build()?,
+        upstream: Upstream::new(&config).with_tape(tape),
+        annotations: AnnotationStore::default(),
+        alerts: AlertsCache::new(config.alerts.clone()),
+        elevators: ElevatorOutages::new(config.elevators.clone()),
+        weather: WeatherSource::new(config.weather.clone(), config.timezone),
+        bikes: BikeShare::new(config.bikes.clone()),
+        vehicles: VehicleTracker::new(config.vehicles.clone()),
+        predictions: Predictions::default(),
+        maintenance: Maintenance::default(),
+        devices: DeviceRegistry::default(),
+        webhook: FailureWebhook::new(config.failure_webhook.clone()),
+        stop_search: StopSearch::new(&config.geocoding)?,
+        errors: Mutex::default(),
+        last_fetch: Mutex::default(),
+        updates: Updates::default(),
+        refreshes: AtomicUsize::new(0),
+        lobby_refreshes: AtomicUsize::new(0),
+        last_frames: Mutex::default(),
+        last_visits: Mutex::default(),
+        renders: Arc::new(Semaphore::new(config.max_concurrent_renders.max(1))),
+        loaded: RwLock::new(Arc::new(Loaded::new(config, store.as_deref())?)),
+        store,
+    });
+    tokio::spawn(watch_config(state.clone()));
+    tokio::spawn(poll_departures(state.clone()));
+
+    // Live streams stay open for as long as the client wants, so only the
+    // other routes are held to the deadline.
+    let streams = Router::new()
+        .route("/events", get(handle_events))
+        .route("/ws", get(handle_ws));
+    let app = Router::new()
+        .route("/", get(handle_index))
+        .route("/stops.png", get(handle_stops_png))
+        .route("/stops.svg", get(handle_stops_svg))
+        .route("/stops.raw", get(handle_stops_raw))
+        .route("/stops.bmp", get(handle_stops_bmp))
+        .route("/views/:name/canary.png", get(handle_canary_png))
+        .route("/views/:name/last.png", get(handle_last_png))
+        .route("/lobby.png", get(handle_lobby_png))
+        .route("/preview.png", get(handle_preview_png))
+        .route("/displays/:file", get(handle_display))
+        .route("/stops.json", get(handle_stops_json))
+        .route("/timetable.pdf", get(handle_timetable_pdf))
+        .route("/api/version", get(handle_version))
+        .route("/api/status", get(handle_status))
+        .route("/api/devices", get(handle_devices))
+        .route("/api/stops/search", get(handle_stop_search))
+        .route("/api/displays", get(handle_list_displays))
+        .route(
+            "/api/displays/:name",
+            get(handle_get_display)
+                .put(handle_put_display)
+                .delete(handle_delete_display),
+        )
+        .route("/metrics", get(handle_metrics))
+        .route("/api/annotations", post(handle_post_annotation))
+        .route("/admin", get(handle_admin).post(handle_save_settings))
+        .route("/admin/preview.png", get(handle_admin_preview))
+        .route(
+            "/admin/maintenance",
+            post(handle_start_maintenance).delete(handle_end_maintenance),
+        )
+        .route("/reload", post(handle_reload))
+        .layer(TimeoutLayer::new(request_timeout))
+        .merge(streams)
+        .with_state(state);
 
     let listener = TcpListener::bind(&"0.0.0.0:3001").await?;
 
-    eprintln!("Visit http://localhost:3001/stops.png");
+    eprintln!("Visit http://localhost:3001/");
 
-    axum::serve(listener, app.into_make_service()).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
 
-async fn handle_stops_png() -> Response<Body> {
-    Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "image/png")
-        .body(Body::from(Bytes::from(get_image().await.unwrap())))
-        .unwrap()
+/// What's read from the config file, replaced as a whole when it changes.
+struct Loaded {
+    config: Config,
+    layouts: Layouts,
+    /// The layouts marked experimental in the config, if any.
+    experimental_layouts: Option<Layouts>,
+    /// Each configured display's layouts, by name.
+    display_layouts: BTreeMap<String, Layouts>,
+    /// Route colors and modes from the configured GTFS routes.
+    routes: Routes,
+    /// Route paths from the configured GTFS shapes.
+    shapes: Arc<Shapes>,
+    /// Trip directions from the configured GTFS trips.
+    trips: Arc<Trips>,
 }
 
-async fn get_image() -> eyre::Result<Vec<u8>> {
-    let client = Client::new();
+impl Loaded {
+    /// `config` with the layouts, routes, shapes and trips it names. Displays in `store`
+    /// replace any of the same name in the file.
+    fn new(mut config: Config, store: Option<&Store>) -> eyre::Result<Self> {
+        if let Some(store) = store {
+            config.displays.extend(store.displays()?);
+        }
+        let display_layouts = config
+            .displays
+            .iter()
+            .map(|(name, display)| {
+                let layouts = Layouts::load_display(&config, display)
+                    .map_err(|e| eyre!("display {name:?}: {e:#}"))?;
+                Ok((name.clone(), layouts))
+            })
+            .collect::<eyre::Result<_>>()?;
+        if let Some(lobby) = &config.lobby {
+            lobby.check()?;
+        }
 
-    let response_txt = client
-        .get("http://api.511.org/transit/StopMonitoring?api_key=[your_key]&agency=SF")
-        .send()
-        .await?
-        .text()
-        .await?;
+        Ok(Self {
+            layouts: Layouts::load(&config)?,
+            experimental_layouts: Layouts::load_experimental(&config)?,
+            display_layouts,
+            routes: config.routes()?,
+            shapes: Arc::new(config.shapes()?),
+            trips: Arc::new(config.trips()?),
+            config,
+        })
+    }
 
-    let response: StopMonitoringResponse = serde_json::from_str(&response_txt)?;
+    /// The board described by the top-level config, at `/stops.png`.
+    fn default_view(&self) -> View<'_> {
+        View {
+            name: "default",
+            config: &self.config,
+            stops: &self.config.stops,
+            grouping: &self.config.grouping,
+            layouts: &self.layouts,
+        }
+    }
+}
 
-    let mut journeys_i_care_about = Vec::new();
+struct AppState {
+    /// The config file and what it names. The data sources below keep the
+    /// settings they started with until the server restarts.
+    loaded: RwLock<Arc<Loaded>>,
+    /// Shared by every outbound request, so connections to 511 and the other
+    /// feeds are pooled and kept alive between refreshes.
+    http: Client,
+    upstream: Upstream,
+    annotations: AnnotationStore,
+    alerts: AlertsCache,
+    elevators: ElevatorOutages,
+    weather: WeatherSource,
+    bikes: BikeShare,
+    vehicles: VehicleTracker,
+    /// Recent predictions for each trip, to spot ones that jump around.
+    predictions: Predictions,
+    maintenance: Maintenance,
+    devices: DeviceRegistry,
+    webhook: FailureWebhook,
+    stop_search: StopSearch,
+    /// Data sources that failed while fetching the most recent board.
+    errors: Mutex<BTreeMap<String, String>>,
+    /// When the most recent board was fetched.
+    last_fetch: Mutex<Option<DateTime<Utc>>>,
+    /// Live updates for `/events` subscribers.
+    updates: Updates,
+    /// Boards drawn so far, used to cycle through pages of overflowing panels.
+    refreshes: AtomicUsize,
+    /// Lobby frames drawn so far, which pick the board shown in cycled mode.
+    /// Kept apart from `refreshes` so other views don't skip boards.
+    lobby_refreshes: AtomicUsize,
+    /// The last PNG drawn for each view, shown on the dashboard so that
+    /// looking at it costs no API quota.
+    last_frames: Mutex<BTreeMap<String, Bytes>>,
+    /// The stop visits fetched most recently, which admin previews are drawn
+    /// from so that editing the settings costs no API quota.
+    last_visits: Mutex<Option<StopMonitoringResponse>>,
+    /// Permits to draw a frame, bounding how many renders run at once.
+    renders: Arc<Semaphore>,
+    /// Displays added through the API and the fetch history, if a database
+    /// is configured.
+    store: Option<Arc<Store>>,
+}
+
+impl AppState {
+    fn loaded(&self) -> Arc<Loaded> {
+        self.loaded.read().unwrap().clone()
+    }
 
-    for stop_visit in response
-        .service_delivery
-        .stop_monitoring_delivery
-        .monitored_stop_visit
-    {
-        let stop = &stop_visit
-            .monitored_vehicle_journey
-            .monitored_call
-            .stop_point_ref;
-        if ["15419", "16996", "15692", "15696"].contains(&stop.as_ref()) {
-            journeys_i_care_about.push(stop_visit.monitored_vehicle_journey);
+    /// Keep `image` as the dashboard's thumbnail of `view`, if browsers can
+    /// show it.
+    fn remember_frame(&self, view: &str, format: Format, image: &[u8]) {
+        if format == Format::Png {
+            self.last_frames
+                .lock()
+                .unwrap()
+                .insert(view.to_owned(), Bytes::copy_from_slice(image));
         }
     }
 
-    let mut directions_to_lines_destinations_to_journeys = HashMap::new();
-    for journey in journeys_i_care_about {
-        let Some(line) = journey.line_ref.clone() else {
+    /// Read the config file again, keeping the current config if the new one
+    /// doesn't load.
+    fn reload(&self) -> eyre::Result<()> {
+        let loaded = Loaded::new(Config::load()?, self.store.as_deref())?;
+        *self.loaded.write().unwrap() = Arc::new(loaded);
+
+        Ok(())
+    }
+}
+
+/// How often the config file is checked for changes.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Reload the config whenever the file's modification time changes.
+async fn watch_config(state: Arc<AppState>) {
+    let path = Config::path();
+    let modified = || {
+        std::fs::metadata(&path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    };
+
+    let mut last_modified = modified();
+    let mut interval = tokio::time::interval(CONFIG_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let now_modified = modified();
+        if now_modified == last_modified {
             continue;
-        };
-        let Some(direction) = journey.direction_ref.clone() else {
+        }
+        last_modified = now_modified;
+
+        match state.reload() {
+            Ok(()) => info!("reloaded {}", path.display()),
+            Err(e) => warn!(
+                "couldn't reload {}, keeping the old config: {e:#}",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// How often departures are fetched for `/events` and `/ws` subscribers
+/// when no device is asking for frames.
+const LIVE_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Publish the default board's departures every [`LIVE_POLL_INTERVAL`] while
+/// anyone is subscribed, so live clients keep updating between image
+/// requests. With nobody listening, or during maintenance and quiet hours,
+/// nothing is fetched.
+async fn poll_departures(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(LIVE_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let loaded = state.loaded();
+        let now = Utc::now();
+        if !state.updates.has_subscribers()
+            || state.maintenance.active(now).is_some()
+            || loaded.config.is_quiet(now)
+        {
             continue;
+        }
+
+        let visits = match departures::fetch_visits(
+            &state.http,
+            &state.upstream,
+            &loaded.config.stops,
+        )
+        .await
+        {
+            Ok(visits) => visits,
+            Err(e) => {
+                warn!("couldn't fetch departures for live clients: {e:#}");
+                continue;
+            }
         };
-        let Some(destination) = journey.monitored_call.destination_display.clone() else {
-            continue;
+        *state.last_visits.lock().unwrap() = Some(visits.clone());
+
+        let mut departures = departures::group(
+            visits,
+            &loaded.config.stop_codes(),
+            &loaded.config.grouping,
+            &loaded.trips,
+        );
+        state.predictions.track(&mut departures, Utc::now());
+        state.updates.publish(Update::Departures {
+            departures: departures::list(&departures, Utc::now(), &loaded.config.walk_times()),
+        });
+    }
+}
+
+/// What one view of the board shows and how it's laid out.
+struct View<'a> {
+    /// Names the view in updates and failure webhooks.
+    name: &'a str,
+    config: &'a Config,
+    stops: &'a [Stop],
+    grouping: &'a Grouping,
+    layouts: &'a Layouts,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct ImageParams {
+    /// Names the device for its refresh statistics, which otherwise go by
+    /// client IP.
+    id: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    device: Option<String>,
+    orientation: Option<Orientation>,
+    rotate: Option<u16>,
+    depth: Option<u8>,
+    dither: Option<Dither>,
+    theme: Option<Theme>,
+    format: Option<Format>,
+}
+
+impl ImageParams {
+    /// The `format` asked for, or else whichever of PNG, WebP and JPEG the
+    /// `Accept` header prefers. Each takes the q-value of the most specific
+    /// range covering it, so `image/*` and `*/*` count too, and `q=0` refuses
+    /// it. Ties go to the range listed first, then to PNG. PNG when nothing
+    /// acceptable is listed, which is also what e-readers fetching with `*/*`
+    /// get.
+    fn format(&self, headers: &HeaderMap) -> Format {
+        if let Some(format) = self.format {
+            return format;
+        }
+
+        // (media range, q-value) in the order listed.
+        let ranges: Vec<(&str, f32)> = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|range| {
+                let mut parts = range.split(';');
+                let media_range = parts.next().unwrap_or_default().trim();
+                let quality = parts
+                    .filter_map(|param| {
+                        let (name, value) = param.split_once('=')?;
+                        name.trim().eq_ignore_ascii_case("q").then_some(value)
+                    })
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                (media_range, quality)
+            })
+            .collect();
+
+        // The q-value `format` gets, and the position of the range giving it.
+        let preference = |format: Format| {
+            [format.content_type(), "image/*", "*/*"]
+                .iter()
+                .find_map(|wanted| {
+                    ranges
+                        .iter()
+                        .position(|(range, _)| range.eq_ignore_ascii_case(wanted))
+                })
+                .map(|position| (ranges[position].1, position))
         };
 
-        directions_to_lines_destinations_to_journeys
-            .entry(direction)
-            .or_insert(HashMap::new())
-            .entry((line, destination))
-            .or_insert(Vec::new())
-            .push(journey);
+        let mut best: Option<(Format, f32, usize)> = None;
+        for format in [Format::Png, Format::Webp, Format::Jpeg] {
+            let Some((quality, position)) = preference(format) else {
+                continue;
+            };
+            let better = best.is_none_or(|(_, best_quality, best_position)| {
+                quality > best_quality || (quality == best_quality && position < best_position)
+            });
+            if quality > 0.0 && better {
+                best = Some((format, quality, position));
+            }
+        }
+
+        best.map_or(Format::Png, |(format, _, _)| format)
     }
 
-    for lines_destinations_to_journeys in directions_to_lines_destinations_to_journeys.values_mut()
-    {
-        for journeys in lines_destinations_to_journeys.values_mut() {
-            journeys.sort_by_key(|j| j.monitored_call.expected_arrival_time.clone());
+    /// These parameters, with the display's device, size and orientation
+    /// filling in for any the request left out. A request giving any of the
+    /// device or size replaces all three.
+    fn or_display(mut self, display: &Display) -> Self {
+        if self.device.is_none() && self.width.is_none() && self.height.is_none() {
+            self.device.clone_from(&display.device);
+            self.width = display.width;
+            self.height = display.height;
         }
+        self.orientation = self.orientation.or(display.orientation);
+        self
     }
 
-    let png_bytes = draw_image(directions_to_lines_destinations_to_journeys)?;
+    /// The layout resized for this request and how to post-process it.
+    ///
+    /// A `device` preset supplies the size and output settings for that
+    /// e-reader, though an explicit `width` or `height` still wins. If only one
+    /// dimension is known the other follows the layout's aspect ratio. When the
+    /// requested pixels are the other orientation from the board, the board is
+    /// drawn sideways and rotated into place. Any explicit `rotate` is applied
+    /// on top of that.
+    fn apply(&self, config: &Config, layouts: &Layouts) -> eyre::Result<(Layout, Output)> {
+        for dimension in [self.width, self.height].into_iter().flatten() {
+            ensure!(
+                (1..=MAX_IMAGE_DIMENSION).contains(&dimension),
+                "image dimensions must be between 1 and {MAX_IMAGE_DIMENSION}"
+            );
+        }
+
+        let orientation = self.orientation.unwrap_or(config.orientation);
+        let layout = layouts.get(orientation);
+
+        let mut output = Output::from_config(config);
+        let mut sideways_rotation = Rotation::Clockwise90;
+        let (mut width, mut height) = (self.width, self.height);
+
+        if let Some(name) = &self.device {
+            let device = Device::find(name).ok_or_else(|| {
+                let known: Vec<_> = DEVICES.iter().map(|device| device.name).collect();
+                eyre!(
+                    "unknown device {name:?}, expected one of {}",
+                    known.join(", ")
+                )
+            })?;
 
-    Ok(png_bytes)
+            output.gray_depth = device.gray_depth;
+            output.dpi = Some(device.dpi);
+            sideways_rotation = device.rotation;
+
+            if width.is_none() && height.is_none() {
+                width = Some(device.width);
+                height = Some(device.height);
+            }
+        }
+
+        let aspect = layout.width / layout.height;
+        let (mut width, mut height) = match (width, height) {
+            (None, None) => (layout.width, layout.height),
+            (Some(width), Some(height)) => (width as f32, height as f32),
+            (Some(width), None) => (width as f32, (width as f32 / aspect).round()),
+            (None, Some(height)) => ((height as f32 * aspect).round(), height as f32),
+        };
+
+        if (height > width) != (layout.orientation() == Orientation::Portrait) {
+            output.rotation = sideways_rotation;
+            (width, height) = (height, width);
+        }
+
+        if let Some(depth) = self.depth {
+            ensure!(
+                (1..=8).contains(&depth),
+                "depth must be between 1 and 8 bits"
+            );
+            output.gray_depth = depth;
+        }
+        output.dither = self.dither;
+        if let Some(theme) = self.theme {
+            output.tone.theme = theme;
+        }
+
+        if let Some(degrees) = self.rotate {
+            let rotation = Rotation::from_degrees(degrees)
+                .ok_or_else(|| eyre!("rotate must be one of 0, 90, 180, or 270"))?;
+            output.rotation = output.rotation.then(rotation);
+        }
+
+        Ok((layout.scaled(width, height), output))
+    }
 }
 
-fn text_bounds(text: &str, (x, y): (f32, f32), font: &Font, paint: &Paint) -> Rect {
-    let (text_width, text_measurements) = font.measure_str(text, Some(paint));
-    Rect::new(x, y + text_measurements.top, x + text_width, y)
+/// A page listing every view the server can draw, with a thumbnail of each
+/// and links to its variants, so a new device can find its URL. It also shows
+/// how the last fetch went and the settings in effect, and reloads itself
+/// every minute so it can be left open while adjusting a layout.
+async fn handle_index(State(state): State<Arc<AppState>>) -> Html<String> {
+    let loaded = state.loaded();
+    let mut views = vec![("default", "default", "/stops.png")];
+    if loaded.experimental_layouts.is_some() {
+        views.push(("default (canary)", "canary", "/views/default/canary.png"));
+    }
+    if loaded.config.lobby.is_some() {
+        views.push(("lobby", "lobby", "/lobby.png"));
+    }
+    let displays: Vec<_> = loaded
+        .config
+        .displays
+        .keys()
+        .map(|name| (name.as_str(), format!("/displays/{name}.png")))
+        .collect();
+    views.extend(
+        displays
+            .iter()
+            .map(|(name, path)| (*name, *name, path.as_str())),
+    );
+
+    // Thumbnails are the frames devices last fetched, so an open dashboard
+    // doesn't spend API quota.
+    let mut body = String::new();
+    for (name, view, path) in views {
+        let devices: Vec<_> = DEVICES
+            .iter()
+            .map(|device| format!(r#"<a href="{path}?device={0}">{0}</a>"#, device.name))
+            .collect();
+
+        body.push_str(&format!(
+            r#"<section>
+<h2>{name}</h2>
+<a href="{path}"><img src="/views/{view}/last.png" width="320" alt="{name} board, not fetched yet"></a>
+<p><a href="{path}">PNG</a> · portrait: <a href="{path}?orientation=portrait">PNG</a> · devices: {}</p>
+</section>
+"#,
+            devices.join(", ")
+        ));
+    }
+
+    body.push_str(&status_section(&state));
+    body.push_str(&config_section(&loaded.config));
+    body.push_str(&devices_section(&state.devices.summaries()));
+
+    Html(format!(
+        r#"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width">
+<meta http-equiv="refresh" content="60">
+<title>Transit board</title>
+</head>
+<body>
+<h1>Transit board</h1>
+{body}<p><a href="/api/status">Status</a> · <a href="/api/version">Version</a> · <a href="/metrics">Metrics</a></p>
+</body>
+</html>
+"#
+    ))
 }
 
-fn draw_image(
-    directions_to_lines_destinations_to_journeys: HashMap<
-        String,
-        HashMap<(String, String), Vec<MonitoredVehicleJourney>>,
-    >,
-) -> eyre::Result<Vec<u8>> {
-    let mut bitmap = Bitmap::new();
-    ensure!(bitmap.set_info(
-        &ImageInfo::new((1024, 758), ColorType::Gray8, AlphaType::Unknown, None),
-        None
-    ));
-    bitmap.alloc_pixels();
-
-    let canvas = Canvas::from_bitmap(&bitmap, None).ok_or(eyre!("skia canvas"))?;
-
-    canvas.clear(Color4f::new(1.0, 1.0, 1.0, 1.0));
-
-    let font_manager = FontMgr::new();
-    let typeface = font_manager
-        .match_family_style("Arial", FontStyle::normal())
-        .unwrap();
-    let font = Font::new(typeface, 24.0);
-
-    let black_paint = Paint::new(Color4f::new(0.0, 0.0, 0.0, 1.0), None);
-    let line_id_bubble_paint = Paint::new(Color4f::new(0.8, 0.8, 0.8, 1.0), None);
-
-    let inbound_journeys = &directions_to_lines_destinations_to_journeys["IB"];
-    let outbound_journeys = &directions_to_lines_destinations_to_journeys["OB"];
-
-    let draw_times = |lines_destinations_to_journeys: &HashMap<
-        (String, String),
-        Vec<MonitoredVehicleJourney>,
-    >,
-                      x1: f32,
-                      x2: f32| {
-        let mut y = 60.0;
-        for ((line_id, destination), journeys) in lines_destinations_to_journeys {
-            let bounds = text_bounds(
-                line_id,
-                (x1 as f32 + 20.0, y as f32),
-                &font,
-                &line_id_bubble_paint,
+/// The PNG last drawn for a view, without fetching or drawing anything.
+async fn handle_last_png(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Response<Body> {
+    match state.last_frames.lock().unwrap().get(&name) {
+        Some(image) => Response::builder()
+            .header("Content-Type", Format::Png.content_type())
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(Body::from(image.clone()))
+            .unwrap(),
+        None => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from(format!("{name:?} hasn't been drawn yet")))
+            .unwrap(),
+    }
+}
+
+/// How the most recent fetch went, and whether fetching is paused.
+fn status_section(state: &AppState) -> String {
+    let now = Utc::now();
+    let mut items = String::new();
+
+    if let Some(window) = state.maintenance.active(now) {
+        let until = window
+            .until
+            .map(|until| format!(" until {}", until.format("%Y-%m-%d %H:%M UTC")))
+            .unwrap_or_default();
+        items.push_str(&format!("<li>Under maintenance{until}</li>\n"));
+    }
+    if state.loaded().config.is_quiet(now) {
+        items.push_str("<li>Quiet hours: nothing is being fetched</li>\n");
+    }
+
+    match *state.last_fetch.lock().unwrap() {
+        Some(fetched) => items.push_str(&format!(
+            "<li>Last fetched {}</li>\n",
+            fetched.format("%Y-%m-%d %H:%M:%S UTC")
+        )),
+        None => items.push_str("<li>Nothing fetched yet</li>\n"),
+    }
+    let errors = state.errors.lock().unwrap();
+    if errors.is_empty() {
+        items.push_str("<li>Every source fetched</li>\n");
+    }
+    for (source, error) in errors.iter() {
+        items.push_str(&format!(
+            "<li>{} failed: {}</li>\n",
+            escape_html(source),
+            escape_html(error)
+        ));
+    }
+
+    format!(
+        r#"<section>
+<h2>Status</h2>
+<ul>
+{items}</ul>
+</section>
+"#
+    )
+}
+
+/// The settings that decide what's on the board. API keys and tokens are
+/// left out.
+fn config_section(config: &Config) -> String {
+    let stops: Vec<_> = config
+        .stops
+        .iter()
+        .map(|stop| match stop.walk_minutes {
+            0 => escape_html(&stop.code),
+            walk => format!("{} ({walk} min walk)", escape_html(&stop.code)),
+        })
+        .collect();
+    let quiet_hours = config
+        .quiet_hours
+        .as_ref()
+        .map(|quiet| {
+            format!(
+                "{}–{}",
+                quiet.start.format("%H:%M"),
+                quiet.end.format("%H:%M")
             )
-            .with_outset((8.0, 8.0));
-            canvas.draw_round_rect(bounds, 24.0, 24.0, &line_id_bubble_paint);
-            canvas.draw_str(line_id, (x1 + 20.0, y), &font, &black_paint);
-            canvas.draw_str(destination, (bounds.right + 15.0, y), &font, &black_paint);
-
-            let mut times_str = String::new();
-            for journey in &journeys[..journeys.len().min(3)] {
-                let Some(time_str) = &journey.monitored_call.expected_arrival_time else {
-                    continue;
-                };
+        })
+        .unwrap_or_else(|| "off".to_owned());
 
-                let Ok(time) = time_str.parse::<DateTime<Utc>>() else {
-                    continue;
-                };
+    let rows = [
+        ("Stops", stops.join(", ")),
+        ("Timezone", config.timezone.to_string()),
+        (
+            "Orientation",
+            format!("{:?}", config.orientation).to_lowercase(),
+        ),
+        ("Theme", format!("{:?}", config.theme).to_lowercase()),
+        ("Quiet hours", quiet_hours),
+        ("API keys", config.api_keys.len().to_string()),
+        (
+            "Config hash",
+            config.hash.clone().unwrap_or_else(|| "defaults".to_owned()),
+        ),
+    ];
+    let rows: String = rows
+        .iter()
+        .map(|(name, value)| format!("<tr><th>{name}</th><td>{value}</td></tr>\n"))
+        .collect();
 
-                if time < Utc::now() {
-                    continue;
-                }
+    format!(
+        r#"<section>
+<h2>Config</h2>
+<table>
+{rows}</table>
+</section>
+"#
+    )
+}
+
+/// A table of each device's polling, with a bar per day of frames served.
+fn devices_section(devices: &[DeviceSummary]) -> String {
+    if devices.is_empty() {
+        return String::new();
+    }
+
+    let most_frames = devices
+        .iter()
+        .flat_map(|device| device.frames_by_day.values())
+        .copied()
+        .max()
+        .unwrap_or(1);
+
+    let mut rows = String::new();
+    for device in devices {
+        let bars: String = device
+            .frames_by_day
+            .iter()
+            .map(|(day, frames)| {
+                format!(
+                    r#"<div title="{day}: {frames} frames" style="display:inline-block;vertical-align:bottom;width:8px;margin-right:2px;background:#333;height:{}px"></div>"#,
+                    (frames * 40 / most_frames).max(1)
+                )
+            })
+            .collect();
+        let interval = device
+            .average_interval_secs
+            .map(|secs| format!("{:.0} s", secs))
+            .unwrap_or_else(|| "–".to_owned());
+
+        rows.push_str(&format!(
+            "<tr><td>{}</td><td>{bars}</td><td>{interval}</td><td>{:.0}%</td><td>{}</td></tr>\n",
+            escape_html(&device.id),
+            device.not_modified_ratio * 100.0,
+            device.last_seen.format("%Y-%m-%d %H:%M:%S UTC"),
+        ));
+    }
+
+    format!(
+        r#"<section>
+<h2>Devices</h2>
+<table>
+<tr><th>Device</th><th>Frames per day</th><th>Average interval</th><th>304s</th><th>Last seen</th></tr>
+{rows}</table>
+</section>
+"#
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+async fn handle_version(State(state): State<Arc<AppState>>) -> Json<BuildInfo> {
+    Json(BuildInfo::new(&state.loaded().config))
+}
+
+/// Read the config file again without restarting. Changes to the file are
+/// also picked up on their own within a few seconds.
+async fn handle_reload(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<StatusCode, Response> {
+    require_token(&state.loaded().config, &headers)?;
+    require_same_origin(&headers)?;
+
+    state
+        .reload()
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, format!("{e:#}")).into_response())?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Per-source errors from the most recent board, empty when everything
+/// fetched.
+async fn handle_status(State(state): State<Arc<AppState>>) -> Json<BTreeMap<String, String>> {
+    Json(state.errors.lock().unwrap().clone())
+}
+
+/// The departures behind the board, for other consumers to reuse.
+async fn handle_stops_json(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<departures::Departure>>, Response> {
+    let loaded = state.loaded();
+    let now = Utc::now();
+    if state.maintenance.active(now).is_some() || loaded.config.is_quiet(now) {
+        return Err(Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body(Body::from("not fetching departures right now"))
+            .unwrap());
+    }
+
+    let directions = departures::fetch(
+        &state.http,
+        &state.upstream,
+        &loaded.config.stops,
+        &loaded.config.grouping,
+        &loaded.trips,
+    )
+    .await
+    .map_err(|e| {
+        Response::builder()
+            .status(StatusCode::BAD_GATEWAY)
+            .body(Body::from(format!("{e:#}")))
+            .unwrap()
+    })?;
+
+    Ok(Json(departures::list(
+        &directions,
+        Utc::now(),
+        &loaded.config.walk_times(),
+    )))
+}
+
+#[derive(Deserialize, Debug)]
+struct TimetableParams {
+    /// How far ahead to list departures, in hours.
+    hours: Option<i64>,
+}
+
+const DEFAULT_TIMETABLE_HOURS: i64 = 3;
+const MAX_TIMETABLE_HOURS: i64 = 24;
+
+/// The predicted departures over the next few hours, at their timetabled
+/// times, laid out for printing. See [`departures::timetable`] for how far
+/// ahead that reaches.
+async fn handle_timetable_pdf(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<TimetableParams>,
+) -> Result<Response, Response> {
+    let loaded = state.loaded();
+    let hours = params.hours.unwrap_or(DEFAULT_TIMETABLE_HOURS);
+    if !(1..=MAX_TIMETABLE_HOURS).contains(&hours) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("hours must be between 1 and {MAX_TIMETABLE_HOURS}"),
+        )
+            .into_response());
+    }
+
+    let now = Utc::now();
+    if state.maintenance.active(now).is_some() || loaded.config.is_quiet(now) {
+        return Err(Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body(Body::from("not fetching departures right now"))
+            .unwrap());
+    }
 
-                let time = format!("{}, ", (time - Utc::now()).num_minutes());
+    let directions = departures::fetch(
+        &state.http,
+        &state.upstream,
+        &loaded.config.stops,
+        &loaded.config.grouping,
+        &loaded.trips,
+    )
+    .await
+    .map_err(|e| {
+        Response::builder()
+            .status(StatusCode::BAD_GATEWAY)
+            .body(Body::from(format!("{e:#}")))
+            .unwrap()
+    })?;
 
-                times_str.push_str(&time);
+    let rows = departures::timetable(
+        &directions,
+        now,
+        now + chrono::Duration::hours(hours),
+        &loaded.config.grouping.line_order,
+    );
+    let title = format!(
+        "Upcoming departures, {}",
+        now.with_timezone(&loaded.config.timezone)
+            .format("%a %e %b %Y, %H:%M")
+    );
+    let family = loaded
+        .layouts
+        .get(loaded.config.orientation)
+        .font
+        .family
+        .clone();
+    let timezone = loaded.config.timezone;
+    let pdf = render_blocking(&state, move || {
+        render::timetable::draw_timetable(&rows, &title, &family, timezone)
+    })
+    .await
+    .map_err(|e| {
+        Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from(format!("{e:#}")))
+            .unwrap()
+    })?;
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/pdf")
+        .body(Body::from(pdf))
+        .unwrap())
+}
+
+/// Server-sent events for each update, named after its `type`, for
+/// dashboards that want to follow the board live.
+async fn handle_events(
+    State(state): State<Arc<AppState>>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let updates =
+        futures_util::stream::unfold(state.updates.subscribe(), |mut subscription| async move {
+            let update = subscription.next().await?;
+            let event = Event::default().event(update.kind()).json_data(&update);
+            Some((event, subscription))
+        });
+
+    Sse::new(updates).keep_alive(KeepAlive::default())
+}
+
+/// The same updates as `/events`, as JSON text messages over a WebSocket,
+/// for companion displays that keep a connection open.
+async fn handle_ws(State(state): State<Arc<AppState>>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| push_updates(socket, state))
+}
+
+async fn push_updates(mut socket: WebSocket, state: Arc<AppState>) {
+    let mut subscription = state.updates.subscribe();
+
+    loop {
+        tokio::select! {
+            update = subscription.next() => {
+                let Some(update) = update else {
+                    break;
+                };
+                let text = serde_json::to_string(&update).expect("updates serialize");
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
             }
-            times_str.pop();
-            times_str.pop();
-            times_str.push_str(" min");
+            // Anything the client sends is ignored, but its going away ends
+            // the subscription.
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+async fn handle_devices(State(state): State<Arc<AppState>>) -> Json<Vec<DeviceSummary>> {
+    Json(state.devices.summaries())
+}
+
+#[derive(Deserialize, Debug)]
+struct StopSearchParams {
+    /// An address or place name, or "lat, lon".
+    q: String,
+    agency: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct StopSearchResults {
+    place: Place,
+    stops: Vec<NearbyStop>,
+}
+
+/// The stops nearest an address. Behind the API token, since each search
+/// goes to the geocoder and, once a day per agency, to 511.
+async fn handle_stop_search(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<StopSearchParams>,
+) -> Result<Json<StopSearchResults>, Response> {
+    require_token(&state.loaded().config, &headers)?;
+
+    let agency = params.agency.as_deref().unwrap_or(DEFAULT_AGENCY);
+    if agency.is_empty() || agency.len() > 3 || !agency.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "agency should be a 511 operator ID, e.g. SF",
+        )
+            .into_response());
+    }
+
+    let (place, stops) = state
+        .stop_search
+        .search(&state.http, &state.upstream, &params.q, agency)
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("{e:#}")).into_response())?;
 
-            canvas.draw_str_align(times_str, (x2 - 20.0, y), &font, &black_paint, Align::Right);
-            canvas.draw_line((x1 + 10.0, y + 10.0), (x2 - 10.0, y + 10.0), &black_paint);
-            y += 40.0;
+    Ok(Json(StopSearchResults { place, stops }))
+}
+
+async fn handle_metrics(State(state): State<Arc<AppState>>) -> String {
+    state.upstream.metrics()
+}
+
+/// Whether the request carries the configured API token, either as a bearer
+/// token or, for browsers, as the password of basic auth with any user name.
+/// Always false when no token is configured.
+fn authorized(config: &Config, headers: &HeaderMap) -> bool {
+    let Some(token) = &config.api_token else {
+        return false;
+    };
+    let Some(value) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+
+    if let Some(given) = value.strip_prefix("Bearer ") {
+        return same_secret(given, token);
+    }
+
+    value
+        .strip_prefix("Basic ")
+        .and_then(|encoded| BASE64_STANDARD.decode(encoded).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .and_then(|credentials| {
+            let (_, password) = credentials.split_once(':')?;
+            Some(same_secret(password, token))
+        })
+        .unwrap_or(false)
+}
+
+/// Whether `given` is `secret`, taking as long however much of it matches,
+/// so response times don't reveal the token a character at a time. Comparing
+/// digests keeps the length secret too.
+fn same_secret(given: &str, secret: &str) -> bool {
+    let (given, secret) = (Sha256::digest(given), Sha256::digest(secret));
+
+    given
+        .iter()
+        .zip(secret.iter())
+        .fold(0, |differences, (a, b)| differences | (a ^ b))
+        == 0
+}
+
+/// Turn away requests to token-protected APIs that don't carry the token, or
+/// all of them if no token is configured.
+// The error is what handlers return anyway, so boxing it would only move the
+// allocation.
+#[allow(clippy::result_large_err)]
+fn require_token(config: &Config, headers: &HeaderMap) -> Result<(), Response> {
+    if config.api_token.is_none() {
+        return Err((StatusCode::NOT_FOUND, "set api_token to enable this API").into_response());
+    }
+    if !authorized(config, headers) {
+        // Prompts browsers to log in, for the admin page.
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, r#"Basic realm="transit board""#)],
+        )
+            .into_response());
+    }
+
+    Ok(())
+}
+
+/// Refuse requests a browser sent from another site's page, so a form
+/// elsewhere can't ride on the Basic credentials the browser attaches to
+/// every request here. Requests from scripts carry no `Origin` and pass.
+#[allow(clippy::result_large_err)]
+fn require_same_origin(headers: &HeaderMap) -> Result<(), Response> {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let cross_site = match (header(header::ORIGIN), header(header::HOST)) {
+        (Some(origin), Some(host)) => {
+            let origin_host = origin.split_once("://").map_or(origin, |(_, rest)| rest);
+            origin_host != host
         }
+        (Some(_), None) => true,
+        (None, _) => header(HeaderName::from_static("sec-fetch-site")) == Some("cross-site"),
     };
+    if cross_site {
+        return Err((StatusCode::FORBIDDEN, "cross-site request refused").into_response());
+    }
 
-    let width = 1024.0;
-    let height = 758.0;
-    let midpoint = 512.0;
-
-    canvas.draw_rect(Rect::new(0.0, 0.0, width, 30.0), &line_id_bubble_paint);
-    canvas.draw_str_align(
-        "Muni Inbound",
-        (midpoint / 2.0, 23.0),
-        &font,
-        &black_paint,
-        Align::Center,
-    );
-    canvas.draw_str_align(
-        "Muni Outbound",
-        (midpoint + midpoint / 2.0, 23.0),
-        &font,
-        &black_paint,
-        Align::Center,
+    Ok(())
+}
+
+async fn handle_post_annotation(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(new): Json<NewAnnotation>,
+) -> Result<Json<Annotation>, Response> {
+    require_token(&state.loaded().config, &headers)?;
+    require_same_origin(&headers)?;
+
+    state
+        .annotations
+        .add(new, Utc::now())
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())
+}
+
+#[derive(Deserialize, Debug)]
+struct MaintenanceParams {
+    /// RFC 3339 deadline, e.g. `2024-02-01T18:00:00-08:00`.
+    until: Option<String>,
+}
+
+/// `{"errors": [...]}`, for API clients to show beside the fields at fault.
+fn invalid(status: StatusCode, errors: Vec<Invalid>) -> Response {
+    (status, Json(serde_json::json!({ "errors": errors }))).into_response()
+}
+
+fn internal_error(e: eyre::Report) -> Response {
+    invalid(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        vec![Invalid::new(None, format!("{e:#}"))],
+    )
+}
+
+fn no_display(name: &str) -> Response {
+    invalid(
+        StatusCode::NOT_FOUND,
+        vec![Invalid::new(None, format!("no display named {name:?}"))],
+    )
+}
+
+/// Every display, by name, including any added to the database.
+async fn handle_list_displays(
+    State(state): State<Arc<AppState>>,
+) -> Json<BTreeMap<String, Display>> {
+    Json(state.loaded().config.displays.clone())
+}
+
+async fn handle_get_display(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<Display>, Response> {
+    state
+        .loaded()
+        .config
+        .displays
+        .get(&name)
+        .cloned()
+        .map(Json)
+        .ok_or_else(|| no_display(&name))
+}
+
+/// Create or replace a display, in the database if there is one and the
+/// config file otherwise, and start serving it.
+async fn handle_put_display(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    body: Result<Json<Display>, JsonRejection>,
+) -> Result<Json<Display>, Response> {
+    require_token(&state.loaded().config, &headers)?;
+    require_same_origin(&headers)?;
+
+    if !display::valid_name(&name) {
+        return Err(invalid(
+            StatusCode::BAD_REQUEST,
+            vec![Invalid::new(
+                None,
+                "display names may only use letters, digits, - and _",
+            )],
+        ));
+    }
+    let Json(display) = body.map_err(|rejection| {
+        invalid(
+            StatusCode::BAD_REQUEST,
+            vec![Invalid::new(None, rejection.body_text())],
+        )
+    })?;
+
+    let errors = display.validate();
+    if !errors.is_empty() {
+        return Err(invalid(StatusCode::UNPROCESSABLE_ENTITY, errors));
+    }
+
+    let (saving, saved) = (state.clone(), display.clone());
+    write_blocking(move || {
+        match &saving.store {
+            Some(store) => store.save_display(&name, &saved),
+            None => saved.save(&Config::path(), &name),
+        }?;
+        saving.reload()
+    })
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(display))
+}
+
+/// Remove a display from the database if there is one, or from the config
+/// file otherwise.
+async fn handle_delete_display(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Result<StatusCode, Response> {
+    require_token(&state.loaded().config, &headers)?;
+    require_same_origin(&headers)?;
+
+    let (removing, removed_name) = (state.clone(), name.clone());
+    let removed = write_blocking(move || {
+        let removed = match &removing.store {
+            Some(store) => store.remove_display(&removed_name),
+            None => display::remove(&Config::path(), &removed_name),
+        }?;
+        if removed {
+            removing.reload()?;
+        }
+        Ok(removed)
+    })
+    .await
+    .map_err(internal_error)?;
+
+    if removed {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(no_display(&name))
+    }
+}
+
+/// Run `write` on the blocking pool: SQLite and the config file are written
+/// synchronously, and reloading afterwards reads them back, none of which
+/// should hold up the async workers.
+async fn write_blocking<T: Send + 'static>(
+    write: impl FnOnce() -> eyre::Result<T> + Send + 'static,
+) -> eyre::Result<T> {
+    tokio::task::spawn_blocking(write)
+        .await
+        .map_err(|e| eyre!("saving panicked: {e}"))?
+}
+
+/// A form for the stops, line order and thresholds, saved back to the config
+/// file, with a preview of the board that follows the form as it's edited.
+async fn handle_admin(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Html<String>, Response> {
+    require_token(&state.loaded().config, &headers)?;
+
+    let settings = Settings::from_config(&state.loaded().config);
+    Ok(Html(admin_page(&SettingsForm::new(&settings), None)))
+}
+
+async fn handle_save_settings(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Form(form): Form<SettingsForm>,
+) -> Result<Html<String>, Response> {
+    require_token(&state.loaded().config, &headers)?;
+    require_same_origin(&headers)?;
+
+    let path = Config::path();
+    let saved = form
+        .parse()
+        .and_then(|settings| settings.save(&path))
+        .and_then(|()| state.reload());
+    match saved {
+        Ok(()) => Ok(Html(admin_page(
+            &form,
+            Some(&format!("Saved to {}.", path.display())),
+        ))),
+        Err(e) => Err((
+            StatusCode::BAD_REQUEST,
+            Html(admin_page(&form, Some(&format!("Not saved: {e:#}")))),
+        )
+            .into_response()),
+    }
+}
+
+/// The board drawn with the settings in the admin form, saved or not.
+async fn handle_admin_preview(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(form): Query<SettingsForm>,
+) -> Result<Response, Response> {
+    let loaded = state.loaded();
+    require_token(&loaded.config, &headers)?;
+
+    let settings = form
+        .parse()
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("{e:#}")).into_response())?;
+    let mut config = loaded.config.clone();
+    settings.apply(&mut config);
+
+    let layouts = Layouts::load(&config)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}")).into_response())?;
+
+    // Drawn from the last departures fetched for any view, with nothing
+    // fetched, published or recorded, so typing in the form has no effect
+    // on the devices. Stops added in the form show once they're saved.
+    let mut board = Board {
+        annotations: state.annotations.active(Utc::now()),
+        walk_times: config.walk_times(),
+        line_order: config.grouping.line_order.clone(),
+        routes: loaded.routes.clone(),
+        shapes: loaded.shapes.clone(),
+        timezone: Some(config.timezone),
+        ..Board::default()
+    };
+    match state.last_visits.lock().unwrap().clone() {
+        Some(visits) => {
+            board.departures = departures::group(
+                visits,
+                &config.stop_codes(),
+                &config.grouping,
+                &loaded.trips,
+            );
+            board
+                .routes
+                .fill_modes(departures::agency_modes(&board.departures, &config.stops));
+        }
+        None => {
+            board
+                .errors
+                .insert("departures".to_owned(), "not fetched yet".to_owned());
+        }
+    }
+
+    let layout = layouts.get(config.orientation).clone();
+    let output = Output::from_config(&config);
+    let image = render_blocking(&state, move || render::draw_image(&layout, &board, &output))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}")).into_response())?;
+
+    Ok(Response::builder()
+        .header("Content-Type", output.format.content_type())
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Body::from(image))
+        .unwrap())
+}
+
+fn admin_page(form: &SettingsForm, notice: Option<&str>) -> String {
+    let notice = notice
+        .map(|notice| format!("<p><strong>{}</strong></p>\n", escape_html(notice)))
+        .unwrap_or_default();
+
+    format!(
+        r#"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width">
+<title>Transit board settings</title>
+</head>
+<body>
+<h1>Settings</h1>
+{notice}<form method="post" action="/admin">
+<p><label>Stops, one per line, each a stop code and optionally the minutes it takes to walk there<br>
+<textarea name="stops" rows="6" cols="30">{stops}</textarea></label></p>
+<p><label>Lines to list first, one per line, in order<br>
+<textarea name="line_order" rows="6" cols="30">{line_order}</textarea></label></p>
+<p><label>Upcoming times per row <input name="upcoming_times" size="4" value="{upcoming_times}"></label></p>
+<p><label>Hide departures sooner than <input name="min_minutes" size="4" value="{min_minutes}"> minutes</label></p>
+<p><label>Emphasize departures within <input name="imminent_minutes" size="4" value="{imminent_minutes}"> minutes</label></p>
+<p>Leave a number blank to use the layout's.</p>
+<p><button>Save</button></p>
+</form>
+<h2>Preview</h2>
+<img id="preview" alt="Preview of the board">
+<script>
+const form = document.querySelector("form");
+const preview = document.getElementById("preview");
+const refresh = () => {{
+  preview.src = "/admin/preview.png?" + new URLSearchParams(new FormData(form));
+}};
+let timer;
+form.addEventListener("input", () => {{
+  clearTimeout(timer);
+  timer = setTimeout(refresh, 500);
+}});
+refresh();
+</script>
+<p><a href="/">Back to the board</a></p>
+</body>
+</html>
+"#,
+        stops = escape_html(&form.stops),
+        line_order = escape_html(&form.line_order),
+        upcoming_times = escape_html(&form.upcoming_times),
+        min_minutes = escape_html(&form.min_minutes),
+        imminent_minutes = escape_html(&form.imminent_minutes),
+    )
+}
+
+async fn handle_start_maintenance(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<MaintenanceParams>,
+) -> Result<Json<maintenance::Window>, Response> {
+    require_token(&state.loaded().config, &headers)?;
+    require_same_origin(&headers)?;
+
+    let until = match params.until.as_deref() {
+        Some(until) => Some(time::parse_instant(until).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                "until must be an RFC 3339 timestamp",
+            )
+                .into_response()
+        })?),
+        None => None,
+    };
+
+    Ok(Json(state.maintenance.start(until)))
+}
+
+async fn handle_end_maintenance(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<StatusCode, Response> {
+    require_token(&state.loaded().config, &headers)?;
+    require_same_origin(&headers)?;
+    state.maintenance.resume();
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// A strong ETag for `image`. It depends only on the image bytes, so a
+/// device's cached frame stays valid across server restarts and deploys as
+/// long as the board looks the same.
+fn etag(image: &[u8]) -> String {
+    format!("\"{:x}\"", Sha256::digest(image))
+}
+
+/// Whether an `If-None-Match` header lists `etag`.
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+async fn handle_stops_png(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ImageParams>,
+    headers: HeaderMap,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+) -> Response<Body> {
+    let loaded = state.loaded();
+    stops_image(
+        &state,
+        loaded.default_view(),
+        &params,
+        &headers,
+        client,
+        None,
+    )
+    .await
+}
+
+/// The board as an SVG, drawn by the same layout engine as the PNG.
+async fn handle_stops_svg(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ImageParams>,
+    headers: HeaderMap,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+) -> Response<Body> {
+    let loaded = state.loaded();
+    stops_image(
+        &state,
+        loaded.default_view(),
+        &params,
+        &headers,
+        client,
+        Some(Format::Svg),
+    )
+    .await
+}
+
+/// The board's pixels with no header, for scripts that write them straight
+/// to the framebuffer.
+async fn handle_stops_raw(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ImageParams>,
+    headers: HeaderMap,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+) -> Response<Body> {
+    let loaded = state.loaded();
+    stops_image(
+        &state,
+        loaded.default_view(),
+        &params,
+        &headers,
+        client,
+        Some(Format::Raw),
+    )
+    .await
+}
+
+/// The board as an 8-bit grayscale BMP.
+async fn handle_stops_bmp(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ImageParams>,
+    headers: HeaderMap,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+) -> Response<Body> {
+    let loaded = state.loaded();
+    stops_image(
+        &state,
+        loaded.default_view(),
+        &params,
+        &headers,
+        client,
+        Some(Format::Bmp),
+    )
+    .await
+}
+
+/// The board drawn with the experimental layouts, to preview them on a second
+/// device before promoting them. `default` is the only view.
+async fn handle_canary_png(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(params): Query<ImageParams>,
+    headers: HeaderMap,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+) -> Response<Body> {
+    let loaded = state.loaded();
+    let Some(layouts) = loaded
+        .experimental_layouts
+        .as_ref()
+        .filter(|_| name == "default")
+    else {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from("no experimental layout for this view"))
+            .unwrap();
+    };
+
+    let view = View {
+        name: "canary",
+        layouts,
+        ..loaded.default_view()
+    };
+    stops_image(&state, view, &params, &headers, client, None).await
+}
+
+/// One of the configured displays, e.g. `/displays/kitchen.png`, with the
+/// same extensions and query parameters as `/stops.png`. The display's
+/// device, size and orientation apply when the request doesn't give them.
+async fn handle_display(
+    State(state): State<Arc<AppState>>,
+    Path(file): Path<String>,
+    Query(params): Query<ImageParams>,
+    headers: HeaderMap,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+) -> Response<Body> {
+    let loaded = state.loaded();
+    let Some((name, format)) = file.rsplit_once('.').and_then(|(name, extension)| {
+        let format = match extension {
+            "png" => None,
+            "svg" => Some(Format::Svg),
+            "raw" => Some(Format::Raw),
+            "bmp" => Some(Format::Bmp),
+            _ => return None,
+        };
+        Some((name, format))
+    }) else {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from("expected a .png, .svg, .raw or .bmp path"))
+            .unwrap();
+    };
+
+    let (Some(display), Some(layouts)) = (
+        loaded.config.displays.get(name),
+        loaded.display_layouts.get(name),
+    ) else {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from(format!("no display named {name:?}")))
+            .unwrap();
+    };
+
+    let view = View {
+        name,
+        config: &loaded.config,
+        stops: &display.stops,
+        grouping: display.grouping.as_ref().unwrap_or(&loaded.config.grouping),
+        layouts,
+    };
+    let params = params.or_display(display);
+    stops_image(&state, view, &params, &headers, client, format).await
+}
+
+async fn stops_image(
+    state: &AppState,
+    view: View<'_>,
+    params: &ImageParams,
+    headers: &HeaderMap,
+    client: SocketAddr,
+    format: Option<Format>,
+) -> Response<Body> {
+    let (layout, mut output) = match params.apply(view.config, view.layouts) {
+        Ok(applied) => applied,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(e.to_string()))
+                .unwrap()
+        }
+    };
+
+    // Paths with their own extension pin the format; the rest negotiate.
+    output.format = format.unwrap_or_else(|| params.format(headers));
+
+    let image = get_image(state, &view, &layout, &output).await;
+    serve_image(state, image, output.format, params, headers, client)
+}
+
+#[derive(Deserialize, Debug)]
+struct PreviewParams {
+    #[serde(default)]
+    scenario: Scenario,
+}
+
+/// The board drawn from made-up departures instead of 511's, e.g.
+/// `/preview.png?scenario=alerts`, with the same other query parameters as
+/// `/stops.png`. Nothing is fetched, so it works at night and costs no API
+/// quota.
+async fn handle_preview_png(
+    State(state): State<Arc<AppState>>,
+    Query(preview): Query<PreviewParams>,
+    Query(params): Query<ImageParams>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let loaded = state.loaded();
+    let (layout, mut output) = match params.apply(&loaded.config, &loaded.layouts) {
+        Ok(applied) => applied,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(e.to_string()))
+                .unwrap()
+        }
+    };
+    output.format = params.format(&headers);
+
+    let board = preview
+        .scenario
+        .board(&loaded.config, loaded.routes.clone(), Utc::now());
+    match render_blocking(&state, move || render::draw_image(&layout, &board, &output)).await {
+        Ok(image) => Response::builder()
+            .header("Content-Type", output.format.content_type())
+            .header(header::CACHE_CONTROL, "no-store")
+            .body(Body::from(image))
+            .unwrap(),
+        Err(e) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from(format!("{e:#}")))
+            .unwrap(),
+    }
+}
+
+/// Every configured lobby board in one large frame, stacked or taking turns.
+async fn handle_lobby_png(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ImageParams>,
+    headers: HeaderMap,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+) -> Response<Body> {
+    let loaded = state.loaded();
+    let Some(lobby) = &loaded.config.lobby else {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from("no lobby configured"))
+            .unwrap();
+    };
+
+    let (layout, mut output) = match params.apply(&loaded.config, &loaded.layouts) {
+        Ok(applied) => applied,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(e.to_string()))
+                .unwrap()
+        }
+    };
+
+    output.format = params.format(&headers);
+
+    let image = get_lobby_image(&state, lobby, &layout, &output).await;
+    serve_image(&state, image, output.format, &params, &headers, client)
+}
+
+/// Send a drawn image, or a 304 if the device already has it.
+fn serve_image(
+    state: &AppState,
+    image: eyre::Result<Vec<u8>>,
+    format: Format,
+    params: &ImageParams,
+    headers: &HeaderMap,
+    client: SocketAddr,
+) -> Response<Body> {
+    let image = match image {
+        Ok(image) => image,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(format!("{e:#}")))
+                .unwrap()
+        }
+    };
+    let etag = etag(&image);
+    let not_modified = etag_matches(headers, &etag);
+
+    let device = params.id.clone().unwrap_or_else(|| client.ip().to_string());
+    let now = Utc::now();
+    state.devices.record(
+        &device,
+        now,
+        state.loaded().config.service_day(now),
+        not_modified,
     );
-    canvas.draw_line((0.0, 30.0), (width, 30.0), &black_paint);
 
-    draw_times(inbound_journeys, 0.0, midpoint);
-    canvas.draw_line((midpoint, 0.0), (midpoint, height), &black_paint);
-    draw_times(outbound_journeys, midpoint, width);
+    if not_modified {
+        return Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(header::VARY, "Accept")
+            .header(header::ETAG, etag)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", format.content_type())
+        .header(header::VARY, "Accept")
+        .header(header::ETAG, etag)
+        .body(Body::from(Bytes::from(image)))
+        .unwrap()
+}
+
+/// Run `draw` on tokio's blocking pool once one of the render permits is
+/// free. Drawing and encoding a frame is CPU work that would otherwise stall
+/// every request sharing the worker thread. If the request goes away
+/// meanwhile the frame is still finished, holding its permit, then dropped;
+/// nothing it touches is shared.
+async fn render_blocking<T: Send + 'static>(
+    state: &AppState,
+    draw: impl FnOnce() -> eyre::Result<T> + Send + 'static,
+) -> eyre::Result<T> {
+    let permit = state.renders.clone().acquire_owned().await?;
+    tokio::task::spawn_blocking(move || {
+        let drawn = draw();
+        drop(permit);
+        drawn
+    })
+    .await
+    .map_err(|e| eyre!("rendering panicked: {e}"))?
+}
+
+/// The local time shown during quiet hours.
+fn night_time(config: &Config) -> String {
+    Utc::now()
+        .with_timezone(&config.timezone)
+        .format("%H:%M")
+        .to_string()
+}
+
+async fn get_image(
+    state: &AppState,
+    view: &View<'_>,
+    layout: &Layout,
+    output: &Output,
+) -> eyre::Result<Vec<u8>> {
+    let (layout, output) = (layout.clone(), *output);
+    if state.maintenance.active(Utc::now()).is_some() {
+        let message = view.config.maintenance_message.clone();
+        return render_blocking(state, move || {
+            render::draw_message(&layout, &message, &output)
+        })
+        .await;
+    }
+    if view.config.is_quiet(Utc::now()) {
+        let time = night_time(view.config);
+        return render_blocking(state, move || render::draw_night(&layout, &time, &output)).await;
+    }
 
-    let png = bitmap
-        .as_image()
-        .encode(None, skia_safe::EncodedImageFormat::PNG, None)
-        .ok_or(eyre!("skia image encode"))?;
-    let png_bytes = png.as_bytes();
+    let client = &state.http;
 
-    Ok(png_bytes.to_owned())
+    let mut board = Board {
+        annotations: state.annotations.active(Utc::now()),
+        page: state.refreshes.fetch_add(1, Ordering::Relaxed),
+        walk_times: WalkTimes::new(view.stops),
+        line_order: view.grouping.line_order.clone(),
+        routes: state.loaded().routes.clone(),
+        shapes: state.loaded().shapes.clone(),
+        timezone: Some(view.config.timezone),
+        ..Board::default()
+    };
+    let mut fetched = Vec::new();
+    let visits = departures::fetch_visits(client, &state.upstream, view.stops).await;
+    if let Ok(visits) = &visits {
+        *state.last_visits.lock().unwrap() = Some(visits.clone());
+    }
+    let stop_codes: Vec<&str> = view.stops.iter().map(|stop| stop.code.as_str()).collect();
+    let trips = state.loaded().trips.clone();
+    match visits.map(|visits| departures::group(visits, &stop_codes, view.grouping, &trips)) {
+        Ok(mut departures) => {
+            state.predictions.track(&mut departures, Utc::now());
+            fetched = departures::list(&departures, Utc::now(), &board.walk_times);
+            board
+                .routes
+                .fill_modes(departures::agency_modes(&departures, view.stops));
+            state.updates.publish(Update::Departures {
+                departures: fetched.clone(),
+            });
+            board.departures = departures;
+        }
+        Err(e) => {
+            warn!("couldn't fetch departures: {e:#}");
+            board
+                .errors
+                .insert("departures".to_owned(), format!("{e:#}"));
+        }
+    }
+    match state.vehicles.get(client, &state.upstream).await {
+        Some(Ok(positions)) => {
+            vehicles::locate(&mut board.departures, &positions);
+            board.vehicles = positions;
+        }
+        Some(Err(e)) => {
+            warn!("couldn't fetch vehicle positions: {e:#}");
+            board.errors.insert("vehicles".to_owned(), format!("{e:#}"));
+        }
+        None => {}
+    }
+    match state.alerts.get(client, &state.upstream).await {
+        Ok(alerts) => {
+            board.alerts = alerts::relevant(alerts, &board.lines(), &stop_codes, Utc::now())
+        }
+        Err(e) => {
+            warn!("couldn't fetch service alerts: {e:#}");
+            board.errors.insert("alerts".to_owned(), format!("{e:#}"));
+        }
+    }
+    match state.elevators.get(client).await {
+        Some(Ok(outages)) => board.elevator_outages = Some(outages),
+        Some(Err(e)) => {
+            warn!("couldn't fetch elevator outages: {e:#}");
+            board
+                .errors
+                .insert("elevators".to_owned(), format!("{e:#}"));
+        }
+        None => {}
+    }
+    match state.weather.get(client).await {
+        Some(Ok(weather)) => board.weather = Some(weather),
+        Some(Err(e)) => {
+            warn!("couldn't fetch the weather: {e:#}");
+            board.errors.insert("weather".to_owned(), format!("{e:#}"));
+        }
+        None => {}
+    }
+    match state.bikes.get(client).await {
+        Some(Ok(bikes)) => board.bikes = Some(bikes),
+        Some(Err(e)) => {
+            warn!("couldn't fetch bike share availability: {e:#}");
+            board.errors.insert("bikes".to_owned(), format!("{e:#}"));
+        }
+        None => {}
+    }
+    *state.errors.lock().unwrap() = board.errors.clone();
+    *state.last_fetch.lock().unwrap() = Some(Utc::now());
+    if let Some(store) = state.store.clone() {
+        // SQLite writes block, and the frame doesn't wait on the history.
+        let (name, at, errors) = (view.name.to_owned(), Utc::now(), board.errors.clone());
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = store.record_fetch(&name, at, &fetched, &errors) {
+                warn!("couldn't record the fetch: {e:#}");
+            }
+        });
+    }
+
+    let errors = board.errors.clone();
+    let format = output.format;
+    let png = render_blocking(state, move || render::draw_image(&layout, &board, &output)).await;
+    if let Ok(image) = &png {
+        state.remember_frame(view.name, format, image);
+        state.updates.publish(Update::Rendered {
+            view: view.name.to_owned(),
+            at: Utc::now(),
+        });
+    }
+
+    match (&png, errors.iter().next()) {
+        (Err(e), _) => state.webhook.failure(view.name, &format!("{e:#}")),
+        (Ok(_), Some((source, error))) => state
+            .webhook
+            .failure(view.name, &format!("{source}: {error}")),
+        (Ok(_), None) => state.webhook.success(view.name, Utc::now()),
+    }
+
+    png
+}
+
+async fn get_lobby_image(
+    state: &AppState,
+    lobby: &LobbyConfig,
+    layout: &Layout,
+    output: &Output,
+) -> eyre::Result<Vec<u8>> {
+    let loaded = state.loaded();
+    let output = *output;
+    if state.maintenance.active(Utc::now()).is_some() {
+        let layout = layout.scaled(lobby.width, lobby.height);
+        let message = loaded.config.maintenance_message.clone();
+        return render_blocking(state, move || {
+            render::draw_message(&layout, &message, &output)
+        })
+        .await;
+    }
+    if loaded.config.is_quiet(Utc::now()) {
+        let layout = layout.scaled(lobby.width, lobby.height);
+        let time = night_time(&loaded.config);
+        return render_blocking(state, move || render::draw_night(&layout, &time, &output)).await;
+    }
+
+    let refresh = state.lobby_refreshes.fetch_add(1, Ordering::Relaxed);
+    let annotations = state.annotations.active(Utc::now());
+
+    // Fetch every shown stop together, so stops on more than one board are
+    // only asked for once, then group per board.
+    let showing = lobby.showing(refresh);
+    let stops: Vec<Stop> = showing
+        .iter()
+        .flat_map(|board| board.stops.iter().cloned())
+        .collect();
+    let response = departures::fetch_visits(&state.http, &state.upstream, &stops).await;
+    match &response {
+        Ok(response) => *state.last_visits.lock().unwrap() = Some(response.clone()),
+        Err(e) => warn!("couldn't fetch departures: {e:#}"),
+    }
+    let alerts = state.alerts.get(&state.http, &state.upstream).await;
+    if let Err(e) = &alerts {
+        warn!("couldn't fetch service alerts: {e:#}");
+    }
+
+    let boards = showing
+        .iter()
+        .map(|shown| {
+            let grouping = shown.grouping.as_ref().unwrap_or(&loaded.config.grouping);
+            let mut board = Board {
+                annotations: annotations.clone(),
+                page: refresh,
+                walk_times: shown.walk_times(),
+                line_order: grouping.line_order.clone(),
+                routes: loaded.routes.clone(),
+                shapes: loaded.shapes.clone(),
+                timezone: Some(loaded.config.timezone),
+                ..Board::default()
+            };
+            match &response {
+                Ok(response) => {
+                    board.departures = departures::group(
+                        response.clone(),
+                        &shown.stop_codes(),
+                        grouping,
+                        &loaded.trips,
+                    );
+                    state.predictions.track(&mut board.departures, Utc::now());
+                    board
+                        .routes
+                        .fill_modes(departures::agency_modes(&board.departures, &shown.stops));
+                }
+                Err(e) => {
+                    board
+                        .errors
+                        .insert("departures".to_owned(), format!("{e:#}"));
+                }
+            }
+            // Each board's banner shows only the alerts for its own lines
+            // and stops.
+            match &alerts {
+                Ok(alerts) => {
+                    board.alerts = alerts::relevant(
+                        alerts.clone(),
+                        &board.lines(),
+                        &shown.stop_codes(),
+                        Utc::now(),
+                    )
+                }
+                Err(e) => {
+                    board.errors.insert("alerts".to_owned(), format!("{e:#}"));
+                }
+            }
+            (shown.title.clone(), board)
+        })
+        .collect::<Vec<_>>();
+
+    let (layout, size) = (layout.clone(), (lobby.width, lobby.height));
+    let png = render_blocking(state, move || {
+        let (titles, boards): (Vec<String>, Vec<Board>) = boards.into_iter().unzip();
+        let boards: Vec<(&str, Board)> = titles.iter().map(String::as_str).zip(boards).collect();
+        render::draw_lobby(&layout, &boards, size, &output)
+    })
+    .await;
+    if let Ok(image) = &png {
+        state.remember_frame("lobby", output.format, image);
+        state.updates.publish(Update::Rendered {
+            view: "lobby".to_owned(),
+            at: Utc::now(),
+        });
+    }
+
+    match (&png, &response) {
+        (Err(e), _) => state.webhook.failure("lobby", &format!("{e:#}")),
+        (Ok(_), Err(e)) => state
+            .webhook
+            .failure("lobby", &format!("departures: {e:#}")),
+        (Ok(_), Ok(_)) => state.webhook.success("lobby", Utc::now()),
+    }
+
+    png
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn headers(name: HeaderName, values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(name.clone(), HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn etag_depends_only_on_the_image() {
+        assert_eq!(etag(b"frame"), etag(b"frame"));
+        assert_ne!(etag(b"frame"), etag(b"frame 2"));
+        assert!(etag(b"frame").starts_with('"') && etag(b"frame").ends_with('"'));
+    }
+
+    #[test]
+    fn if_none_match_finds_the_etag_in_a_list() {
+        let tag = etag(b"frame");
+        let listed = headers(header::IF_NONE_MATCH, &[&format!("\"other\", {tag}")]);
+        let unlisted = headers(header::IF_NONE_MATCH, &["\"other\", \"another\""]);
+
+        assert!(etag_matches(&listed, &tag));
+        assert!(!etag_matches(&unlisted, &tag));
+    }
+
+    #[test]
+    fn if_none_match_reads_every_header() {
+        let tag = etag(b"frame");
+        let headers = headers(header::IF_NONE_MATCH, &["\"other\"", &tag]);
+
+        assert!(etag_matches(&headers, &tag));
+    }
+
+    #[test]
+    fn if_none_match_star_matches_anything() {
+        assert!(etag_matches(
+            &headers(header::IF_NONE_MATCH, &["*"]),
+            &etag(b"frame")
+        ));
+    }
+
+    #[test]
+    fn weak_validators_match_weakly() {
+        let tag = etag(b"frame");
+
+        assert!(etag_matches(
+            &headers(header::IF_NONE_MATCH, &[&format!("W/{tag}")]),
+            &tag
+        ));
+    }
+
+    #[test]
+    fn no_if_none_match_matches_nothing() {
+        assert!(!etag_matches(&HeaderMap::new(), &etag(b"frame")));
+    }
+
+    /// The format sent for a request accepting `accept`.
+    fn negotiated(accept: &str) -> Format {
+        ImageParams::default().format(&headers(header::ACCEPT, &[accept]))
+    }
+
+    #[test]
+    fn format_parameter_wins_over_accept() {
+        let params = ImageParams {
+            format: Some(Format::Jpeg),
+            ..ImageParams::default()
+        };
+
+        assert_eq!(
+            params.format(&headers(header::ACCEPT, &["image/webp"])),
+            Format::Jpeg
+        );
+    }
+
+    #[test]
+    fn accept_prefers_higher_q_values() {
+        assert_eq!(negotiated("image/png;q=0.5, image/webp"), Format::Webp);
+        assert_eq!(
+            negotiated("image/webp;q=0.4, image/jpeg;q=0.9"),
+            Format::Jpeg
+        );
+        assert_eq!(
+            negotiated("image/webp; Q=0.2, image/png; q=0.3"),
+            Format::Png
+        );
+    }
+
+    #[test]
+    fn accept_q_zero_refuses_a_type() {
+        assert_eq!(negotiated("image/webp;q=0, image/jpeg;q=0.1"), Format::Jpeg);
+        assert_eq!(negotiated("image/png;q=0, */*"), Format::Webp);
+    }
+
+    #[test]
+    fn accept_ties_go_to_the_first_listed() {
+        assert_eq!(negotiated("image/jpeg, image/webp"), Format::Jpeg);
+        assert_eq!(
+            negotiated("image/webp;q=0.5, image/png;q=0.5"),
+            Format::Webp
+        );
+    }
+
+    #[test]
+    fn accept_wildcards_cover_every_format() {
+        assert_eq!(negotiated("*/*"), Format::Png);
+        assert_eq!(negotiated("image/*"), Format::Png);
+        assert_eq!(negotiated("image/webp;q=0.5, image/*;q=0.8"), Format::Png);
+        assert_eq!(negotiated("image/webp, */*;q=0.8"), Format::Webp);
+    }
+
+    #[test]
+    fn accept_without_an_image_type_falls_back_to_png() {
+        assert_eq!(negotiated("text/html, application/json"), Format::Png);
+        assert_eq!(negotiated("image/png;q=0, image/webp;q=0"), Format::Png);
+        assert_eq!(
+            ImageParams::default().format(&HeaderMap::new()),
+            Format::Png
+        );
+    }
+
+    fn with_token(token: &str) -> Config {
+        Config {
+            api_token: Some(token.to_owned()),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn token_is_accepted_as_bearer_or_basic_password() {
+        let config = with_token("s3cret");
+        let basic = format!("Basic {}", BASE64_STANDARD.encode("admin:s3cret"));
+
+        assert!(authorized(
+            &config,
+            &headers(header::AUTHORIZATION, &["Bearer s3cret"])
+        ));
+        assert!(authorized(
+            &config,
+            &headers(header::AUTHORIZATION, &[&basic])
+        ));
+    }
+
+    #[test]
+    fn wrong_or_missing_tokens_are_refused() {
+        let config = with_token("s3cret");
+        let basic = format!("Basic {}", BASE64_STANDARD.encode("admin:s3cre"));
+
+        assert!(!authorized(
+            &config,
+            &headers(header::AUTHORIZATION, &["Bearer s3cre"])
+        ));
+        assert!(!authorized(
+            &config,
+            &headers(header::AUTHORIZATION, &["Bearer s3crets"])
+        ));
+        assert!(!authorized(
+            &config,
+            &headers(header::AUTHORIZATION, &[&basic])
+        ));
+        assert!(!authorized(&config, &HeaderMap::new()));
+        assert!(!authorized(
+            &Config::default(),
+            &headers(header::AUTHORIZATION, &["Bearer "])
+        ));
+    }
+
+    #[test]
+    fn cross_site_requests_are_refused() {
+        let mut same = headers(header::HOST, &["board.local:3001"]);
+        same.insert(
+            header::ORIGIN,
+            HeaderValue::from_static("http://board.local:3001"),
+        );
+        let mut other = headers(header::HOST, &["board.local:3001"]);
+        other.insert(
+            header::ORIGIN,
+            HeaderValue::from_static("https://evil.example"),
+        );
+        let fetched = headers(HeaderName::from_static("sec-fetch-site"), &["cross-site"]);
+
+        assert!(require_same_origin(&same).is_ok());
+        assert!(require_same_origin(&HeaderMap::new()).is_ok());
+        assert!(require_same_origin(&other).is_err());
+        assert!(require_same_origin(&fetched).is_err());
+    }
 }
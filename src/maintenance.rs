@@ -0,0 +1,41 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// A planned pause: every view shows a maintenance notice and the upstream
+/// API isn't polled.
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct Window {
+    /// When the pause ends on its own, or `None` to wait for a resume.
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default)]
+pub struct Maintenance {
+    window: Mutex<Option<Window>>,
+}
+
+impl Maintenance {
+    pub fn start(&self, until: Option<DateTime<Utc>>) -> Window {
+        let window = Window { until };
+        *self.window.lock().unwrap() = Some(window);
+
+        window
+    }
+
+    pub fn resume(&self) {
+        *self.window.lock().unwrap() = None;
+    }
+
+    /// The maintenance window in effect at `now`, if any. A window that has
+    /// passed its deadline is cleared.
+    pub fn active(&self, now: DateTime<Utc>) -> Option<Window> {
+        let mut window = self.window.lock().unwrap();
+        if window.is_some_and(|window| window.until.is_some_and(|until| until <= now)) {
+            *window = None;
+        }
+
+        *window
+    }
+}
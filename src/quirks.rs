@@ -0,0 +1,241 @@
+//! Workarounds for how 511 formats its responses: bodies start with a UTF-8
+//! byte order mark, identifiers and numbers arrive as strings or numbers
+//! depending on the agency and the endpoint, and some feeds only come as
+//! XML.
+
+use eyre::eyre;
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use serde_json::{Map, Value};
+use tracing::warn;
+
+/// Parse a 511 response body, ignoring its byte order mark.
+pub fn from_json<T: DeserializeOwned>(body: &str) -> serde_json::Result<T> {
+    serde_json::from_str(body.trim_start_matches('\u{feff}'))
+}
+
+/// Parse a SIRI response body sent as either JSON or XML, going by whether
+/// it starts with a tag.
+pub fn from_json_or_xml<T: DeserializeOwned>(body: &str) -> eyre::Result<T> {
+    let trimmed = body.trim_start_matches('\u{feff}').trim_start();
+    if trimmed.starts_with('<') {
+        Ok(serde_json::from_value(xml_to_json(trimmed)?)?)
+    } else {
+        Ok(serde_json::from_str(trimmed)?)
+    }
+}
+
+/// XML as the JSON 511 would have sent for it, so both go through the same
+/// types: an element holding only text becomes a string, any other element
+/// an object of its attributes and children, and a repeated child an array.
+/// The root element itself (`Siri`) is dropped, as are namespaces.
+pub fn xml_to_json(xml: &str) -> eyre::Result<Value> {
+    let mut reader = Reader::from_str(xml);
+    // Each open element's name, children and text.
+    let mut open: Vec<(String, Map<String, Value>, String)> = Vec::new();
+    // The attributes of each open element, kept only if it has children.
+    let mut attributes: Vec<Map<String, Value>> = Vec::new();
+
+    loop {
+        match reader
+            .read_event()
+            .map_err(|e| eyre!("malformed XML at {}: {e}", reader.buffer_position()))?
+        {
+            Event::Start(start) => {
+                let name = String::from_utf8_lossy(start.local_name().as_ref()).into_owned();
+                open.push((name, Map::new(), String::new()));
+                attributes.push(attribute_map(&start)?);
+            }
+            Event::Empty(empty) => {
+                let name = String::from_utf8_lossy(empty.local_name().as_ref()).into_owned();
+                let attributes = attribute_map(&empty)?;
+                let value = match attributes.is_empty() {
+                    true => Value::Null,
+                    false => Value::Object(attributes),
+                };
+                if let Some((_, children, _)) = open.last_mut() {
+                    add_child(children, name, value);
+                }
+            }
+            Event::Text(text) => {
+                if let Some((_, _, content)) = open.last_mut() {
+                    content.push_str(&text.unescape()?);
+                }
+            }
+            Event::CData(data) => {
+                if let Some((_, _, content)) = open.last_mut() {
+                    content.push_str(&String::from_utf8_lossy(&data));
+                }
+            }
+            Event::End(_) => {
+                let (name, mut children, content) =
+                    open.pop().ok_or_else(|| eyre!("unbalanced XML"))?;
+                let attributes = attributes.pop().unwrap_or_default();
+                let value = match children.is_empty() {
+                    true => Value::String(content.trim().to_owned()),
+                    false => {
+                        for (key, value) in attributes {
+                            children.entry(key).or_insert(value);
+                        }
+                        Value::Object(children)
+                    }
+                };
+                match open.last_mut() {
+                    Some((_, parent, _)) => add_child(parent, name, value),
+                    None => return Ok(value),
+                }
+            }
+            Event::Eof => return Err(eyre!("XML ended before its root element closed")),
+            _ => {}
+        }
+    }
+}
+
+fn attribute_map(element: &BytesStart) -> eyre::Result<Map<String, Value>> {
+    let mut map = Map::new();
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        let key = attribute.key;
+        if key.as_ref().starts_with(b"xmlns") {
+            continue;
+        }
+        let name = String::from_utf8_lossy(key.local_name().as_ref()).into_owned();
+        map.insert(
+            name,
+            Value::String(attribute.unescape_value()?.into_owned()),
+        );
+    }
+
+    Ok(map)
+}
+
+fn add_child(children: &mut Map<String, Value>, name: String, value: Value) {
+    match children.get_mut(&name) {
+        None => {
+            children.insert(name, value);
+        }
+        Some(Value::Array(siblings)) => siblings.push(value),
+        Some(first) => {
+            let first = first.take();
+            children.insert(name, Value::Array(vec![first, value]));
+        }
+    }
+}
+
+/// A JSON scalar of whichever type the producer picked.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Scalar {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl Scalar {
+    fn into_string(self) -> String {
+        match self {
+            Scalar::String(string) => string,
+            Scalar::Integer(n) => n.to_string(),
+            Scalar::Float(n) => n.to_string(),
+            Scalar::Bool(b) => b.to_string(),
+        }
+    }
+}
+
+/// A list whose entries that don't parse are logged and left out, so one
+/// malformed entry doesn't lose the rest. A lone entry converted from XML
+/// arrives as an object rather than a list, and is taken as a list of one.
+/// Needs `#[serde(default)]` alongside it.
+pub fn skip_malformed<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let entries = match Value::deserialize(deserializer)? {
+        Value::Array(entries) => entries,
+        Value::Null => Vec::new(),
+        entry => vec![entry],
+    };
+    let kind = std::any::type_name::<T>()
+        .rsplit("::")
+        .next()
+        .unwrap_or("entry");
+
+    Ok(entries
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, entry)| match serde_json::from_value(entry) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                warn!("skipping malformed {kind} {i}: {e}");
+                None
+            }
+        })
+        .collect())
+}
+
+/// A string that may have been sent as a number, e.g. `"LineRef": 14`.
+pub fn string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Scalar::deserialize(deserializer).map(Scalar::into_string)
+}
+
+/// Like [`string`], for fields that may be missing or null. Needs
+/// `#[serde(default)]` alongside it.
+pub fn opt_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(Option::<Scalar>::deserialize(deserializer)?.map(Scalar::into_string))
+}
+
+/// A whole number that may have been sent as a string, e.g.
+/// `"Start": "1706806800"`. Needs `#[serde(default)]` alongside it.
+pub fn opt_i64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+    use serde::de::Error;
+
+    match Option::<Scalar>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Scalar::Integer(n)) => Ok(Some(n)),
+        Some(Scalar::String(s)) if s.trim().is_empty() => Ok(None),
+        Some(Scalar::String(s)) => s
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| D::Error::custom(format!("expected a whole number, got {s:?}"))),
+        Some(_) => Err(D::Error::custom("expected a whole number")),
+    }
+}
+
+/// A number that may have been sent as a string, e.g. `"Latitude":
+/// "37.7764"`. Needs `#[serde(default)]` alongside it.
+pub fn opt_f64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    use serde::de::Error;
+
+    match Option::<Scalar>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Scalar::Integer(n)) => Ok(Some(n as f64)),
+        Some(Scalar::Float(n)) => Ok(Some(n)),
+        Some(Scalar::String(s)) if s.trim().is_empty() => Ok(None),
+        Some(Scalar::String(s)) => s
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| D::Error::custom(format!("expected a number, got {s:?}"))),
+        Some(_) => Err(D::Error::custom("expected a number")),
+    }
+}
+
+/// A flag that may have been sent as `"true"` or `"false"`. Needs
+/// `#[serde(default)]` alongside it.
+pub fn opt_bool<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<bool>, D::Error> {
+    use serde::de::Error;
+
+    match Option::<Scalar>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Scalar::Bool(b)) => Ok(Some(b)),
+        Some(Scalar::String(s)) if s.eq_ignore_ascii_case("true") => Ok(Some(true)),
+        Some(Scalar::String(s)) if s.eq_ignore_ascii_case("false") => Ok(Some(false)),
+        Some(_) => Err(D::Error::custom("expected true or false")),
+    }
+}
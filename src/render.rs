@@ -0,0 +1,1458 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::Write,
+    sync::{LazyLock, Mutex},
+};
+
+use chrono::prelude::*;
+use eyre::eyre;
+use qrcode::QrCode;
+use serde::Deserialize;
+use skia_safe::{
+    svg, utils::text_utils::Align, Canvas, Color4f, Font, FontMgr, FontStyle, Paint, PaintStyle,
+    Path, PathEffect, Rect, Typeface,
+};
+use tracing::warn;
+
+use crate::{
+    annotations::Severity,
+    bikes::Availability,
+    board::Board,
+    config::Config,
+    departures::{self, Arrival, LinesDestinationsToJourneys, Upcoming},
+    layout::{Alignment, Emphasis, FontSpec, Layout, Overflow, Panel, Stroke},
+    routes::Mode,
+    shapes::Area,
+    weather::{Sky, Weather},
+};
+
+pub mod device;
+pub mod frame;
+mod logo;
+mod text;
+pub mod timetable;
+
+use frame::{Dither, Frame, PngOptions, Rotation};
+
+fn text_bounds(text: &str, (x, y): (f32, f32), font: &Font, paint: &Paint) -> Rect {
+    let (_, text_measurements) = font.measure_str(text, Some(paint));
+    let text_width = text::measure(text, font, paint);
+    Rect::new(x, y + text_measurements.top, x + text_width, y)
+}
+
+/// The largest width or height a frame can be drawn at, to keep a typo from
+/// allocating an enormous bitmap.
+pub const MAX_IMAGE_DIMENSION: u32 = 4096;
+
+/// Used whenever a configured font family isn't installed, so the board still
+/// renders in minimal containers without any system fonts.
+const FALLBACK_FONT: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+
+/// The platform's font manager, created once. Creating one loads the font
+/// configuration, which is too slow to do for every frame.
+static FONT_MGR: LazyLock<FontMgr> = LazyLock::new(FontMgr::new);
+
+fn font_manager() -> &'static FontMgr {
+    &FONT_MGR
+}
+
+/// The bundled fallback typeface, parsed once.
+fn fallback_typeface() -> Option<Typeface> {
+    static FALLBACK: LazyLock<Option<Typeface>> =
+        LazyLock::new(|| font_manager().new_from_data(FALLBACK_FONT, None));
+
+    FALLBACK.clone()
+}
+
+/// Typefaces by family, looked up once. Matching a family goes through the
+/// platform's font configuration, which is slow enough to show in every frame.
+static TYPEFACES: LazyLock<Mutex<HashMap<String, Typeface>>> = LazyLock::new(Mutex::default);
+
+fn load_font(spec: &FontSpec) -> eyre::Result<Font> {
+    if let Some(typeface) = TYPEFACES.lock().unwrap().get(&spec.family) {
+        return Ok(Font::new(typeface.clone(), spec.size));
+    }
+
+    // Matched without the lock held, so a slow lookup doesn't hold up other
+    // renders. Two renders may both look up a new family; the second insert
+    // is harmless.
+    let typeface = match font_manager().match_family_style(&spec.family, FontStyle::normal()) {
+        Some(typeface) => typeface,
+        None => {
+            warn!(
+                family = %spec.family,
+                "font family not installed, using bundled DejaVu Sans"
+            );
+            fallback_typeface().ok_or(eyre!("bundled fallback font"))?
+        }
+    };
+    TYPEFACES
+        .lock()
+        .unwrap()
+        .insert(spec.family.clone(), typeface.clone());
+
+    Ok(Font::new(typeface, spec.size))
+}
+
+/// `text` cut short with an ellipsis so that it fits within `max_width`.
+fn ellipsize<'a>(text: &'a str, max_width: f32, font: &Font, paint: &Paint) -> Cow<'a, str> {
+    let fits = |text: &str| text::measure(text, font, paint) <= max_width;
+    if fits(text) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut end = text.len();
+    while let Some((i, _)) = text[..end].char_indices().next_back() {
+        end = i;
+        let truncated = format!("{}…", text[..end].trim_end());
+        if fits(&truncated) {
+            return Cow::Owned(truncated);
+        }
+    }
+
+    Cow::Borrowed("")
+}
+
+/// The y coordinate of the baseline that vertically centers a line of text in
+/// `rect`.
+fn centered_baseline(rect: Rect, font: &Font) -> f32 {
+    let (_, metrics) = font.metrics();
+    rect.top + (rect.height() - (metrics.descent - metrics.ascent)) / 2.0 - metrics.ascent
+}
+
+/// A contrast and gamma curve applied to every shade before it's drawn.
+///
+/// E-ink panels render midtones much lighter than an LCD, so light grays that
+/// look fine in a browser can all but vanish on a Kindle. A `gamma` above 1
+/// darkens midtones and a `contrast` above 1 pushes shades away from middle
+/// gray; black and white are unaffected by either.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tone {
+    pub gamma: f32,
+    pub contrast: f32,
+    /// Set from the top-level `theme` or the request, not the `tone` table.
+    #[serde(skip)]
+    pub theme: Theme,
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            contrast: 1.0,
+            theme: Theme::default(),
+        }
+    }
+}
+
+/// Which way round the board is drawn.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    /// Black on white.
+    #[default]
+    Light,
+    /// White on black, with every gray swapped for its opposite.
+    Dark,
+}
+
+impl Tone {
+    /// Adjust `shade`, from 0.0 (black) to 1.0 (white).
+    pub fn apply(&self, shade: f32) -> f32 {
+        let shade = match self.theme {
+            Theme::Light => shade,
+            Theme::Dark => 1.0 - shade,
+        };
+        let contrasted = ((shade - 0.5) * self.contrast + 0.5).clamp(0.0, 1.0);
+        contrasted.powf(self.gamma)
+    }
+
+    pub fn color(&self, shade: f32) -> Color4f {
+        let shade = self.apply(shade);
+        Color4f::new(shade, shade, shade, 1.0)
+    }
+
+    pub fn paint(&self, shade: f32) -> Paint {
+        Paint::new(self.color(shade), None)
+    }
+
+    /// The same curve in the light theme, for panels that must keep their
+    /// own colors in the dark one: an inverted QR code won't scan on many
+    /// phones, and a logo's colors shouldn't be negated.
+    fn unthemed(&self) -> Tone {
+        Tone {
+            theme: Theme::Light,
+            ..*self
+        }
+    }
+
+    /// A paint for outlining with `stroke`, or `None` if it's disabled.
+    fn stroke(&self, stroke: &Stroke) -> Option<Paint> {
+        if !stroke.enabled {
+            return None;
+        }
+
+        let mut paint = self.paint(stroke.shade);
+        paint.set_style(PaintStyle::Stroke);
+        paint.set_stroke_width(stroke.width);
+        if !stroke.dash.is_empty() {
+            paint.set_path_effect(PathEffect::dash(&stroke.dash, 0.0));
+        }
+
+        Some(paint)
+    }
+}
+
+/// How each row of a departures panel is drawn.
+struct RowStyle {
+    font: Font,
+    /// The smallest size `font` may shrink to so a row fits its panel.
+    min_font_size: f32,
+    row_height: f32,
+    text: Paint,
+    bubble: Paint,
+    /// For bubbles shaded by route color.
+    tone: Tone,
+    /// Rule drawn under each row, if any.
+    separator: Option<Paint>,
+    upcoming: Upcoming,
+    /// Paint for arrivals too soon to catch, when they're dimmed.
+    dimmed: Paint,
+    imminent: Emphasis,
+    /// Background for inverted arrivals, which are drawn in `inverted_text`.
+    inverted: Paint,
+    inverted_text: Paint,
+    overflow: Overflow,
+}
+
+/// Horizontal space in a departures row that isn't text: the margins at
+/// either end, the line bubble's outset, and the gaps either side of the
+/// destination.
+const ROW_PADDING: f32 = 20.0 + 8.0 + 8.0 + 15.0 + 15.0 + 20.0;
+
+impl RowStyle {
+    /// The row font, shrunk if needed so `texts` set side by side fit across
+    /// `width`.
+    fn fitted_font(&self, texts: &[&str], width: f32) -> Font {
+        let text_width: f32 = texts
+            .iter()
+            .map(|text| text::measure(text, &self.font, &self.text))
+            .sum();
+        let available = width - ROW_PADDING;
+
+        if text_width <= available {
+            return self.font.clone();
+        }
+
+        let size = (self.font.size() * available / text_width).max(self.min_font_size);
+        let mut font = self.font.clone();
+        font.set_size(size);
+
+        font
+    }
+}
+
+/// How a drawn frame is prepared for and post-processed into the image sent
+/// to the device.
+#[derive(Debug, Clone, Copy)]
+pub struct Output {
+    pub tone: Tone,
+    pub rotation: Rotation,
+    /// Bits of grayscale to quantize to; 8 leaves the frame untouched.
+    pub gray_depth: u8,
+    pub dither: Option<Dither>,
+    pub dpi: Option<u32>,
+    pub format: Format,
+    pub png: PngOptions,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self {
+            tone: Tone::default(),
+            rotation: Rotation::None,
+            gray_depth: 8,
+            dither: None,
+            dpi: None,
+            format: Format::default(),
+            png: PngOptions::default(),
+        }
+    }
+}
+
+/// What the finished image is encoded as.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    #[default]
+    Png,
+    /// Drawn as vectors rather than pixels, so rotation, gray depth and
+    /// dithering don't apply.
+    Svg,
+    /// Bare 8-bit pixels, a row at a time with no padding or header.
+    Raw,
+    /// 8-bit grayscale BMP.
+    Bmp,
+    /// Lossless WebP, usually much smaller than the PNG.
+    Webp,
+    /// JPEG at quality 85. Lossy, so text and fine lines pick up some noise.
+    Jpeg,
+}
+
+impl Format {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Png => "image/png",
+            Format::Svg => "image/svg+xml",
+            Format::Raw => "application/octet-stream",
+            Format::Bmp => "image/bmp",
+            Format::Webp => "image/webp",
+            Format::Jpeg => "image/jpeg",
+        }
+    }
+}
+
+impl Output {
+    /// The default output with the configured tone curve.
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            tone: Tone {
+                theme: config.theme,
+                ..config.tone
+            },
+            png: config.png,
+            ..Self::default()
+        }
+    }
+
+    /// Quantize, rotate and encode a drawn frame.
+    fn encode(&self, mut frame: Frame) -> eyre::Result<Vec<u8>> {
+        frame.quantize(self.gray_depth, self.dither);
+        let frame = frame.rotated(self.rotation);
+
+        let encoded = match self.format {
+            Format::Png => frame.encode_png(self.dpi, &self.png, self.gray_depth),
+            Format::Raw => Ok(frame.encode_raw()),
+            Format::Bmp => Ok(frame.encode_bmp(self.dpi)),
+            Format::Webp => frame.encode_webp(),
+            Format::Jpeg => frame.encode_jpeg(),
+            Format::Svg => Err(eyre!("this view can't be drawn as SVG")),
+        };
+        frame.recycle();
+
+        encoded
+    }
+}
+
+/// An image the size of `layout` drawn by `draw`, on whichever canvas the
+/// output format needs.
+fn draw_with(
+    layout: &Layout,
+    output: &Output,
+    draw: impl FnOnce(&Canvas) -> eyre::Result<()>,
+) -> eyre::Result<Vec<u8>> {
+    if output.format == Format::Svg {
+        let canvas = svg::Canvas::new(Rect::from_wh(layout.width, layout.height), None);
+        draw(&canvas)?;
+        return Ok(canvas.end().as_bytes().to_vec());
+    }
+
+    let mut frame = Frame::new(
+        layout.width.round() as usize,
+        layout.height.round() as usize,
+    );
+    {
+        let info = frame.image_info();
+        let canvas = Canvas::from_raster_direct(&info, &mut frame.pixels, frame.width, None)
+            .ok_or(eyre!("skia canvas"))?;
+        draw(&canvas)?;
+    }
+
+    output.encode(frame)
+}
+
+pub fn draw_image(layout: &Layout, board: &Board, output: &Output) -> eyre::Result<Vec<u8>> {
+    draw_with(layout, output, |canvas| {
+        draw_board(canvas, layout, board, &output.tone)
+    })
+}
+
+/// Several titled boards stacked into one `width` by `height` frame, each
+/// drawn with `layout` scaled to fit its share of the height.
+pub fn draw_lobby(
+    layout: &Layout,
+    boards: &[(&str, Board)],
+    (width, height): (f32, f32),
+    output: &Output,
+) -> eyre::Result<Vec<u8>> {
+    let mut frame = Frame::new(width.round() as usize, height.round() as usize);
+    let tone = &output.tone;
+
+    let section_height = height / boards.len().max(1) as f32;
+    // With many boards in a short frame the title gets at most half of each
+    // section, and the board at least a pixel.
+    let title_height = (section_height * 0.08)
+        .clamp(24.0, 60.0)
+        .min(section_height / 2.0);
+    let board_layout = layout.scaled(width, (section_height - title_height).max(1.0));
+
+    let mut font = load_font(&layout.font)?;
+    font.set_size(title_height * 0.7);
+
+    {
+        let info = frame.image_info();
+        let canvas = Canvas::from_raster_direct(&info, &mut frame.pixels, frame.width, None)
+            .ok_or(eyre!("skia canvas"))?;
+        canvas.clear(tone.color(1.0));
+
+        let paint = tone.paint(1.0);
+        for (i, (title, _)) in boards.iter().enumerate() {
+            let strip = Rect::from_xywh(0.0, i as f32 * section_height, width, title_height);
+            canvas.draw_rect(strip, &tone.paint(0.0));
+            let title = ellipsize(title, width - 20.0, &font, &paint);
+            text::draw(
+                &canvas,
+                &title,
+                (10.0, centered_baseline(strip, &font)),
+                &font,
+                &paint,
+            );
+        }
+    }
+
+    for (i, (_, board)) in boards.iter().enumerate() {
+        let top = i as f32 * section_height + title_height;
+        let drawn = draw_frame(&board_layout, board, tone)?;
+        frame.paste(&drawn, 0, top.round() as usize);
+        drawn.recycle();
+    }
+
+    output.encode(frame)
+}
+
+/// A frame with nothing but `message`, centered in the layout's font.
+pub fn draw_message(layout: &Layout, message: &str, output: &Output) -> eyre::Result<Vec<u8>> {
+    let font = load_font(&layout.font)?;
+    draw_centered(layout, message, &font, output)
+}
+
+/// The quiet-hours board: just the time, large, on an otherwise blank frame
+/// so the panel isn't left holding a stale board overnight.
+pub fn draw_night(layout: &Layout, time: &str, output: &Output) -> eyre::Result<Vec<u8>> {
+    let spec = FontSpec {
+        family: layout.font.family.clone(),
+        size: layout.font.size * 3.0,
+    };
+    let font = load_font(&spec)?;
+    draw_centered(layout, time, &font, output)
+}
+
+fn draw_centered(
+    layout: &Layout,
+    message: &str,
+    font: &Font,
+    output: &Output,
+) -> eyre::Result<Vec<u8>> {
+    draw_with(layout, output, |canvas| {
+        let tone = &output.tone;
+        canvas.clear(tone.color(1.0));
+
+        let paint = tone.paint(0.0);
+        let rect = Rect::from_wh(layout.width, layout.height);
+        let message = ellipsize(message, rect.width() - 40.0, font, &paint);
+        text::draw_align(
+            canvas,
+            &message,
+            (rect.center_x(), centered_baseline(rect, font)),
+            font,
+            &paint,
+            Align::Center,
+        );
+
+        Ok(())
+    })
+}
+
+pub fn draw_frame(layout: &Layout, board: &Board, tone: &Tone) -> eyre::Result<Frame> {
+    let mut frame = Frame::new(
+        layout.width.round() as usize,
+        layout.height.round() as usize,
+    );
+    {
+        let info = frame.image_info();
+        let canvas = Canvas::from_raster_direct(&info, &mut frame.pixels, frame.width, None)
+            .ok_or(eyre!("skia canvas"))?;
+        draw_board(&canvas, layout, board, tone)?;
+    }
+
+    Ok(frame)
+}
+
+/// Every panel of `layout`, filled in from `board`.
+fn draw_board(canvas: &Canvas, layout: &Layout, board: &Board, tone: &Tone) -> eyre::Result<()> {
+    canvas.clear(tone.color(1.0));
+
+    let default_font = load_font(&layout.font)?;
+    let panel_font = |spec: &Option<FontSpec>| match spec {
+        Some(spec) => load_font(spec),
+        None => Ok(default_font.clone()),
+    };
+
+    let black_paint = tone.paint(0.0);
+    let divider_paint = tone.stroke(&layout.theme.divider);
+    let border_paint = tone.stroke(&layout.theme.border);
+    let now = board.now.unwrap_or_else(Utc::now);
+    let no_journeys = LinesDestinationsToJourneys::new();
+
+    let draw_panel = |panel: &Panel| -> eyre::Result<()> {
+        match panel {
+            Panel::Fill { rect, shade } => {
+                if layout.theme.fills {
+                    canvas.draw_rect(rect.to_rect(), &tone.paint(*shade));
+                }
+            }
+            Panel::Line { from, to } => {
+                if let Some(paint) = &divider_paint {
+                    canvas.draw_line(*from, *to, paint);
+                }
+            }
+            Panel::Text {
+                rect,
+                text,
+                align,
+                font,
+                ..
+            } => {
+                let font = panel_font(font)?;
+                let rect = rect.to_rect();
+                text::draw_align(
+                    canvas,
+                    text,
+                    (align.anchor(rect), centered_baseline(rect, &font)),
+                    &font,
+                    &black_paint,
+                    Align::from(*align),
+                );
+            }
+            Panel::Departures {
+                rect,
+                direction,
+                font,
+                row_height,
+                bubble_shade,
+                min_font_size,
+                overflow,
+                ..
+            } => {
+                if let Some(error) = board.errors.get("departures") {
+                    return Err(eyre!("{error}"));
+                }
+
+                let style = RowStyle {
+                    font: panel_font(font)?,
+                    min_font_size: *min_font_size,
+                    row_height: *row_height,
+                    text: black_paint.clone(),
+                    bubble: tone.paint(*bubble_shade),
+                    tone: *tone,
+                    separator: tone.stroke(&layout.theme.row_separator),
+                    upcoming: layout.upcoming(panel),
+                    dimmed: tone.paint(0.6),
+                    imminent: layout.imminent_style,
+                    inverted: tone.paint(0.0),
+                    inverted_text: tone.paint(1.0),
+                    overflow: *overflow,
+                };
+                let lines_destinations_to_journeys =
+                    board.departures.get(direction).unwrap_or(&no_journeys);
+
+                draw_times(
+                    canvas,
+                    lines_destinations_to_journeys,
+                    rect.to_rect(),
+                    &style,
+                    now,
+                    board,
+                );
+                if let Some(paint) = &border_paint {
+                    canvas.draw_rect(rect.to_rect(), paint);
+                }
+            }
+            Panel::LeaveNow { rect, font } => {
+                let upcoming = layout.upcoming(panel);
+                let next = departures::next_catchable(
+                    &board.departures,
+                    now,
+                    &upcoming,
+                    &board.walk_times,
+                );
+                draw_leave_now(
+                    canvas,
+                    next,
+                    upcoming.min_minutes,
+                    rect.to_rect(),
+                    &panel_font(font)?,
+                    tone,
+                );
+            }
+            Panel::Banner { rect, font } => {
+                draw_banner(canvas, board, rect.to_rect(), &panel_font(font)?, tone);
+            }
+            Panel::Weather { rect, font } => {
+                if let Some(error) = board.errors.get("weather") {
+                    return Err(eyre!("{error}"));
+                }
+
+                if let Some(weather) = &board.weather {
+                    draw_weather(canvas, weather, rect.to_rect(), &panel_font(font)?, tone);
+                }
+            }
+            Panel::Bikes { rect, font } => {
+                if let Some(error) = board.errors.get("bikes") {
+                    return Err(eyre!("{error}"));
+                }
+
+                if let Some(bikes) = &board.bikes {
+                    let lines: Vec<String> = bikes.iter().map(Availability::label).collect();
+                    draw_lines(canvas, &lines, rect.to_rect(), &panel_font(font)?, tone);
+                }
+            }
+            Panel::Environment { rect, font } => {
+                if let Some(error) = board.errors.get("weather") {
+                    return Err(eyre!("{error}"));
+                }
+
+                if let Some(weather) = &board.weather {
+                    let mut parts = Vec::new();
+                    if let Some(aqi) = weather.aqi {
+                        parts.push(format!("AQI {aqi}"));
+                    }
+                    if let Some(sunrise) = weather.sunrise {
+                        parts.push(format!("Sunrise {}", board.local(sunrise).format("%-H:%M")));
+                    }
+                    if let Some(sunset) = weather.sunset {
+                        parts.push(format!("Sunset {}", board.local(sunset).format("%-H:%M")));
+                    }
+                    draw_aligned(
+                        canvas,
+                        &parts.join(" · "),
+                        rect.to_rect(),
+                        Alignment::Left,
+                        &panel_font(font)?,
+                        &black_paint,
+                    );
+                }
+            }
+            Panel::Clock {
+                rect,
+                align,
+                font,
+                twelve_hour,
+            } => {
+                let format = if *twelve_hour { "%-I:%M %p" } else { "%H:%M" };
+                let time = board.local(now).format(format).to_string();
+                draw_aligned(
+                    canvas,
+                    &time,
+                    rect.to_rect(),
+                    *align,
+                    &panel_font(font)?,
+                    &black_paint,
+                );
+            }
+            Panel::Date {
+                rect,
+                align,
+                font,
+                format,
+            } => {
+                // An invalid format fails to display rather than to parse.
+                let mut date = String::new();
+                write!(date, "{}", board.local(now).format(format))
+                    .map_err(|_| eyre!("invalid date format {format:?}"))?;
+                draw_aligned(
+                    canvas,
+                    &date,
+                    rect.to_rect(),
+                    *align,
+                    &panel_font(font)?,
+                    &black_paint,
+                );
+            }
+            Panel::Footer {
+                rect,
+                align,
+                font,
+                source,
+            } => {
+                let rect = rect.to_rect();
+                let font = panel_font(font)?;
+                let updated = board.local(now).format("%H:%M");
+                let status = if board.errors.contains_key("departures") {
+                    format!("Update failed {updated}")
+                } else {
+                    format!("Updated {updated}")
+                };
+                let footer = format!("{status} · data: {source}");
+                let paint = tone.paint(0.3);
+                let footer = ellipsize(&footer, rect.width() - 20.0, &font, &paint);
+                draw_aligned(canvas, &footer, rect, *align, &font, &paint);
+            }
+            Panel::Logo {
+                rect,
+                path,
+                depth,
+                dither,
+            } => logo::draw_logo(
+                canvas,
+                path,
+                rect.to_rect(),
+                *depth,
+                *dither,
+                &tone.unthemed(),
+            )?,
+            Panel::QrCode { rect, url } => {
+                if let Some(url) = url {
+                    draw_qr_code(canvas, url, rect.to_rect(), &tone.unthemed())?;
+                }
+            }
+            Panel::Map {
+                rect,
+                north,
+                south,
+                east,
+                west,
+                lines,
+            } => {
+                let area = Area {
+                    north: *north,
+                    south: *south,
+                    east: *east,
+                    west: *west,
+                };
+                draw_map(canvas, board, area, lines, rect.to_rect(), tone);
+            }
+            Panel::Elevators { rect, font } => {
+                if let Some(error) = board.errors.get("elevators") {
+                    return Err(eyre!("{error}"));
+                }
+
+                if let Some(outages) = &board.elevator_outages {
+                    draw_elevators(canvas, outages, rect.to_rect(), &panel_font(font)?, tone);
+                }
+            }
+        }
+
+        Ok(())
+    };
+
+    for panel in &layout.panels {
+        if let Err(e) = draw_panel(panel) {
+            warn!(panel = panel.kind(), "panel failed to draw: {e:#}");
+            if let Some(rect) = panel.bounds() {
+                draw_placeholder(canvas, rect.to_rect(), &default_font, tone);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Route paths in light gray with tracked vehicles on `lines` as black
+/// dots, projected straight onto `rect` so `area` fills it. Vehicles are
+/// left off if their positions couldn't be fetched.
+fn draw_map(canvas: &Canvas, board: &Board, area: Area, lines: &[String], rect: Rect, tone: &Tone) {
+    let project = |(lat, lon): (f64, f64)| {
+        let x = (lon - area.west) / (area.east - area.west);
+        let y = (area.north - lat) / (area.north - area.south);
+        (
+            rect.left + x as f32 * rect.width(),
+            rect.top + y as f32 * rect.height(),
+        )
+    };
+
+    canvas.save();
+    canvas.clip_rect(rect, None, Some(true));
+
+    let mut route = tone.paint(0.6);
+    route.set_style(PaintStyle::Stroke).set_stroke_width(2.0);
+    for shape in board.shapes.0.iter().filter(|shape| shape.overlaps(&area)) {
+        let mut path = Path::new();
+        let mut points = shape.points.iter().map(|&point| project(point));
+        if let Some(start) = points.next() {
+            path.move_to(start);
+        }
+        for point in points {
+            path.line_to(point);
+        }
+        canvas.draw_path(&path, &route);
+    }
+
+    let dot = tone.paint(0.0);
+    let mut halo = tone.paint(1.0);
+    halo.set_style(PaintStyle::Stroke).set_stroke_width(2.0);
+    let vehicles = board.vehicles.iter().filter(|vehicle| {
+        lines.is_empty()
+            || vehicle
+                .line
+                .as_ref()
+                .is_some_and(|line| lines.contains(line))
+    });
+    for location in vehicles.filter_map(|vehicle| vehicle.location) {
+        if area.contains(location) {
+            let center = project(location);
+            canvas.draw_circle(center, 6.0, &dot);
+            canvas.draw_circle(center, 7.0, &halo);
+        }
+    }
+
+    canvas.restore();
+
+    let mut border = tone.paint(0.0);
+    border.set_style(PaintStyle::Stroke);
+    canvas.draw_rect(rect, &border);
+}
+
+/// A small sky icon, then the temperature and chance of rain.
+fn draw_weather(canvas: &Canvas, weather: &Weather, rect: Rect, font: &Font, tone: &Tone) {
+    let size = rect.height() * 0.8;
+    let icon = Rect::from_xywh(rect.left + 10.0, rect.center_y() - size / 2.0, size, size);
+    draw_sky(canvas, weather.sky, icon, tone);
+
+    let paint = tone.paint(0.0);
+    let label = weather.label();
+    let label = ellipsize(&label, rect.right - icon.right - 30.0, font, &paint);
+    text::draw(
+        canvas,
+        &label,
+        (icon.right + 10.0, centered_baseline(rect, font)),
+        font,
+        &paint,
+    );
+}
+
+/// An icon for `sky` drawn from circles and strokes, so it needs no image
+/// assets and stays crisp at any size.
+fn draw_sky(canvas: &Canvas, sky: Sky, rect: Rect, tone: &Tone) {
+    let unit = rect.width() / 10.0;
+    let mut outline = tone.paint(0.0);
+    outline.set_style(PaintStyle::Stroke);
+    outline.set_stroke_width(unit * 0.6);
+    let cloud_fill = tone.paint(0.8);
+
+    let sun = |center: (f32, f32), radius: f32| {
+        canvas.draw_circle(center, radius, &outline);
+        for ray in 0..8 {
+            let angle = ray as f32 * std::f32::consts::FRAC_PI_4;
+            let (sin, cos) = angle.sin_cos();
+            canvas.draw_line(
+                (center.0 + cos * radius * 1.4, center.1 + sin * radius * 1.4),
+                (center.0 + cos * radius * 1.9, center.1 + sin * radius * 1.9),
+                &outline,
+            );
+        }
+    };
+    let cloud = |top: f32| {
+        for (x, y, radius) in [(3.5, 1.5, 2.0), (6.0, 1.0, 2.5), (5.0, 2.5, 2.0)] {
+            let center = (rect.left + x * unit, top + y * unit);
+            canvas.draw_circle(center, radius * unit, &cloud_fill);
+            canvas.draw_circle(center, radius * unit, &outline);
+        }
+    };
+
+    match sky {
+        Sky::Clear => sun((rect.center_x(), rect.center_y()), unit * 2.5),
+        Sky::PartlyCloudy => {
+            sun((rect.left + 3.5 * unit, rect.top + 3.5 * unit), unit * 1.8);
+            cloud(rect.top + 4.0 * unit);
+        }
+        Sky::Cloudy => cloud(rect.top + 2.5 * unit),
+        Sky::Fog => {
+            for row in 0..4 {
+                let y = rect.top + (2.5 + row as f32 * 1.8) * unit;
+                canvas.draw_line((rect.left + unit, y), (rect.right - unit, y), &outline);
+            }
+        }
+        Sky::Rain | Sky::Snow | Sky::Storm => {
+            cloud(rect.top + unit);
+            for drop in 0..3 {
+                let x = rect.left + (3.0 + drop as f32 * 2.0) * unit;
+                let y = rect.top + 6.5 * unit;
+                match sky {
+                    Sky::Snow => canvas.draw_circle((x, y + unit), unit * 0.6, &outline),
+                    Sky::Storm if drop == 1 => {
+                        canvas.draw_line((x + unit, y), (x - unit, y + 2.5 * unit), &outline)
+                    }
+                    _ => canvas.draw_line((x, y), (x - unit * 0.7, y + 2.0 * unit), &outline),
+                };
+            }
+        }
+    }
+}
+
+/// A QR code for `text`, as large as fits in `rect` with whole-pixel modules
+/// so it scans reliably after dithering.
+fn draw_qr_code(canvas: &Canvas, text: &str, rect: Rect, tone: &Tone) -> eyre::Result<()> {
+    // Scanners want a margin of four modules around the code.
+    const QUIET_ZONE: usize = 4;
+
+    let code = QrCode::new(text.as_bytes()).map_err(|e| eyre!("QR code for {text:?}: {e}"))?;
+    let modules = code.width();
+    let side = rect.width().min(rect.height());
+    let module = (side / (modules + 2 * QUIET_ZONE) as f32).floor();
+    if module < 1.0 {
+        return Err(eyre!("QR code for {text:?} doesn't fit"));
+    }
+
+    let size = module * (modules + 2 * QUIET_ZONE) as f32;
+    let left = (rect.left + (rect.width() - size) / 2.0).round();
+    let top = (rect.top + (rect.height() - size) / 2.0).round();
+    canvas.draw_rect(Rect::from_xywh(left, top, size, size), &tone.paint(1.0));
+
+    let dark = tone.paint(0.0);
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color != qrcode::Color::Dark {
+            continue;
+        }
+        let (x, y) = (i % modules + QUIET_ZONE, i / modules + QUIET_ZONE);
+        canvas.draw_rect(
+            Rect::from_xywh(
+                left + x as f32 * module,
+                top + y as f32 * module,
+                module,
+                module,
+            ),
+            &dark,
+        );
+    }
+
+    Ok(())
+}
+
+/// A small glyph for how a line travels, filling the square `rect`.
+fn draw_mode(canvas: &Canvas, mode: Mode, rect: Rect, tone: &Tone) {
+    let unit = rect.width() / 10.0;
+    let at = |x: f32, y: f32| (rect.left + x * unit, rect.top + y * unit);
+    let area = |left: f32, top: f32, right: f32, bottom: f32| {
+        Rect::new(
+            rect.left + left * unit,
+            rect.top + top * unit,
+            rect.left + right * unit,
+            rect.top + bottom * unit,
+        )
+    };
+
+    let fill = tone.paint(0.0);
+    let cutout = tone.paint(1.0);
+    let mut outline = tone.paint(0.0);
+    outline.set_style(PaintStyle::Stroke);
+    outline.set_stroke_width(unit * 0.8);
+
+    match mode {
+        Mode::Bus => {
+            canvas.draw_round_rect(area(1.0, 1.0, 9.0, 7.5), unit, unit, &fill);
+            canvas.draw_rect(area(2.0, 2.0, 8.0, 4.5), &cutout);
+            canvas.draw_circle(at(2.5, 6.2), unit * 0.5, &cutout);
+            canvas.draw_circle(at(7.5, 6.2), unit * 0.5, &cutout);
+            canvas.draw_circle(at(3.0, 8.3), unit, &fill);
+            canvas.draw_circle(at(7.0, 8.3), unit, &fill);
+        }
+        Mode::LightRail => {
+            canvas.draw_line(at(5.0, 2.0), at(5.0, 0.7), &outline);
+            canvas.draw_line(at(3.5, 0.7), at(6.5, 0.7), &outline);
+            canvas.draw_round_rect(area(1.5, 2.0, 8.5, 8.0), unit * 1.5, unit * 1.5, &fill);
+            canvas.draw_rect(area(2.5, 3.0, 7.5, 5.0), &cutout);
+            canvas.draw_line(at(1.0, 9.3), at(9.0, 9.3), &outline);
+        }
+        Mode::Rail => {
+            canvas.draw_round_rect(area(2.0, 0.5, 8.0, 7.5), unit * 2.0, unit * 2.0, &fill);
+            canvas.draw_rect(area(3.0, 2.0, 7.0, 4.0), &cutout);
+            canvas.draw_circle(at(3.5, 6.0), unit * 0.6, &cutout);
+            canvas.draw_circle(at(6.5, 6.0), unit * 0.6, &cutout);
+            canvas.draw_line(at(3.5, 7.5), at(2.0, 9.5), &outline);
+            canvas.draw_line(at(6.5, 7.5), at(8.0, 9.5), &outline);
+        }
+        Mode::Ferry => {
+            canvas.draw_rect(area(3.5, 2.0, 7.0, 4.5), &fill);
+            let mut hull = Path::new();
+            hull.move_to(at(1.0, 4.5))
+                .line_to(at(9.0, 4.5))
+                .line_to(at(7.5, 7.0))
+                .line_to(at(2.5, 7.0))
+                .close();
+            canvas.draw_path(&hull, &fill);
+            canvas.draw_line(at(1.0, 8.7), at(9.0, 8.7), &outline);
+        }
+    }
+}
+
+/// `text` vertically centered in `rect`, aligned a little in from its edges.
+fn draw_aligned(
+    canvas: &Canvas,
+    text: &str,
+    rect: Rect,
+    align: Alignment,
+    font: &Font,
+    paint: &Paint,
+) {
+    text::draw_align(
+        canvas,
+        text,
+        (
+            align.anchor(rect.with_inset((10.0, 0.0))),
+            centered_baseline(rect, font),
+        ),
+        font,
+        paint,
+        Align::from(align),
+    );
+}
+
+/// A compact note drawn in place of a panel that couldn't be drawn, so one
+/// failing panel doesn't take the rest of the board down with it.
+fn draw_placeholder(canvas: &Canvas, rect: Rect, font: &Font, tone: &Tone) {
+    let paint = tone.paint(0.5);
+    let mut font = font.clone();
+    font.set_size(font.size().min(rect.height() * 0.6));
+
+    let message = ellipsize("unavailable", rect.width(), &font, &paint);
+    canvas.draw_str_align(
+        message.as_ref(),
+        (rect.center_x(), centered_baseline(rect, &font)),
+        &font,
+        &paint,
+        Align::Center,
+    );
+}
+
+/// A black strip telling you when to leave for `next`, which is "now" once
+/// it's within `min_minutes`. Blank when nothing can be caught.
+fn draw_leave_now(
+    canvas: &Canvas,
+    next: Option<(&str, &str, Arrival)>,
+    min_minutes: i64,
+    rect: Rect,
+    font: &Font,
+    tone: &Tone,
+) {
+    let Some((line, destination, arrival)) = next else {
+        return;
+    };
+
+    let when = if arrival.minutes <= min_minutes {
+        "Leave now".to_owned()
+    } else {
+        format!("Leave in {} min", arrival.minutes)
+    };
+    let message = format!("{when} for the {line} → {destination}");
+
+    let text_paint = tone.paint(1.0);
+    canvas.draw_rect(rect, &tone.paint(0.0));
+    let message = ellipsize(&message, rect.width() - 40.0, font, &text_paint);
+    text::draw_align(
+        canvas,
+        &message,
+        (rect.center_x(), centered_baseline(rect, font)),
+        font,
+        &text_paint,
+        Align::Center,
+    );
+}
+
+/// One line per station with an elevator out, or a single line saying
+/// they're all working.
+fn draw_elevators(canvas: &Canvas, outages: &[String], rect: Rect, font: &Font, tone: &Tone) {
+    let lines = if outages.is_empty() {
+        vec!["All elevators in service".to_owned()]
+    } else {
+        outages
+            .iter()
+            .map(|station| format!("Elevator out: {station}"))
+            .collect()
+    };
+
+    draw_lines(canvas, &lines, rect, font, tone);
+}
+
+/// `lines` from the top of `rect`, one per line of `font`, leaving off any
+/// that don't fit.
+fn draw_lines(canvas: &Canvas, lines: &[String], rect: Rect, font: &Font, tone: &Tone) {
+    let paint = tone.paint(0.0);
+    let line_height = font.spacing();
+    let mut y = rect.top + line_height;
+    for line in lines {
+        if y > rect.bottom {
+            break;
+        }
+        let line = ellipsize(line, rect.width() - 40.0, font, &paint);
+        text::draw(canvas, &line, (rect.left + 20.0, y), font, &paint);
+        y += line_height;
+    }
+}
+
+/// Every annotation on one line, styled after the most severe of them.
+/// Critical annotations invert the banner so they stand out on e-ink. With no
+/// annotations, service alerts are shown instead, one per refresh.
+fn draw_banner(canvas: &Canvas, board: &Board, rect: Rect, font: &Font, tone: &Tone) {
+    let annotations = &board.annotations;
+    let (severity, message) = match annotations.first() {
+        Some(most_severe) => (
+            most_severe.severity,
+            annotations
+                .iter()
+                .map(|annotation| annotation.text.as_str())
+                .collect::<Vec<_>>()
+                .join(" · "),
+        ),
+        // Service alerts take turns, one per refresh.
+        None if !board.alerts.is_empty() => {
+            let alerts = &board.alerts;
+            let alert = &alerts[board.page % alerts.len()];
+            let message = if alerts.len() > 1 {
+                format!(
+                    "{} ({}/{})",
+                    alert.text,
+                    board.page % alerts.len() + 1,
+                    alerts.len()
+                )
+            } else {
+                alert.text.clone()
+            };
+            (Severity::Warning, message)
+        }
+        None => return,
+    };
+
+    let (background, text_paint) = match severity {
+        Severity::Info => (tone.paint(0.9), tone.paint(0.0)),
+        Severity::Warning => (tone.paint(0.7), tone.paint(0.0)),
+        Severity::Critical => (tone.paint(0.0), tone.paint(1.0)),
+    };
+
+    canvas.draw_rect(rect, &background);
+
+    let message = ellipsize(&message, rect.width() - 40.0, font, &text_paint);
+    text::draw(
+        canvas,
+        &message,
+        (rect.left + 20.0, centered_baseline(rect, font)),
+        font,
+        &text_paint,
+    );
+}
+
+/// `arrivals` as written by [`departures::times_str`], starting at `(x, y)`,
+/// with cancelled runs struck through, any too soon to catch dimmed, and any
+/// imminent ones emphasized.
+fn draw_arrivals(
+    canvas: &Canvas,
+    arrivals: &[Arrival],
+    (x, y): (f32, f32),
+    font: &Font,
+    style: &RowStyle,
+) {
+    let mut bold = font.clone();
+    bold.set_embolden(true);
+
+    // Draws `text` at `x` and returns where the next token starts.
+    let draw = |token: &str, x: f32, font: &Font, paint: &Paint| {
+        text::draw(canvas, token, (x, y), font, paint);
+        x + text::measure(token, font, paint)
+    };
+
+    let mut x = x;
+    for (i, arrival) in arrivals.iter().enumerate() {
+        if i > 0 {
+            x = draw(", ", x, font, &style.text);
+        }
+
+        let label = arrival.label();
+        x = if arrival.cancelled {
+            let bounds = text_bounds(&label, (x, y), font, &style.text);
+            let strike_y = bounds.center_y();
+            canvas.draw_line(
+                (bounds.left, strike_y),
+                (bounds.right, strike_y),
+                &style.text,
+            );
+            draw(&label, x, font, &style.text)
+        } else if arrival.too_soon {
+            draw(&label, x, font, &style.dimmed)
+        } else if arrival.imminent && style.imminent == Emphasis::Bold {
+            draw(&label, x, &bold, &style.text)
+        } else if arrival.imminent {
+            let bounds = text_bounds(&label, (x, y), font, &style.text).with_outset((3.0, 3.0));
+            canvas.draw_round_rect(bounds, 4.0, 4.0, &style.inverted);
+            draw(&label, x, font, &style.inverted_text)
+        } else {
+            draw(&label, x, font, &style.text)
+        };
+
+        if let Some(crowding) = arrival.crowding {
+            x = draw_crowding(canvas, crowding, (x, y), font, &style.text);
+        }
+    }
+    draw(" min", x, font, &style.text);
+}
+
+/// How wide [`draw_arrivals`] draws `arrivals`, with imminent ones measured
+/// in bold where they're drawn that way.
+fn arrivals_width(arrivals: &[Arrival], font: &Font, style: &RowStyle) -> f32 {
+    let mut bold = font.clone();
+    bold.set_embolden(true);
+    let measure = |token: &str, font: &Font| text::measure(token, font, &style.text);
+
+    let mut width = measure(" min", font);
+    for (i, arrival) in arrivals.iter().enumerate() {
+        if i > 0 {
+            width += measure(", ", font);
+        }
+
+        let emboldened = arrival.imminent
+            && !arrival.cancelled
+            && !arrival.too_soon
+            && style.imminent == Emphasis::Bold;
+        width += measure(&arrival.label(), if emboldened { &bold } else { font });
+        if arrival.crowding.is_some() {
+            width += crowding_width(font);
+        }
+    }
+
+    width
+}
+
+/// Stops shown on a progress track; vehicles further away wait at its start.
+const PROGRESS_STOPS: u32 = 8;
+
+/// A thin track from `left` to `right` along `y`, with a tick for each stop,
+/// the stop itself as a square at the right end, and the vehicle as a dot
+/// `stops_away` stops back from it.
+fn draw_progress(
+    canvas: &Canvas,
+    stops_away: u32,
+    (left, right): (f32, f32),
+    y: f32,
+    paint: &Paint,
+) {
+    let mut stroke = paint.clone();
+    stroke.set_style(PaintStyle::Stroke).set_stroke_width(1.5);
+    canvas.draw_line((left, y), (right, y), &stroke);
+
+    let spacing = (right - left) / PROGRESS_STOPS as f32;
+    for stop in 1..PROGRESS_STOPS {
+        let x = right - spacing * stop as f32;
+        canvas.draw_line((x, y - 2.0), (x, y + 2.0), &stroke);
+    }
+    canvas.draw_rect(Rect::from_xywh(right - 3.0, y - 3.0, 6.0, 6.0), paint);
+
+    let x = right - spacing * stops_away.min(PROGRESS_STOPS) as f32;
+    let mut fill = paint.clone();
+    fill.set_style(PaintStyle::Fill);
+    canvas.draw_circle((x, y), 4.0, &fill);
+}
+
+/// The label shown for a platform or track: bare numbers get a "Plat"
+/// prefix, names such as "NB" are shown as given.
+fn platform_label(platform: &str) -> String {
+    match platform.chars().all(|c| c.is_ascii_digit()) {
+        true => format!("Plat {platform}"),
+        false => platform.to_owned(),
+    }
+}
+
+/// A platform label in a small outlined box ending at `right` on the
+/// baseline `y`. Returns its width, including the gap after it.
+fn draw_platform(
+    canvas: &Canvas,
+    label: &str,
+    (right, y): (f32, f32),
+    font: &Font,
+    paint: &Paint,
+) -> f32 {
+    let mut small = font.clone();
+    small.set_size(font.size() * 0.6);
+    let width = text::measure(label, &small, paint);
+    let left = right - 15.0 - width;
+    let baseline = y - font.size() * 0.15;
+
+    let bounds = text_bounds(label, (left, baseline), &small, paint).with_outset((4.0, 4.0));
+    let mut outline = paint.clone();
+    outline.set_style(PaintStyle::Stroke).set_stroke_width(1.5);
+    canvas.draw_rect(bounds, &outline);
+    text::draw(canvas, label, (left, baseline), &small, paint);
+
+    right - bounds.left
+}
+
+/// Width of the crowding glyph for `font`, including the gap before it.
+fn crowding_width(font: &Font) -> f32 {
+    font.size() * (0.15 + 3.0 * 0.12 + 2.0 * 0.08)
+}
+
+/// Three rising bars, `crowding` of them filled, sitting on the baseline at
+/// `(x, y)`. Returns where the next token starts.
+fn draw_crowding(
+    canvas: &Canvas,
+    crowding: u8,
+    (x, y): (f32, f32),
+    font: &Font,
+    paint: &Paint,
+) -> f32 {
+    let size = font.size();
+    let (bar_width, gap) = (size * 0.12, size * 0.08);
+
+    let mut outline = paint.clone();
+    outline.set_style(PaintStyle::Stroke);
+
+    let mut left = x + size * 0.15;
+    for bar in 1..=3u8 {
+        let height = size * (0.2 + 0.15 * bar as f32);
+        let rect = Rect::new(left, y - height, left + bar_width, y);
+        canvas.draw_rect(rect, if bar <= crowding { paint } else { &outline });
+        left += bar_width + gap;
+    }
+
+    x + crowding_width(font)
+}
+
+fn draw_times(
+    canvas: &Canvas,
+    lines_destinations_to_journeys: &LinesDestinationsToJourneys,
+    rect: Rect,
+    style: &RowStyle,
+    now: DateTime<Utc>,
+    board: &Board,
+) {
+    let black_paint = &style.text;
+    let line_id_bubble_paint = &style.bubble;
+    let x1 = rect.left;
+    let x2 = rect.right;
+
+    let capacity = (rect.height() / style.row_height).floor() as usize;
+    // Not even one row fits, so nothing is drawn rather than spilling out of
+    // the panel.
+    if capacity == 0 {
+        return;
+    }
+
+    // Rows are sorted so that pages hold the same rows from one refresh to
+    // the next.
+    let mut rows: Vec<_> = lines_destinations_to_journeys
+        .iter()
+        .map(|(key, journeys)| {
+            let arrivals = departures::arrivals(journeys, now, &style.upcoming, &board.walk_times);
+            (key, arrivals)
+        })
+        .collect();
+    rows.sort_by(|(a, _), (b, _)| {
+        departures::row_key(a, &board.line_order).cmp(&departures::row_key(b, &board.line_order))
+    });
+
+    // When there are more rows than fit, the last row becomes a footer and
+    // either successive refreshes cycle through pages of the rest, or the
+    // rows departing soonest are kept and the rest only counted. A panel
+    // with room for one row only shows the footer.
+    let overflowing = rows.len() > capacity;
+    let per_page = if overflowing { capacity - 1 } else { capacity };
+    if overflowing && style.overflow == Overflow::Soonest {
+        // Ranked by the first run that's still going; a row with only
+        // cancellations goes last.
+        rows.sort_by_key(|(_, arrivals)| {
+            arrivals
+                .iter()
+                .find(|arrival| !arrival.cancelled)
+                .map_or(i64::MAX, |arrival| arrival.arrival_minutes)
+        });
+        rows.truncate(per_page);
+        rows.sort_by(|(a, _), (b, _)| {
+            departures::row_key(a, &board.line_order)
+                .cmp(&departures::row_key(b, &board.line_order))
+        });
+    }
+    let pages = match per_page {
+        0 => 1,
+        per_page => rows.len().div_ceil(per_page).max(1),
+    };
+    let page = board.page % pages;
+    let page_rows = &rows[page * per_page..((page + 1) * per_page).min(rows.len())];
+
+    let mut y = rect.top + style.row_height - 10.0;
+    for (key, arrivals) in page_rows {
+        let (line_id, destination) = key;
+        let times_str = departures::times_str(arrivals);
+        let font = &style.fitted_font(
+            &[line_id.as_str(), destination.as_str(), times_str.as_str()],
+            rect.width(),
+        );
+
+        let line_paint = board
+            .routes
+            .shades
+            .get(line_id)
+            .map(|&shade| style.tone.paint(shade));
+        let bubble_paint = line_paint.as_ref().unwrap_or(line_id_bubble_paint);
+        let bounds =
+            text_bounds(line_id, (x1 + 20.0, y), font, bubble_paint).with_outset((8.0, 8.0));
+        canvas.draw_round_rect(bounds, 24.0, 24.0, bubble_paint);
+        text::draw(canvas, line_id, (x1 + 20.0, y), font, black_paint);
+
+        let times_width = arrivals_width(arrivals, font, style);
+        let mut destination_left = bounds.right + 15.0;
+        if let Some(&mode) = board.routes.modes.get(line_id) {
+            let size = font.size();
+            let icon = Rect::from_xywh(destination_left, y - size * 0.85, size, size);
+            draw_mode(canvas, mode, icon, &style.tone);
+            destination_left = icon.right + 10.0;
+        }
+        let times_left = x2 - 20.0 - times_width;
+        let platform_width = departures::platform(&lines_destinations_to_journeys[*key])
+            .map(|platform| {
+                let label = platform_label(platform);
+                draw_platform(canvas, &label, (times_left, y), font, black_paint)
+            })
+            .unwrap_or(0.0);
+        let destination_width = (times_left - platform_width - 15.0) - destination_left;
+        let destination = ellipsize(destination, destination_width, font, black_paint);
+        text::draw(
+            canvas,
+            &destination,
+            (destination_left, y),
+            font,
+            black_paint,
+        );
+
+        let stops_away = arrivals.first().and_then(|arrival| arrival.stops_away);
+        if let Some(stops_away) = stops_away.filter(|_| style.upcoming.show_progress) {
+            let track = (destination_left, destination_left + destination_width);
+            draw_progress(canvas, stops_away, track, y + 5.0, black_paint);
+        }
+
+        draw_arrivals(canvas, arrivals, (times_left, y), font, style);
+        if let Some(separator) = &style.separator {
+            canvas.draw_line((x1 + 10.0, y + 10.0), (x2 - 10.0, y + 10.0), separator);
+        }
+        y += style.row_height;
+    }
+
+    let hidden = lines_destinations_to_journeys.len() - page_rows.len();
+    if hidden > 0 {
+        let more = format!(
+            "+{hidden} more {}",
+            if hidden == 1 { "line" } else { "lines" }
+        );
+        let mut font = style.font.clone();
+        font.set_size(style.min_font_size.max(style.font.size() * 0.75));
+        canvas.draw_str(&more, (x1 + 20.0, y), &font, black_paint);
+        if pages > 1 {
+            canvas.draw_str_align(
+                format!("{}/{pages}", page + 1),
+                (x2 - 20.0, y),
+                &font,
+                black_paint,
+                Align::Right,
+            );
+        }
+    }
+}
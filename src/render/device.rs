@@ -0,0 +1,50 @@
+use super::frame::Rotation;
+
+/// An e-reader the board can be rendered for.
+///
+/// `width` and `height` are the panel's native pixel dimensions as the device
+/// reads its image, which for the e-readers below is portrait.
+#[derive(Debug, Clone, Copy)]
+pub struct Device {
+    pub name: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub dpi: u32,
+    /// How to turn a board drawn in the other orientation to match the panel.
+    pub rotation: Rotation,
+    /// Bits of grayscale the panel can display.
+    pub gray_depth: u8,
+}
+
+pub const DEVICES: &[Device] = &[
+    Device {
+        name: "kindle_4",
+        width: 600,
+        height: 800,
+        dpi: 167,
+        rotation: Rotation::Clockwise90,
+        gray_depth: 4,
+    },
+    Device {
+        name: "kindle_pw3",
+        width: 1072,
+        height: 1448,
+        dpi: 300,
+        rotation: Rotation::Clockwise90,
+        gray_depth: 4,
+    },
+    Device {
+        name: "kobo_clara",
+        width: 1072,
+        height: 1448,
+        dpi: 300,
+        rotation: Rotation::Clockwise90,
+        gray_depth: 4,
+    },
+];
+
+impl Device {
+    pub fn find(name: &str) -> Option<&'static Device> {
+        DEVICES.iter().find(|device| device.name == name)
+    }
+}
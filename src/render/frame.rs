@@ -0,0 +1,668 @@
+use std::sync::Mutex;
+
+use eyre::eyre;
+use serde::Deserialize;
+use skia_safe::{images, AlphaType, ColorType, Data, EncodedImageFormat, ImageInfo};
+
+/// A rendered grayscale image: one byte per pixel, rows stored top to bottom
+/// with no padding.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+/// Pixel buffers of finished frames, kept for the next frame rather than
+/// freed, since a board is the same size from one refresh to the next.
+static SPARE_PIXELS: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+/// The most buffers kept spare, enough for a few renders at once.
+const MAX_SPARE_FRAMES: usize = 4;
+
+/// A buffer of `len` black pixels, reusing a spare one if there is one.
+fn spare_pixels(len: usize) -> Vec<u8> {
+    let spare = SPARE_PIXELS.lock().unwrap().pop();
+    match spare {
+        Some(mut pixels) => {
+            pixels.clear();
+            pixels.resize(len, 0);
+            pixels
+        }
+        None => vec![0; len],
+    }
+}
+
+/// How hard to work at shrinking PNGs.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PngCompression {
+    Fast,
+    #[default]
+    Default,
+    /// Smallest files, for devices on weak Wi-Fi, at some cost in CPU.
+    Best,
+}
+
+impl From<PngCompression> for png::Compression {
+    fn from(compression: PngCompression) -> Self {
+        match compression {
+            PngCompression::Fast => png::Compression::Fast,
+            PngCompression::Default => png::Compression::Default,
+            PngCompression::Best => png::Compression::Best,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct PngOptions {
+    pub compression: PngCompression,
+    /// Store 1, 2 or 4 bits per pixel when the frame has been quantized to
+    /// that depth, rather than a full byte. Frames at other depths are
+    /// stored at 8 bits.
+    pub pack: bool,
+}
+
+/// How quantization spreads the error between a pixel's true shade and the
+/// gray level it's reduced to.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Dither {
+    /// Diffuse the error into neighbouring pixels. Best for photos and logos.
+    FloydSteinberg,
+    /// Threshold against a repeating Bayer pattern. Noisier, but stable from
+    /// frame to frame, which avoids e-ink ghosting.
+    Ordered,
+}
+
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Clockwise rotation applied to a frame after it has been drawn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+impl Rotation {
+    pub fn from_degrees(degrees: u16) -> Option<Self> {
+        match degrees % 360 {
+            0 => Some(Rotation::None),
+            90 => Some(Rotation::Clockwise90),
+            180 => Some(Rotation::Clockwise180),
+            270 => Some(Rotation::Clockwise270),
+            _ => None,
+        }
+    }
+
+    pub fn degrees(self) -> u16 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Clockwise90 => 90,
+            Rotation::Clockwise180 => 180,
+            Rotation::Clockwise270 => 270,
+        }
+    }
+
+    /// This rotation followed by `other`.
+    pub fn then(self, other: Rotation) -> Self {
+        Self::from_degrees(self.degrees() + other.degrees()).expect("sum of right angles")
+    }
+
+    /// Whether the rotation swaps the frame's width and height.
+    pub fn is_sideways(self) -> bool {
+        matches!(self, Rotation::Clockwise90 | Rotation::Clockwise270)
+    }
+}
+
+impl Frame {
+    /// A black frame of the given size.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: spare_pixels(width * height),
+        }
+    }
+
+    /// Give the frame's pixel buffer back for the next frame to reuse.
+    pub fn recycle(self) {
+        let mut spare = SPARE_PIXELS.lock().unwrap();
+        if spare.len() < MAX_SPARE_FRAMES {
+            spare.push(self.pixels);
+        }
+    }
+
+    pub fn image_info(&self) -> ImageInfo {
+        ImageInfo::new(
+            (self.width as i32, self.height as i32),
+            ColorType::Gray8,
+            AlphaType::Opaque,
+            None,
+        )
+    }
+
+    /// Copy `other` onto this frame with its top left corner at `(x, y)`,
+    /// clipping whatever falls outside.
+    pub fn paste(&mut self, other: &Frame, x: usize, y: usize) {
+        let width = other.width.min(self.width.saturating_sub(x));
+        for row in 0..other.height.min(self.height.saturating_sub(y)) {
+            let from = row * other.width;
+            let to = (y + row) * self.width + x;
+            self.pixels[to..to + width].copy_from_slice(&other.pixels[from..from + width]);
+        }
+    }
+
+    pub fn rotated(self, rotation: Rotation) -> Self {
+        let (width, height) = (self.width, self.height);
+        if rotation == Rotation::None {
+            return self;
+        }
+
+        let (new_width, new_height) = if rotation.is_sideways() {
+            (height, width)
+        } else {
+            (width, height)
+        };
+
+        let mut pixels = spare_pixels(self.pixels.len());
+        for y in 0..height {
+            for x in 0..width {
+                let (new_x, new_y) = match rotation {
+                    Rotation::None => (x, y),
+                    Rotation::Clockwise90 => (height - 1 - y, x),
+                    Rotation::Clockwise180 => (width - 1 - x, height - 1 - y),
+                    Rotation::Clockwise270 => (y, width - 1 - x),
+                };
+                pixels[new_y * new_width + new_x] = self.pixels[y * width + x];
+            }
+        }
+        self.recycle();
+
+        Self {
+            width: new_width,
+            height: new_height,
+            pixels,
+        }
+    }
+
+    /// Reduce the frame to `2^bits` evenly spaced gray levels, matching what
+    /// the target panel can actually display. Without dithering each pixel
+    /// snaps to its nearest level, which keeps text crisp but flattens shading.
+    pub fn quantize(&mut self, bits: u8, dither: Option<Dither>) {
+        if bits >= 8 {
+            return;
+        }
+
+        let max_level = ((1u32 << bits) - 1) as f32;
+        let nearest = |value: f32| {
+            let level = (value.clamp(0.0, 255.0) / 255.0 * max_level).round();
+            (level / max_level * 255.0).round()
+        };
+
+        match dither {
+            None => {
+                for pixel in &mut self.pixels {
+                    *pixel = nearest(*pixel as f32) as u8;
+                }
+            }
+            Some(Dither::Ordered) => {
+                let step = 255.0 / max_level;
+                for y in 0..self.height {
+                    for x in 0..self.width {
+                        let threshold = (BAYER_4X4[y % 4][x % 4] as f32 + 0.5) / 16.0 - 0.5;
+                        let pixel = &mut self.pixels[y * self.width + x];
+                        *pixel = nearest(*pixel as f32 + threshold * step) as u8;
+                    }
+                }
+            }
+            Some(Dither::FloydSteinberg) => {
+                let (width, height) = (self.width, self.height);
+                let mut values: Vec<f32> = self.pixels.iter().map(|&p| p as f32).collect();
+
+                for y in 0..height {
+                    for x in 0..width {
+                        let i = y * width + x;
+                        let old = values[i];
+                        let new = nearest(old);
+                        self.pixels[i] = new as u8;
+
+                        let error = old - new;
+                        if x + 1 < width {
+                            values[i + 1] += error * 7.0 / 16.0;
+                        }
+                        if y + 1 < height {
+                            if x > 0 {
+                                values[i + width - 1] += error * 3.0 / 16.0;
+                            }
+                            values[i + width] += error * 5.0 / 16.0;
+                            if x + 1 < width {
+                                values[i + width + 1] += error * 1.0 / 16.0;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The pixels as they are, one byte per pixel and `width` bytes per row,
+    /// for scripts that copy them straight into a framebuffer.
+    pub fn encode_raw(&self) -> Vec<u8> {
+        self.pixels.clone()
+    }
+
+    /// Encode as an 8-bit grayscale BMP, which `eips` on older Kindles shows
+    /// faster than it decodes a PNG.
+    pub fn encode_bmp(&self, dpi: Option<u32>) -> Vec<u8> {
+        const FILE_HEADER: u32 = 14;
+        const INFO_HEADER: u32 = 40;
+        const PALETTE: u32 = 256 * 4;
+
+        // Rows are padded to a multiple of four bytes.
+        let stride = self.width.next_multiple_of(4);
+        let image_size = (stride * self.height) as u32;
+        let offset = FILE_HEADER + INFO_HEADER + PALETTE;
+        let pixels_per_meter = dpi.map_or(0, |dpi| (dpi as f64 / 0.0254).round() as u32);
+
+        let mut bmp = Vec::with_capacity((offset + image_size) as usize);
+        bmp.extend_from_slice(b"BM");
+        bmp.extend_from_slice(&(offset + image_size).to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+        bmp.extend_from_slice(&offset.to_le_bytes());
+
+        bmp.extend_from_slice(&INFO_HEADER.to_le_bytes());
+        bmp.extend_from_slice(&(self.width as i32).to_le_bytes());
+        bmp.extend_from_slice(&(self.height as i32).to_le_bytes());
+        // One plane, eight bits per pixel, uncompressed.
+        bmp.extend_from_slice(&1u16.to_le_bytes());
+        bmp.extend_from_slice(&8u16.to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+        bmp.extend_from_slice(&image_size.to_le_bytes());
+        bmp.extend_from_slice(&pixels_per_meter.to_le_bytes());
+        bmp.extend_from_slice(&pixels_per_meter.to_le_bytes());
+        bmp.extend_from_slice(&256u32.to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+
+        for gray in 0..=255u8 {
+            bmp.extend_from_slice(&[gray, gray, gray, 0]);
+        }
+
+        // Stored bottom row first.
+        for row in self.pixels.chunks(self.width).rev() {
+            bmp.extend_from_slice(row);
+            bmp.resize(bmp.len() + stride - self.width, 0);
+        }
+
+        bmp
+    }
+
+    /// Encode as PNG, tagging the image with `dpi` if given so viewers know
+    /// its physical size. `bits` is the depth the frame was quantized to,
+    /// which lets `options.pack` store 1, 2 or 4 bits per pixel instead of 8.
+    pub fn encode_png(
+        &self,
+        dpi: Option<u32>,
+        options: &PngOptions,
+        bits: u8,
+    ) -> eyre::Result<Vec<u8>> {
+        let bits = if options.pack && matches!(bits, 1 | 2 | 4) {
+            bits
+        } else {
+            8
+        };
+        let depth = match bits {
+            1 => png::BitDepth::One,
+            2 => png::BitDepth::Two,
+            4 => png::BitDepth::Four,
+            _ => png::BitDepth::Eight,
+        };
+
+        let mut png_bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png_bytes, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(depth);
+        encoder.set_compression(options.compression.into());
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.packed(bits))?;
+        writer.finish()?;
+
+        if let Some(dpi) = dpi {
+            insert_png_dpi(&mut png_bytes, dpi);
+        }
+
+        Ok(png_bytes)
+    }
+
+    /// The rows with each pixel reduced to a `bits`-bit level, packed most
+    /// significant first and each row starting on a fresh byte, as PNG
+    /// stores them.
+    fn packed(&self, bits: u8) -> Vec<u8> {
+        if bits >= 8 {
+            return self.pixels.clone();
+        }
+
+        let max_level = (1u32 << bits) - 1;
+        let per_byte = (8 / bits) as usize;
+        let mut packed = Vec::with_capacity(self.height * self.width.div_ceil(per_byte));
+        for row in self.pixels.chunks(self.width) {
+            for chunk in row.chunks(per_byte) {
+                let mut byte = 0u8;
+                for (i, &pixel) in chunk.iter().enumerate() {
+                    let level = (pixel as u32 * max_level + 127) / 255;
+                    byte |= (level as u8) << (8 - bits as usize * (i + 1));
+                }
+                packed.push(byte);
+            }
+        }
+
+        packed
+    }
+
+    /// Encode as JPEG, for clients that would rather save bandwidth than keep
+    /// text edges sharp.
+    pub fn encode_jpeg(&self) -> eyre::Result<Vec<u8>> {
+        self.encode(EncodedImageFormat::JPEG, Some(85))
+    }
+
+    /// Encode as lossless WebP, usually a good deal smaller than the PNG.
+    pub fn encode_webp(&self) -> eyre::Result<Vec<u8>> {
+        // Skia encodes WebP losslessly at quality 100.
+        self.encode(EncodedImageFormat::WEBP, Some(100))
+    }
+
+    fn encode(&self, format: EncodedImageFormat, quality: Option<u32>) -> eyre::Result<Vec<u8>> {
+        let image =
+            images::raster_from_data(&self.image_info(), Data::new_copy(&self.pixels), self.width)
+                .ok_or(eyre!("skia image from frame"))?;
+
+        let encoded = image
+            .encode(None, format, quality)
+            .ok_or(eyre!("skia image encode"))?;
+
+        Ok(encoded.as_bytes().to_owned())
+    }
+}
+
+/// Insert a `pHYs` chunk recording `dpi` directly after the PNG's `IHDR`
+/// chunk, where the spec requires it to appear before any image data.
+fn insert_png_dpi(png: &mut Vec<u8>, dpi: u32) {
+    // 8 byte signature, then IHDR: 4 byte length, 4 byte type, 13 bytes of
+    // data and a 4 byte CRC.
+    const IHDR_END: usize = 8 + 4 + 4 + 13 + 4;
+    const METERS_PER_INCH: f64 = 0.0254;
+
+    let pixels_per_meter = (dpi as f64 / METERS_PER_INCH).round() as u32;
+
+    let mut body = Vec::with_capacity(13);
+    body.extend_from_slice(b"pHYs");
+    body.extend_from_slice(&pixels_per_meter.to_be_bytes());
+    body.extend_from_slice(&pixels_per_meter.to_be_bytes());
+    // Unit specifier: the values above are per meter.
+    body.push(1);
+
+    let mut chunk = Vec::with_capacity(21);
+    chunk.extend_from_slice(&9u32.to_be_bytes());
+    chunk.extend_from_slice(&body);
+    chunk.extend_from_slice(&crc32fast::hash(&body).to_be_bytes());
+
+    png.splice(IHDR_END..IHDR_END, chunk);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A frame of the given size with every pixel set by `shade(x, y)`.
+    fn frame(width: usize, height: usize, shade: impl Fn(usize, usize) -> u8) -> Frame {
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| shade(x, y))
+            .collect();
+        Frame {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    fn mean(frame: &Frame) -> f64 {
+        frame.pixels.iter().map(|&p| p as f64).sum::<f64>() / frame.pixels.len() as f64
+    }
+
+    #[test]
+    fn quantizing_snaps_to_evenly_spaced_levels() {
+        for (bits, levels) in [
+            (1, vec![0_i32, 255]),
+            (2, vec![0, 85, 170, 255]),
+            (4, (0..16).map(|level| level * 17).collect()),
+        ] {
+            let mut ramp = frame(256, 1, |x, _| x as u8);
+            ramp.quantize(bits, None);
+
+            for (shade, &quantized) in ramp.pixels.iter().enumerate() {
+                let nearest = levels
+                    .iter()
+                    .min_by_key(|&&level| (level - shade as i32).abs())
+                    .unwrap();
+                assert_eq!(
+                    quantized as i32, *nearest,
+                    "{bits} bits: {shade} became {quantized}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn eight_bits_are_left_alone() {
+        let mut ramp = frame(256, 1, |x, _| x as u8);
+        ramp.quantize(8, Some(Dither::FloydSteinberg));
+
+        assert!(ramp
+            .pixels
+            .iter()
+            .enumerate()
+            .all(|(x, &p)| p as usize == x));
+    }
+
+    #[test]
+    fn dithering_keeps_the_average_shade() {
+        for bits in [1, 2, 4] {
+            let step = 255.0 / ((1 << bits) - 1) as f64;
+            // Error diffused off the edges is lost, and a 4x4 Bayer pattern
+            // only has 16 thresholds per level step, so can land half of one
+            // off.
+            for (dither, tolerance) in [
+                (Dither::FloydSteinberg, 2.0),
+                (Dither::Ordered, step / 32.0 + 0.5),
+            ] {
+                for shade in [40, 100, 128, 200] {
+                    let mut flat = frame(64, 64, |_, _| shade);
+                    flat.quantize(bits, Some(dither));
+
+                    let drift = (mean(&flat) - shade as f64).abs();
+                    assert!(
+                        drift <= tolerance,
+                        "{dither:?} at {bits} bits drifted {drift:.1} from {shade}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dithering_mixes_levels_where_snapping_flattens() {
+        let mut snapped = frame(16, 16, |_, _| 100);
+        let mut dithered = snapped.clone();
+        snapped.quantize(1, None);
+        dithered.quantize(1, Some(Dither::FloydSteinberg));
+
+        assert!(snapped.pixels.iter().all(|&p| p == 0));
+        assert!(dithered.pixels.contains(&0) && dithered.pixels.contains(&255));
+    }
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn bmp_headers_describe_a_padded_bottom_up_image() {
+        // Five pixels wide, so each row takes three bytes of padding.
+        let frame = frame(5, 3, |x, y| (y * 10 + x) as u8);
+        let bmp = frame.encode_bmp(Some(300));
+
+        let offset = 14 + 40 + 256 * 4;
+        assert_eq!(&bmp[0..2], b"BM");
+        assert_eq!(u32_at(&bmp, 2) as usize, bmp.len());
+        assert_eq!(bmp.len(), offset + 8 * 3);
+        assert_eq!(u32_at(&bmp, 10) as usize, offset);
+
+        assert_eq!(u32_at(&bmp, 14), 40);
+        assert_eq!(u32_at(&bmp, 18), 5);
+        // Positive height: rows run bottom to top.
+        assert_eq!(u32_at(&bmp, 22), 3);
+        assert_eq!(u16_at(&bmp, 26), 1);
+        assert_eq!(u16_at(&bmp, 28), 8);
+        assert_eq!(u32_at(&bmp, 30), 0);
+        assert_eq!(u32_at(&bmp, 34), 8 * 3);
+        assert_eq!(u32_at(&bmp, 38), 11811);
+        assert_eq!(u32_at(&bmp, 42), 11811);
+        assert_eq!(u32_at(&bmp, 46), 256);
+
+        // The palette maps each index to its own gray.
+        assert_eq!(&bmp[54 + 4 * 200..54 + 4 * 201], [200, 200, 200, 0]);
+
+        let rows: Vec<&[u8]> = bmp[offset..].chunks(8).collect();
+        assert_eq!(
+            rows,
+            [
+                &[20, 21, 22, 23, 24, 0, 0, 0][..],
+                &[10, 11, 12, 13, 14, 0, 0, 0][..],
+                &[0, 1, 2, 3, 4, 0, 0, 0][..],
+            ]
+        );
+    }
+
+    #[test]
+    fn bmp_rows_a_multiple_of_four_wide_are_unpadded() {
+        let bmp = frame(8, 2, |_, _| 255).encode_bmp(None);
+
+        assert_eq!(bmp.len(), 14 + 40 + 256 * 4 + 8 * 2);
+        assert_eq!(u32_at(&bmp, 38), 0);
+    }
+
+    /// The bit depth and 8-bit gray pixels of `png`.
+    fn decode_png(png: &[u8]) -> (png::BitDepth, Vec<u8>) {
+        let mut decoder = png::Decoder::new(png);
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info().unwrap();
+        let depth = reader.info().bit_depth;
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        pixels.truncate(info.buffer_size());
+
+        (depth, pixels)
+    }
+
+    #[test]
+    fn packed_pngs_decode_to_the_quantized_pixels() {
+        let options = PngOptions {
+            pack: true,
+            ..PngOptions::default()
+        };
+        for (bits, depth) in [
+            (1, png::BitDepth::One),
+            (2, png::BitDepth::Two),
+            (4, png::BitDepth::Four),
+        ] {
+            // An odd width, so rows end partway through a byte.
+            let mut ramp = frame(11, 3, |x, y| (x * 23 + y * 40) as u8);
+            ramp.quantize(bits, None);
+
+            let (decoded_depth, pixels) =
+                decode_png(&ramp.encode_png(None, &options, bits).unwrap());
+            assert_eq!(decoded_depth, depth);
+            assert_eq!(pixels, ramp.pixels, "{bits} bits");
+        }
+    }
+
+    #[test]
+    fn pngs_stay_eight_bit_unless_packing_a_packable_depth() {
+        let mut ramp = frame(11, 3, |x, y| (x * 23 + y * 40) as u8);
+        ramp.quantize(2, None);
+        let packed = PngOptions {
+            pack: true,
+            ..PngOptions::default()
+        };
+
+        for (options, bits) in [(PngOptions::default(), 2), (packed, 3), (packed, 8)] {
+            let (depth, pixels) = decode_png(&ramp.encode_png(None, &options, bits).unwrap());
+            assert_eq!(depth, png::BitDepth::Eight);
+            assert_eq!(pixels, ramp.pixels);
+        }
+    }
+
+    /// Where the single white pixel of `frame` is.
+    fn marker(frame: &Frame) -> (usize, usize) {
+        let i = frame.pixels.iter().position(|&p| p == 255).unwrap();
+        (i % frame.width, i / frame.width)
+    }
+
+    #[test]
+    fn rotation_moves_the_top_left_corner_clockwise() {
+        // 4 wide and 2 high, white in the top left corner.
+        let corner = || frame(4, 2, |x, y| if (x, y) == (0, 0) { 255 } else { 0 });
+
+        for (rotation, size, at) in [
+            (Rotation::None, (4, 2), (0, 0)),
+            (Rotation::Clockwise90, (2, 4), (1, 0)),
+            (Rotation::Clockwise180, (4, 2), (3, 1)),
+            (Rotation::Clockwise270, (2, 4), (0, 3)),
+        ] {
+            let rotated = corner().rotated(rotation);
+
+            assert_eq!((rotated.width, rotated.height), size, "{rotation:?}");
+            assert_eq!(marker(&rotated), at, "{rotation:?}");
+        }
+    }
+
+    #[test]
+    fn quarter_turns_compose() {
+        let off_center = || frame(5, 3, |x, y| if (x, y) == (1, 2) { 255 } else { 0 });
+        let twice = off_center()
+            .rotated(Rotation::Clockwise90)
+            .rotated(Rotation::Clockwise90);
+        let back = off_center()
+            .rotated(Rotation::Clockwise90)
+            .rotated(Rotation::Clockwise270);
+
+        assert_eq!(
+            twice.pixels,
+            off_center().rotated(Rotation::Clockwise180).pixels
+        );
+        assert_eq!((back.width, back.height), (5, 3));
+        assert_eq!(marker(&back), (1, 2));
+    }
+
+    #[test]
+    fn rotations_add_up_in_degrees() {
+        assert_eq!(
+            Rotation::Clockwise90.then(Rotation::Clockwise270),
+            Rotation::None
+        );
+        assert_eq!(
+            Rotation::Clockwise180.then(Rotation::Clockwise270),
+            Rotation::Clockwise90
+        );
+        assert_eq!(Rotation::from_degrees(450), Some(Rotation::Clockwise90));
+        assert_eq!(Rotation::from_degrees(45), None);
+    }
+}
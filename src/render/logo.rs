@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use eyre::eyre;
+use skia_safe::{
+    images, svg, Canvas, Color, CubicResampler, Data, Image, Paint, Rect, SamplingOptions,
+};
+
+use super::{
+    font_manager,
+    frame::{Dither, Frame},
+    Tone,
+};
+
+/// The image at `path`, drawn to fit `rect` and dithered to `depth` bits.
+///
+/// The logo is drawn into a frame of its own and quantized there, so it gets
+/// the dithering a photo-like image needs even when the rest of the board is
+/// left at full depth for its text.
+pub fn draw_logo(
+    canvas: &Canvas,
+    path: &Path,
+    rect: Rect,
+    depth: u8,
+    dither: Dither,
+    tone: &Tone,
+) -> eyre::Result<()> {
+    let (width, height) = (
+        rect.width().round() as usize,
+        rect.height().round() as usize,
+    );
+    if width == 0 || height == 0 {
+        return Ok(());
+    }
+
+    let bytes =
+        std::fs::read(path).map_err(|e| eyre!("couldn't read logo {}: {e}", path.display()))?;
+
+    let mut frame = Frame::new(width, height);
+    let info = frame.image_info();
+    let logo_canvas = Canvas::from_raster_direct(&info, &mut frame.pixels, frame.width, None)
+        .ok_or(eyre!("skia canvas"))?;
+    logo_canvas.clear(Color::WHITE);
+
+    if path.extension().is_some_and(|ext| ext == "svg") {
+        let mut dom = svg::Dom::from_bytes(&bytes, font_manager().clone())
+            .map_err(|e| eyre!("couldn't parse logo {}: {e:?}", path.display()))?;
+        dom.set_container_size((width as f32, height as f32));
+        dom.render(&logo_canvas);
+    } else {
+        let image = Image::from_encoded(Data::new_copy(&bytes))
+            .ok_or_else(|| eyre!("couldn't decode logo {}", path.display()))?;
+        let scale =
+            (width as f32 / image.width() as f32).min(height as f32 / image.height() as f32);
+        let (fitted_width, fitted_height) =
+            (image.width() as f32 * scale, image.height() as f32 * scale);
+        let fitted = Rect::from_xywh(
+            (width as f32 - fitted_width) / 2.0,
+            (height as f32 - fitted_height) / 2.0,
+            fitted_width,
+            fitted_height,
+        );
+        logo_canvas.draw_image_rect_with_sampling_options(
+            &image,
+            None,
+            fitted,
+            SamplingOptions::from(CubicResampler::mitchell()),
+            &Paint::default(),
+        );
+    }
+    drop(logo_canvas);
+
+    // The board's tone curve applies to the logo as to everything else,
+    // before it's reduced to the panel's grays. The caller keeps it in the
+    // light theme.
+    for pixel in &mut frame.pixels {
+        *pixel = (tone.apply(*pixel as f32 / 255.0) * 255.0).round() as u8;
+    }
+    frame.quantize(depth, Some(dither));
+
+    let image = images::raster_from_data(&frame.image_info(), Data::new_copy(&frame.pixels), width)
+        .ok_or(eyre!("skia image from logo"))?;
+    canvas.draw_image(&image, (rect.left, rect.top), None);
+
+    Ok(())
+}
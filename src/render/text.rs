@@ -0,0 +1,116 @@
+//! Text drawing that falls back to other typefaces, character by character,
+//! when the layout's font has no glyph, so names like "Cañada" or "東京"
+//! don't come out as tofu boxes.
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
+
+use skia_safe::{utils::text_utils::Align, Canvas, Font, FontStyle, Paint, Typeface};
+
+use super::{fallback_typeface, font_manager};
+
+/// The most characters whose fallback typeface is remembered, so an alert in
+/// an unusual script can't grow the cache without end.
+const MAX_FALLBACKS: usize = 1024;
+
+/// The fallback typeface for each character the layout's fonts lack, looked
+/// up once. Like matching a family, matching a character goes through the
+/// platform's font configuration.
+static FALLBACKS: LazyLock<Mutex<HashMap<char, Option<Typeface>>>> = LazyLock::new(Mutex::default);
+
+/// The typeface to draw `c` with: `font`'s own if it has the glyph, otherwise
+/// whichever installed typeface the platform suggests, otherwise the bundled
+/// fallback.
+fn typeface_for(c: char, font: &Font) -> Option<Typeface> {
+    if c.is_whitespace() || c.is_control() || font.unichar_to_glyph(c as i32) != 0 {
+        return None;
+    }
+
+    if let Some(typeface) = FALLBACKS.lock().unwrap().get(&c) {
+        return typeface.clone().or_else(fallback_typeface);
+    }
+
+    let typeface =
+        font_manager().match_family_style_character("", FontStyle::normal(), &[], c as i32);
+    let mut fallbacks = FALLBACKS.lock().unwrap();
+    if fallbacks.len() >= MAX_FALLBACKS {
+        fallbacks.clear();
+    }
+    fallbacks.insert(c, typeface.clone());
+
+    typeface.or_else(fallback_typeface)
+}
+
+/// `text` split into runs that can each be drawn with a single font.
+fn runs<'a>(text: &'a str, font: &Font) -> Vec<(Font, &'a str)> {
+    let mut runs: Vec<(Font, &str)> = Vec::new();
+    let mut start = 0;
+    let mut current: Option<Typeface> = None;
+
+    for (i, c) in text.char_indices() {
+        let typeface = typeface_for(c, font);
+        let same = match (&current, &typeface) {
+            (None, None) => true,
+            (Some(a), Some(b)) => a.unique_id() == b.unique_id(),
+            _ => false,
+        };
+
+        if !same && i > start {
+            runs.push((with_typeface(font, current.take()), &text[start..i]));
+            start = i;
+        }
+        current = typeface;
+    }
+    if start < text.len() {
+        runs.push((with_typeface(font, current), &text[start..]));
+    }
+
+    runs
+}
+
+fn with_typeface(font: &Font, typeface: Option<Typeface>) -> Font {
+    let mut font = font.clone();
+    if let Some(typeface) = typeface {
+        font.set_typeface(typeface);
+    }
+
+    font
+}
+
+/// The advance width of `text`, including any fallback runs.
+pub fn measure(text: &str, font: &Font, paint: &Paint) -> f32 {
+    runs(text, font)
+        .iter()
+        .map(|(font, run)| font.measure_str(run, Some(paint)).0)
+        .sum()
+}
+
+/// Draw `text` with its left end of the baseline at `(x, y)`.
+pub fn draw(canvas: &Canvas, text: &str, (x, y): (f32, f32), font: &Font, paint: &Paint) {
+    let mut x = x;
+    for (font, run) in runs(text, font) {
+        canvas.draw_str(run, (x, y), &font, paint);
+        x += font.measure_str(run, Some(paint)).0;
+    }
+}
+
+/// Draw `text` aligned to `(x, y)` the way `Canvas::draw_str_align` would.
+pub fn draw_align(
+    canvas: &Canvas,
+    text: &str,
+    (x, y): (f32, f32),
+    font: &Font,
+    paint: &Paint,
+    align: Align,
+) {
+    let width = measure(text, font, paint);
+    let left = match align {
+        Align::Left => x,
+        Align::Center => x - width / 2.0,
+        Align::Right => x - width,
+    };
+
+    draw(canvas, text, (left, y), font, paint);
+}
@@ -0,0 +1,114 @@
+use chrono_tz::Tz;
+use skia_safe::{pdf, Canvas, Color, Paint};
+
+use super::{ellipsize, load_font, text};
+use crate::{departures::TimetableRow, layout::FontSpec};
+
+/// US Letter, in points.
+const PAGE: (f32, f32) = (612.0, 792.0);
+const MARGIN: f32 = 48.0;
+const LINE_WIDTH: f32 = 56.0;
+const DESTINATION_WIDTH: f32 = 160.0;
+const GAP: f32 = 12.0;
+
+/// `rows` as a printable PDF, headed by `title`, starting a new page
+/// whenever the current one fills up.
+///
+/// Paper isn't an e-ink panel, so the board's tone and theme don't apply:
+/// everything is black on white.
+pub fn draw_timetable(
+    rows: &[TimetableRow],
+    title: &str,
+    family: &str,
+    tz: Tz,
+) -> eyre::Result<Vec<u8>> {
+    let spec = |size: f32| FontSpec {
+        family: family.to_owned(),
+        size,
+    };
+    let title_font = load_font(&spec(20.0))?;
+    let font = load_font(&spec(11.0))?;
+    let mut paint = Paint::default();
+    paint.set_anti_alias(true).set_color(Color::BLACK);
+    let mut rule = paint.clone();
+    rule.set_stroke_width(0.5).set_color(Color::GRAY);
+
+    let line_height = font.spacing() * 1.2;
+    let time_width = text::measure("00:00", &font, &paint) + GAP;
+    let times_left = MARGIN + LINE_WIDTH + DESTINATION_WIDTH + GAP;
+    let times_per_line = (((PAGE.0 - MARGIN - times_left) / time_width) as usize).max(1);
+    let bottom = PAGE.1 - MARGIN;
+
+    let mut out = Vec::new();
+    let mut document = pdf::new_document(&mut out, None);
+    let mut page = 0;
+    let mut rows = rows.iter().peekable();
+
+    // Always emit at least one page, so an empty timetable still says so.
+    while page == 0 || rows.peek().is_some() {
+        page += 1;
+        let mut on_page = document.begin_page(PAGE, None);
+        let canvas: &Canvas = on_page.canvas();
+        canvas.clear(Color::WHITE);
+
+        let mut y = MARGIN + title_font.size();
+        let heading = match page {
+            1 => title.to_owned(),
+            page => format!("{title} (page {page})"),
+        };
+        text::draw(canvas, &heading, (MARGIN, y), &title_font, &paint);
+        y += line_height * 2.0;
+
+        if page == 1 && rows.peek().is_none() {
+            text::draw(
+                canvas,
+                "No departures predicted.",
+                (MARGIN, y),
+                &font,
+                &paint,
+            );
+        }
+
+        let mut first = true;
+        while let Some(row) = rows.peek() {
+            let lines = row.times.len().div_ceil(times_per_line).max(1);
+            let height = line_height * lines as f32;
+            // A row that can't fit on an empty page is drawn anyway and left
+            // to run off the bottom.
+            if y + height > bottom && !first {
+                break;
+            }
+
+            let line = ellipsize(&row.line, LINE_WIDTH - GAP, &font, &paint);
+            text::draw(canvas, &line, (MARGIN, y), &font, &paint);
+            let destination = ellipsize(&row.destination, DESTINATION_WIDTH - GAP, &font, &paint);
+            text::draw(
+                canvas,
+                &destination,
+                (MARGIN + LINE_WIDTH, y),
+                &font,
+                &paint,
+            );
+
+            for (i, chunk) in row.times.chunks(times_per_line).enumerate() {
+                let baseline = y + line_height * i as f32;
+                for (j, time) in chunk.iter().enumerate() {
+                    let time = time.with_timezone(&tz).format("%H:%M").to_string();
+                    let x = times_left + time_width * j as f32;
+                    text::draw(canvas, &time, (x, baseline), &font, &paint);
+                }
+            }
+
+            let rule_y = y + height - line_height + font.metrics().1.descent + 3.0;
+            canvas.draw_line((MARGIN, rule_y), (PAGE.0 - MARGIN, rule_y), &rule);
+            y += height;
+            first = false;
+            rows.next();
+        }
+
+        document = on_page.end_page();
+    }
+    document.close();
+
+    Ok(out)
+}
@@ -0,0 +1,177 @@
+use std::{collections::HashMap, path::Path};
+
+use eyre::{ensure, eyre};
+
+/// Lightest and darkest bubble shades handed out. The line ID is drawn in
+/// black on top, so bubbles stay light enough to read it.
+const LIGHTEST: f32 = 0.9;
+const DARKEST: f32 = 0.45;
+
+/// The fields of one line of a CSV file, honouring double-quoted fields.
+pub(crate) fn fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+/// Relative luminance of a `RRGGBB` hex color, from 0.0 (black) to 1.0
+/// (white).
+fn luminance(hex: &str) -> Option<f32> {
+    let hex = hex.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| Some(u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()? as f32 / 255.0);
+
+    Some(0.2126 * channel(0)? + 0.7152 * channel(2)? + 0.0722 * channel(4)?)
+}
+
+/// How a line travels, for the icon drawn beside it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Bus,
+    /// Streetcars, light rail and cable cars.
+    LightRail,
+    /// Subway, metro and commuter rail.
+    Rail,
+    Ferry,
+}
+
+impl Mode {
+    /// The mode for a GTFS `route_type`, including the common extended
+    /// types.
+    pub fn from_route_type(route_type: u16) -> Option<Self> {
+        match route_type {
+            0 | 5 | 900..=999 => Some(Mode::LightRail),
+            1 | 2 | 12 | 100..=199 | 400..=499 => Some(Mode::Rail),
+            3 | 11 | 200..=299 | 700..=899 => Some(Mode::Bus),
+            4 | 1000..=1099 | 1200..=1299 => Some(Mode::Ferry),
+            _ => None,
+        }
+    }
+
+    /// The mode every line of a 511 agency runs, for agencies that run only
+    /// one. Muni runs several, so its lines need GTFS routes.
+    pub fn for_agency(agency: &str) -> Option<Self> {
+        match agency {
+            "CT" | "BA" => Some(Mode::Rail),
+            "SB" | "GF" => Some(Mode::Ferry),
+            _ => None,
+        }
+    }
+}
+
+/// One line in a GTFS `routes.txt`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    pub line: String,
+    /// Luminance of the route color, if it has one.
+    pub luminance: Option<f32>,
+    pub mode: Option<Mode>,
+}
+
+/// The routes in a GTFS `routes.txt`. Each route is listed under both its
+/// `route_id` and `route_short_name`, since agencies differ in which one
+/// their realtime feeds call the line.
+pub fn parse(body: &str) -> eyre::Result<Vec<Route>> {
+    let mut lines = body.trim_start_matches('\u{feff}').lines();
+    let header = fields(lines.next().ok_or_else(|| eyre!("routes.txt is empty"))?);
+    let column = |name: &str| header.iter().position(|field| field.trim() == name);
+
+    let (color, route_type) = (column("route_color"), column("route_type"));
+    let ids = [column("route_id"), column("route_short_name")];
+    ensure!(
+        ids.iter().any(Option::is_some),
+        "routes.txt has no route_id or route_short_name column"
+    );
+
+    let mut routes = Vec::new();
+    for line in lines.filter(|line| !line.trim().is_empty()) {
+        let fields = fields(line);
+        let field = |column: Option<usize>| fields.get(column?).map(|field| field.trim());
+        let luminance = field(color).and_then(luminance);
+        let mode = field(route_type)
+            .and_then(|route_type| route_type.parse().ok())
+            .and_then(Mode::from_route_type);
+
+        for id in ids.iter().filter_map(|&i| field(i)) {
+            if !id.is_empty() {
+                routes.push(Route {
+                    line: id.to_owned(),
+                    luminance,
+                    mode,
+                });
+            }
+        }
+    }
+
+    Ok(routes)
+}
+
+/// What's known about each line from the GTFS routes.
+#[derive(Debug, Clone, Default)]
+pub struct Routes {
+    /// Bubble shades for lines with a route color.
+    pub shades: HashMap<String, f32>,
+    pub modes: HashMap<String, Mode>,
+}
+
+impl Routes {
+    /// Distinct colors are ranked by luminance and their shades spread evenly
+    /// between the lightest and darkest, so lines stay apart in grayscale
+    /// even when their colors are equally bright.
+    pub fn new(routes: &[Route]) -> Self {
+        let mut luminances: Vec<f32> = routes.iter().filter_map(|route| route.luminance).collect();
+        luminances.sort_by(|a, b| b.total_cmp(a));
+        luminances.dedup();
+
+        let step = (LIGHTEST - DARKEST) / (luminances.len().max(2) - 1) as f32;
+        let shades = routes
+            .iter()
+            .filter_map(|route| {
+                let luminance = route.luminance?;
+                let rank = luminances
+                    .iter()
+                    .position(|other| *other == luminance)
+                    .unwrap_or(0);
+                Some((route.line.clone(), LIGHTEST - step * rank as f32))
+            })
+            .collect();
+        let modes = routes
+            .iter()
+            .filter_map(|route| Some((route.line.clone(), route.mode?)))
+            .collect();
+
+        Self { shades, modes }
+    }
+
+    /// Add `modes` for lines the GTFS routes don't already give one.
+    pub fn fill_modes(&mut self, modes: impl IntoIterator<Item = (String, Mode)>) {
+        for (line, mode) in modes {
+            self.modes.entry(line).or_insert(mode);
+        }
+    }
+
+    /// The routes in the GTFS `routes.txt` at `path`.
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+
+        Ok(Self::new(&parse(&contents)?))
+    }
+}
@@ -0,0 +1,134 @@
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use serde::Deserialize;
+
+use crate::{
+    alerts::ServiceAlert,
+    board::Board,
+    config::Config,
+    departures,
+    routes::Routes,
+    siri::{
+        MonitoredCall, MonitoredStopVisit, MonitoredVehicleJourney, ServiceDelivery,
+        StopMonitoringDelivery, StopMonitoringResponse,
+    },
+    trips::Trips,
+};
+
+/// Lines the made-up departures run on, with where they head inbound and
+/// outbound.
+const LINES: [(&str, &str, &str); 4] = [
+    ("N", "Caltrain / Ballpark", "Ocean Beach"),
+    ("J", "Embarcadero", "Balboa Park"),
+    ("KT", "Chinatown", "Balboa Park"),
+    ("L", "Embarcadero", "SF Zoo"),
+];
+
+/// A set of made-up departures to draw the board from, so a layout can be
+/// worked on at night or without spending API quota.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Scenario {
+    /// Every line at every stop, more often than fits.
+    #[default]
+    Busy,
+    /// Nothing running.
+    Empty,
+    /// A few departures, with service alerts and an elevator outage.
+    Alerts,
+}
+
+impl Scenario {
+    /// Minutes from now until each line's departures, in each direction.
+    fn minutes(self) -> &'static [i64] {
+        match self {
+            Scenario::Busy => &[1, 4, 9, 15, 24, 38],
+            Scenario::Empty => &[],
+            Scenario::Alerts => &[3, 12, 27],
+        }
+    }
+
+    /// A board for `config`'s stops as if 511 had reported this scenario
+    /// at `now`. The departures go through the config's grouping like real
+    /// ones, so renames and filters show up in the preview.
+    pub fn board(self, config: &Config, routes: Routes, now: DateTime<Utc>) -> Board {
+        let stops = config.stop_codes();
+        let mut board = Board {
+            departures: departures::group(
+                self.response(&stops, now),
+                &stops,
+                &config.grouping,
+                &Trips::default(),
+            ),
+            walk_times: config.walk_times(),
+            line_order: config.grouping.line_order.clone(),
+            routes,
+            timezone: Some(config.timezone),
+            now: Some(now),
+            ..Board::default()
+        };
+
+        if self == Scenario::Alerts {
+            board.alerts = vec![
+                ServiceAlert::new(
+                    "N Judah trains are running every 20 minutes due to track work.",
+                    vec!["N".to_owned()],
+                    Vec::new(),
+                ),
+                ServiceAlert::new(
+                    "This stop is temporarily closed. Board at the next stop.",
+                    Vec::new(),
+                    stops.iter().take(1).map(|&stop| stop.to_owned()).collect(),
+                ),
+            ];
+            board.elevator_outages = Some(vec!["Civic Center".to_owned()]);
+        }
+
+        board
+    }
+
+    fn response(self, stops: &[&str], now: DateTime<Utc>) -> StopMonitoringResponse {
+        let mut visits = Vec::new();
+        for stop in stops {
+            for (line, inbound, outbound) in LINES {
+                for (direction, destination) in [("IB", inbound), ("OB", outbound)] {
+                    for minutes in self.minutes() {
+                        let arrival = (now + Duration::minutes(*minutes))
+                            .to_rfc3339_opts(SecondsFormat::Secs, true);
+                        visits.push(MonitoredStopVisit {
+                            recorded_at_time: None,
+                            monitored_vehicle_journey: MonitoredVehicleJourney {
+                                line_ref: Some(line.to_owned()),
+                                direction_ref: Some(direction.to_owned()),
+                                framed_vehicle_journey_ref: None,
+                                vehicle_ref: None,
+                                destination_name: Some(destination.to_owned()),
+                                monitored: Some(true),
+                                prediction_inaccurate: None,
+                                occupancy: None,
+                                cancellation: None,
+                                monitored_call: MonitoredCall {
+                                    aimed_arrival_time: Some(arrival.clone()),
+                                    expected_arrival_time: Some(arrival),
+                                    arrival_status: None,
+                                    stop_point_ref: stop.to_string(),
+                                    destination_display: Some(destination.to_owned()),
+                                    arrival_platform_name: None,
+                                },
+                                stops_away: None,
+                                prediction_spread: None,
+                            },
+                        });
+                    }
+                }
+            }
+        }
+
+        StopMonitoringResponse {
+            service_delivery: ServiceDelivery {
+                stop_monitoring_delivery: StopMonitoringDelivery {
+                    monitored_stop_visit: visits,
+                },
+            },
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::path::Path;
+
+use eyre::eyre;
+use serde::Deserialize;
+use toml_edit::{value, Array, InlineTable, Item};
+
+use crate::{config::Config, departures::Stop};
+
+/// The parts of the config that can be changed from the admin page.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub stops: Vec<Stop>,
+    pub line_order: Vec<String>,
+    pub upcoming_times: Option<usize>,
+    pub min_minutes: Option<i64>,
+    pub imminent_minutes: Option<i64>,
+}
+
+impl Settings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            stops: config.stops.clone(),
+            line_order: config.grouping.line_order.clone(),
+            upcoming_times: config.upcoming_times,
+            min_minutes: config.min_minutes,
+            imminent_minutes: config.imminent_minutes,
+        }
+    }
+
+    /// Overwrite these settings in `config`.
+    pub fn apply(&self, config: &mut Config) {
+        config.stops.clone_from(&self.stops);
+        config.grouping.line_order.clone_from(&self.line_order);
+        config.upcoming_times = self.upcoming_times;
+        config.min_minutes = self.min_minutes;
+        config.imminent_minutes = self.imminent_minutes;
+    }
+
+    /// Write these settings into the config file at `path`.
+    pub fn save(&self, path: &Path) -> eyre::Result<()> {
+        Config::edit(path, |document| {
+            let stops: Array = self
+                .stops
+                .iter()
+                .map(|stop| {
+                    let mut table = InlineTable::new();
+                    table.insert("code", stop.code.as_str().into());
+                    if stop.walk_minutes != 0 {
+                        table.insert("walk_minutes", stop.walk_minutes.into());
+                    }
+                    if let Some(agency) = &stop.agency {
+                        table.insert("agency", agency.as_str().into());
+                    }
+                    table
+                })
+                .collect();
+            document["stops"] = value(stops);
+
+            if self.line_order.is_empty() {
+                if let Some(grouping) = document
+                    .get_mut("grouping")
+                    .and_then(Item::as_table_like_mut)
+                {
+                    grouping.remove("line_order");
+                }
+            } else {
+                let line_order = Array::from_iter(self.line_order.iter().map(String::as_str));
+                document["grouping"]["line_order"] = value(line_order);
+            }
+
+            let thresholds = [
+                ("upcoming_times", self.upcoming_times.map(|n| n as i64)),
+                ("min_minutes", self.min_minutes),
+                ("imminent_minutes", self.imminent_minutes),
+            ];
+            for (key, threshold) in thresholds {
+                match threshold {
+                    Some(threshold) => document[key] = value(threshold),
+                    None => {
+                        document.remove(key);
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+/// The admin page's form as submitted, every field as typed.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct SettingsForm {
+    /// One stop per line: its code, then optionally its walking time and
+    /// agency.
+    pub stops: String,
+    /// Lines to list first, one per line of text, in order.
+    pub line_order: String,
+    pub upcoming_times: String,
+    pub min_minutes: String,
+    pub imminent_minutes: String,
+}
+
+impl SettingsForm {
+    pub fn new(settings: &Settings) -> Self {
+        let number = |n: Option<i64>| n.map(|n| n.to_string()).unwrap_or_default();
+
+        Self {
+            stops: settings
+                .stops
+                .iter()
+                .map(|stop| match (stop.walk_minutes, &stop.agency) {
+                    (0, None) => stop.code.clone(),
+                    (walk_minutes, None) => format!("{} {walk_minutes}", stop.code),
+                    (walk_minutes, Some(agency)) => {
+                        format!("{} {walk_minutes} {agency}", stop.code)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            line_order: settings.line_order.join("\n"),
+            upcoming_times: number(settings.upcoming_times.map(|n| n as i64)),
+            min_minutes: number(settings.min_minutes),
+            imminent_minutes: number(settings.imminent_minutes),
+        }
+    }
+
+    /// The settings typed into the form. Blank thresholds fall back to the
+    /// layouts'.
+    pub fn parse(&self) -> eyre::Result<Settings> {
+        let mut stops = Vec::new();
+        for (i, line) in self.stops.lines().enumerate() {
+            let mut fields = line.split_whitespace();
+            let Some(code) = fields.next() else {
+                continue;
+            };
+            let walk_minutes = match fields.next() {
+                Some(minutes) => minutes
+                    .parse()
+                    .map_err(|_| eyre!("stop on line {}: {minutes:?} isn't minutes", i + 1))?,
+                None => 0,
+            };
+            let agency = fields.next().map(str::to_owned);
+            if fields.next().is_some() {
+                return Err(eyre!(
+                    "stop on line {}: expected a code, walking minutes and agency",
+                    i + 1
+                ));
+            }
+            stops.push(Stop {
+                code: code.to_owned(),
+                walk_minutes,
+                agency,
+            });
+        }
+
+        let line_order = self
+            .line_order
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_owned)
+            .collect();
+
+        Ok(Settings {
+            stops,
+            line_order,
+            upcoming_times: threshold("upcoming_times", &self.upcoming_times)?,
+            min_minutes: threshold("min_minutes", &self.min_minutes)?,
+            imminent_minutes: threshold("imminent_minutes", &self.imminent_minutes)?,
+        })
+    }
+}
+
+/// A number typed into the form, or `None` if it was left blank.
+fn threshold<T: std::str::FromStr>(name: &str, text: &str) -> eyre::Result<Option<T>> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+
+    text.parse()
+        .map(Some)
+        .map_err(|_| eyre!("{name} must be a whole number, not {text:?}"))
+}
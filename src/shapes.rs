@@ -0,0 +1,106 @@
+use std::{collections::BTreeMap, path::Path};
+
+use eyre::{ensure, eyre};
+
+use crate::routes::fields;
+
+/// A route's path, as (latitude, longitude) points in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shape {
+    pub points: Vec<(f64, f64)>,
+    /// South, west, north and east edges of the points.
+    pub extent: (f64, f64, f64, f64),
+}
+
+impl Shape {
+    fn new(points: Vec<(f64, f64)>) -> Self {
+        let extent = points.iter().fold(
+            (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+            |(south, west, north, east), &(lat, lon)| {
+                (south.min(lat), west.min(lon), north.max(lat), east.max(lon))
+            },
+        );
+
+        Self { points, extent }
+    }
+
+    /// Whether any of the shape could fall within `area`.
+    pub fn overlaps(&self, area: &Area) -> bool {
+        let (south, west, north, east) = self.extent;
+        south <= area.north && north >= area.south && west <= area.east && east >= area.west
+    }
+}
+
+/// A box of the map, in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Area {
+    pub north: f64,
+    pub south: f64,
+    pub east: f64,
+    pub west: f64,
+}
+
+impl Area {
+    pub fn contains(&self, (lat, lon): (f64, f64)) -> bool {
+        (self.south..=self.north).contains(&lat) && (self.west..=self.east).contains(&lon)
+    }
+}
+
+/// The shapes in a GTFS `shapes.txt`, for drawing routes on a map.
+pub fn parse(body: &str) -> eyre::Result<Vec<Shape>> {
+    let mut lines = body.trim_start_matches('\u{feff}').lines();
+    let header = fields(lines.next().ok_or_else(|| eyre!("shapes.txt is empty"))?);
+    let column = |name: &str| header.iter().position(|field| field.trim() == name);
+
+    let (Some(id), Some(lat), Some(lon), Some(sequence)) = (
+        column("shape_id"),
+        column("shape_pt_lat"),
+        column("shape_pt_lon"),
+        column("shape_pt_sequence"),
+    ) else {
+        return Err(eyre!(
+            "shapes.txt needs shape_id, shape_pt_lat, shape_pt_lon and shape_pt_sequence columns"
+        ));
+    };
+
+    let mut points: BTreeMap<String, Vec<(u32, f64, f64)>> = BTreeMap::new();
+    for (i, line) in lines
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+    {
+        let fields = fields(line);
+        let field = |column: usize| fields.get(column).map(|field| field.trim());
+        let point = (|| {
+            Some((
+                field(sequence)?.parse().ok()?,
+                field(lat)?.parse().ok()?,
+                field(lon)?.parse().ok()?,
+            ))
+        })();
+        let (Some(id), Some(point)) = (field(id), point) else {
+            return Err(eyre!("shapes.txt line {} is malformed", i + 2));
+        };
+        points.entry(id.to_owned()).or_default().push(point);
+    }
+    ensure!(!points.is_empty(), "shapes.txt has no points");
+
+    Ok(points
+        .into_values()
+        .map(|mut points| {
+            points.sort_by_key(|&(sequence, _, _)| sequence);
+            Shape::new(points.into_iter().map(|(_, lat, lon)| (lat, lon)).collect())
+        })
+        .collect())
+}
+
+/// The routes' shapes from the configured GTFS `shapes.txt`.
+#[derive(Debug, Clone, Default)]
+pub struct Shapes(pub Vec<Shape>);
+
+impl Shapes {
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+
+        Ok(Self(parse(&contents)?))
+    }
+}
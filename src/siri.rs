@@ -0,0 +1,288 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    quirks,
+    upstream::{Priority, Transport, Upstream},
+};
+
+const STOP_MONITORING_URL: &str = "http://api.511.org/transit/StopMonitoring";
+const STOPS_URL: &str = "http://api.511.org/transit/stops";
+
+/// Which encoding to ask the API for. Responses are parsed as whichever they
+/// turn out to be.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SiriFormat {
+    #[default]
+    Json,
+    Xml,
+}
+
+impl SiriFormat {
+    pub fn query_value(self) -> &'static str {
+        match self {
+            SiriFormat::Json => "json",
+            SiriFormat::Xml => "xml",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct StopMonitoringResponse {
+    pub service_delivery: ServiceDelivery,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct ServiceDelivery {
+    pub stop_monitoring_delivery: StopMonitoringDelivery,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct StopMonitoringDelivery {
+    /// Visits that don't parse are logged and left out, so one malformed
+    /// entry doesn't blank the whole board.
+    #[serde(default, deserialize_with = "quirks::skip_malformed")]
+    pub monitored_stop_visit: Vec<MonitoredStopVisit>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct MonitoredStopVisit {
+    /// When the producer made this prediction.
+    #[serde(default)]
+    pub recorded_at_time: Option<String>,
+    pub monitored_vehicle_journey: MonitoredVehicleJourney,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct MonitoredVehicleJourney {
+    #[serde(default, deserialize_with = "quirks::opt_string")]
+    pub line_ref: Option<String>,
+    #[serde(default, deserialize_with = "quirks::opt_string")]
+    pub direction_ref: Option<String>,
+    /// Identifies the trip, the same at every stop it calls at.
+    #[serde(default)]
+    pub framed_vehicle_journey_ref: Option<FramedVehicleJourneyRef>,
+    #[serde(default, deserialize_with = "quirks::opt_string")]
+    pub vehicle_ref: Option<String>,
+    pub destination_name: Option<String>,
+    /// Whether the vehicle is being tracked live. Untracked journeys only
+    /// have timetable estimates.
+    #[serde(default, deserialize_with = "quirks::opt_bool")]
+    pub monitored: Option<bool>,
+    /// Set by the producer when it doesn't trust its own prediction, e.g.
+    /// the vehicle is stuck in traffic.
+    #[serde(default, deserialize_with = "quirks::opt_bool")]
+    pub prediction_inaccurate: Option<bool>,
+    /// How full the vehicle is, e.g. "seatsAvailable", "standingAvailable",
+    /// or "full".
+    #[serde(default)]
+    pub occupancy: Option<String>,
+    /// Set when the whole journey has been cancelled.
+    #[serde(default, deserialize_with = "quirks::opt_bool")]
+    pub cancellation: Option<bool>,
+    pub monitored_call: MonitoredCall,
+    /// How many stops the vehicle calls at up to and including this one, so
+    /// 1 when this is its next stop. Filled in from vehicle positions when
+    /// they're tracked.
+    #[serde(skip)]
+    pub stops_away: Option<u32>,
+    /// How many minutes either side this trip's recent predictions have
+    /// wandered, if enough unsteadily to mention. Filled in from earlier
+    /// refreshes by [`crate::departures::Predictions`].
+    #[serde(skip)]
+    pub prediction_spread: Option<i64>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "PascalCase")]
+pub struct FramedVehicleJourneyRef {
+    #[serde(default, deserialize_with = "quirks::opt_string")]
+    pub data_frame_ref: Option<String>,
+    #[serde(default, deserialize_with = "quirks::opt_string")]
+    pub dated_vehicle_journey_ref: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct MonitoredCall {
+    pub aimed_arrival_time: Option<String>,
+    pub expected_arrival_time: Option<String>,
+    /// e.g. "onTime", "delayed", or "cancelled" when this call won't be
+    /// made.
+    #[serde(default)]
+    pub arrival_status: Option<String>,
+    #[serde(deserialize_with = "quirks::string")]
+    pub stop_point_ref: String,
+    pub destination_display: Option<String>,
+    /// The platform or track, where the agency gives one, e.g. Caltrain's
+    /// "NB" and "SB" platforms.
+    #[serde(default, deserialize_with = "quirks::opt_string")]
+    pub arrival_platform_name: Option<String>,
+}
+
+impl MonitoredVehicleJourney {
+    /// What tells this trip apart from others on the line: the journey ref,
+    /// or failing that the vehicle.
+    pub fn trip(&self) -> Option<String> {
+        self.framed_vehicle_journey_ref
+            .as_ref()
+            .and_then(|framed| {
+                let journey = framed.dated_vehicle_journey_ref.as_deref()?;
+                Some(format!(
+                    "{}/{journey}",
+                    framed.data_frame_ref.as_deref().unwrap_or_default()
+                ))
+            })
+            .or_else(|| {
+                self.vehicle_ref
+                    .as_ref()
+                    .map(|vehicle| format!("vehicle {vehicle}"))
+            })
+    }
+
+    /// Whether this journey won't call at the stop after all.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation == Some(true)
+            || self
+                .monitored_call
+                .arrival_status
+                .as_deref()
+                .is_some_and(|status| status.eq_ignore_ascii_case("cancelled"))
+    }
+
+    /// When the journey is expected at the stop or, if it's cancelled, when
+    /// it was scheduled to be.
+    pub fn arrival_time(&self) -> Option<&str> {
+        let call = &self.monitored_call;
+        if self.is_cancelled() {
+            call.expected_arrival_time
+                .as_deref()
+                .or(call.aimed_arrival_time.as_deref())
+        } else {
+            call.expected_arrival_time.as_deref()
+        }
+    }
+}
+
+/// Fetch the stop visits for `agency`, at only `stop_code` if given or at
+/// every stop otherwise.
+pub async fn fetch_stop_monitoring(
+    client: &dyn Transport,
+    upstream: &Upstream,
+    agency: &str,
+    stop_code: Option<&str>,
+) -> eyre::Result<StopMonitoringResponse> {
+    let params: Vec<_> = stop_code
+        .map(|code| ("stopcode", code))
+        .into_iter()
+        .collect();
+    let response_txt = upstream
+        .get_siri_for(client, STOP_MONITORING_URL, agency, &params, Priority::High)
+        .await?;
+
+    quirks::from_json_or_xml(&response_txt)
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct StopsResponse {
+    contents: StopsContents,
+}
+
+#[derive(Deserialize, Debug)]
+struct StopsContents {
+    #[serde(rename = "dataObjects")]
+    data_objects: StopsDataObjects,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct StopsDataObjects {
+    scheduled_stop_point: Vec<ScheduledStopPoint>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScheduledStopPoint {
+    #[serde(deserialize_with = "quirks::string")]
+    id: String,
+    #[serde(rename = "Name", default, deserialize_with = "quirks::opt_string")]
+    name: Option<String>,
+    #[serde(rename = "Location")]
+    location: Option<StopLocation>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct StopLocation {
+    #[serde(default, deserialize_with = "quirks::opt_f64")]
+    latitude: Option<f64>,
+    #[serde(default, deserialize_with = "quirks::opt_f64")]
+    longitude: Option<f64>,
+}
+
+/// A stop with its name and where it is, for finding stops near a place.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StopPoint {
+    pub code: String,
+    pub name: String,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// The code of every stop the agency serves.
+pub async fn fetch_stop_codes(
+    client: &dyn Transport,
+    upstream: &Upstream,
+    agency: &str,
+) -> eyre::Result<Vec<String>> {
+    Ok(fetch_stops(client, upstream, agency)
+        .await?
+        .into_iter()
+        .map(|stop| stop.id)
+        .collect())
+}
+
+/// Every stop the agency serves that 511 gives a location for.
+pub async fn fetch_stop_points(
+    client: &dyn Transport,
+    upstream: &Upstream,
+    agency: &str,
+) -> eyre::Result<Vec<StopPoint>> {
+    Ok(fetch_stops(client, upstream, agency)
+        .await?
+        .into_iter()
+        .filter_map(|stop| {
+            let location = stop.location?;
+            Some(StopPoint {
+                name: stop.name.unwrap_or_else(|| stop.id.clone()),
+                code: stop.id,
+                lat: location.latitude?,
+                lon: location.longitude?,
+            })
+        })
+        .collect())
+}
+
+async fn fetch_stops(
+    client: &dyn Transport,
+    upstream: &Upstream,
+    agency: &str,
+) -> eyre::Result<Vec<ScheduledStopPoint>> {
+    let response_txt = upstream
+        .get_siri_for(
+            client,
+            STOPS_URL,
+            agency,
+            &[("operator_id", agency)],
+            Priority::Low,
+        )
+        .await?;
+    let response: StopsResponse = quirks::from_json_or_xml(&response_txt)?;
+
+    Ok(response.contents.data_objects.scheduled_stop_point)
+}
@@ -0,0 +1,159 @@
+use std::{collections::BTreeMap, path::PathBuf, sync::Mutex};
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use rusqlite::{params, Connection};
+use serde::Deserialize;
+
+use crate::{departures::Departure, display::Display};
+
+fn default_history_days() -> u32 {
+    30
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// The SQLite file, created if it doesn't exist.
+    pub path: PathBuf,
+    /// How long fetch history and prediction snapshots are kept.
+    #[serde(default = "default_history_days")]
+    pub history_days: u32,
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS displays (
+    name TEXT PRIMARY KEY,
+    display TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS fetches (
+    id INTEGER PRIMARY KEY,
+    view TEXT NOT NULL,
+    fetched_at TEXT NOT NULL,
+    errors TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS fetches_fetched_at ON fetches (fetched_at);
+
+CREATE TABLE IF NOT EXISTS predictions (
+    fetch_id INTEGER NOT NULL REFERENCES fetches (id) ON DELETE CASCADE,
+    line TEXT NOT NULL,
+    destination TEXT NOT NULL,
+    stop TEXT NOT NULL,
+    expected_arrival_time TEXT,
+    aimed_arrival_time TEXT,
+    cancelled INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS predictions_fetch_id ON predictions (fetch_id);
+";
+
+/// RFC 3339 in UTC, which sorts as text in time order.
+fn timestamp(instant: DateTime<Utc>) -> String {
+    instant.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Displays added through the API, and a history of every fetch with the
+/// predictions it returned, kept in SQLite so they outlast the process and
+/// can be compared with what actually happened.
+pub struct Store {
+    connection: Mutex<Connection>,
+    history: Duration,
+}
+
+impl Store {
+    pub fn open(config: &DatabaseConfig) -> eyre::Result<Self> {
+        let connection = Connection::open(&config.path)?;
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        connection.execute_batch(SCHEMA)?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+            history: Duration::days(config.history_days.into()),
+        })
+    }
+
+    /// Every stored display, by name.
+    pub fn displays(&self) -> eyre::Result<BTreeMap<String, Display>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare("SELECT name, display FROM displays")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+
+        let mut displays = BTreeMap::new();
+        for row in rows {
+            let (name, display) = row?;
+            displays.insert(name, serde_json::from_str(&display)?);
+        }
+
+        Ok(displays)
+    }
+
+    /// Store `display` as `name`, replacing any display already called that.
+    pub fn save_display(&self, name: &str, display: &Display) -> eyre::Result<()> {
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO displays (name, display, updated_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT (name) DO UPDATE SET display = ?2, updated_at = ?3",
+            params![name, serde_json::to_string(display)?, timestamp(Utc::now())],
+        )?;
+
+        Ok(())
+    }
+
+    /// Whether there was a display called `name` to remove.
+    pub fn remove_display(&self, name: &str) -> eyre::Result<bool> {
+        let removed = self
+            .connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM displays WHERE name = ?1", [name])?;
+
+        Ok(removed > 0)
+    }
+
+    /// Note that `view` fetched `departures` at `at`, with `errors` from any
+    /// sources that failed, and forget fetches older than the configured
+    /// history.
+    pub fn record_fetch(
+        &self,
+        view: &str,
+        at: DateTime<Utc>,
+        departures: &[Departure],
+        errors: &BTreeMap<String, String>,
+    ) -> eyre::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+
+        transaction.execute(
+            "INSERT INTO fetches (view, fetched_at, errors) VALUES (?1, ?2, ?3)",
+            params![view, timestamp(at), serde_json::to_string(errors)?],
+        )?;
+        let fetch_id = transaction.last_insert_rowid();
+        {
+            let mut insert = transaction.prepare(
+                "INSERT INTO predictions (fetch_id, line, destination, stop,
+                     expected_arrival_time, aimed_arrival_time, cancelled)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for departure in departures {
+                insert.execute(params![
+                    fetch_id,
+                    departure.line,
+                    departure.destination,
+                    departure.stop,
+                    departure.expected_arrival_time.map(timestamp),
+                    departure.aimed_arrival_time.map(timestamp),
+                    departure.cancelled,
+                ])?;
+            }
+        }
+        transaction.execute(
+            "DELETE FROM fetches WHERE fetched_at < ?1",
+            [timestamp(at - self.history)],
+        )?;
+
+        transaction.commit()?;
+
+        Ok(())
+    }
+}
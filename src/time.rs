@@ -0,0 +1,183 @@
+use chrono::{
+    DateTime, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+
+/// Parse an upstream timestamp into an absolute instant.
+///
+/// Timestamps carry their own UTC offset, which changes across DST
+/// transitions, so they must be compared as parsed instants rather than as
+/// strings: `01:50-07:00` is earlier than `01:05-08:00` on fall-back night.
+pub fn parse_instant(timestamp: &str) -> Option<DateTime<Utc>> {
+    timestamp.parse::<DateTime<Utc>>().ok()
+}
+
+/// Whole minutes from `now` until `arrival`, or `None` if it has already
+/// passed. Both sides are UTC instants, so the result is the real elapsed
+/// time regardless of any wall-clock shift in between.
+pub fn minutes_until(now: DateTime<Utc>, arrival: DateTime<Utc>) -> Option<i64> {
+    (arrival >= now).then(|| (arrival - now).num_minutes())
+}
+
+/// Resolve a local wall-clock time to an instant, explicitly handling DST.
+///
+/// A time that occurs twice on fall-back night resolves to its first
+/// occurrence. A time skipped on spring-forward night is read with the offset
+/// in effect before the jump, landing the same distance past the transition
+/// (02:30 becomes 03:30 daylight time).
+pub fn local_datetime(tz: Tz, naive: NaiveDateTime) -> DateTime<Tz> {
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(instant) => instant,
+        LocalResult::Ambiguous(earliest, _) => earliest,
+        LocalResult::None => {
+            let offset = tz.offset_from_utc_datetime(&(naive - Duration::days(1)));
+            let utc = naive - Duration::seconds(offset.fix().local_minus_utc().into());
+
+            tz.from_utc_datetime(&utc)
+        }
+    }
+}
+
+/// The instant at which the service day `date` begins.
+pub fn service_day_begins(date: NaiveDate, tz: Tz, start: NaiveTime) -> DateTime<Tz> {
+    local_datetime(tz, date.and_time(start))
+}
+
+/// The service day `instant` belongs to. A service day runs from `start` local
+/// time until `start` the next day, so a 01:30 owl trip counts toward the
+/// previous calendar day.
+pub fn service_day(instant: DateTime<Utc>, tz: Tz, start: NaiveTime) -> NaiveDate {
+    let date = instant.with_timezone(&tz).date_naive();
+
+    if instant >= service_day_begins(date, tz, start) {
+        date
+    } else {
+        date.pred_opt().expect("date is not the minimum NaiveDate")
+    }
+}
+
+/// Whether the wall-clock `time` falls in the window from `start` until
+/// `end`. A window whose end is before its start runs past midnight, so
+/// 23:00–06:00 contains 02:00 but not 12:00.
+pub fn within(time: NaiveTime, start: NaiveTime, end: NaiveTime) -> bool {
+    if start <= end {
+        start <= time && time < end
+    } else {
+        start <= time || time < end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::America::Los_Angeles;
+
+    use super::*;
+
+    fn hm(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn instant(timestamp: &str) -> DateTime<Utc> {
+        parse_instant(timestamp).unwrap()
+    }
+
+    #[test]
+    fn minutes_until_across_spring_forward() {
+        // 01:55 PST is ten real minutes before 03:05 PDT.
+        let now = instant("2024-03-10T01:55:00-08:00");
+        let arrival = instant("2024-03-10T03:05:00-07:00");
+
+        assert_eq!(minutes_until(now, arrival), Some(10));
+    }
+
+    #[test]
+    fn minutes_until_across_fall_back() {
+        // The first 01:50 (PDT) is fifteen real minutes before the second
+        // 01:05 (PST), even though the wall clock went backwards.
+        let now = instant("2024-11-03T01:50:00-07:00");
+        let arrival = instant("2024-11-03T01:05:00-08:00");
+
+        assert_eq!(minutes_until(now, arrival), Some(15));
+        assert_eq!(minutes_until(arrival, now), None);
+    }
+
+    #[test]
+    fn arrivals_sort_by_instant_across_fall_back() {
+        let mut timestamps = vec![
+            "2024-11-03T01:05:00-08:00",
+            "2024-11-03T01:50:00-07:00",
+            "2024-11-03T01:20:00-08:00",
+        ];
+        timestamps.sort_by_key(|t| parse_instant(t));
+
+        assert_eq!(
+            timestamps,
+            [
+                "2024-11-03T01:50:00-07:00",
+                "2024-11-03T01:05:00-08:00",
+                "2024-11-03T01:20:00-08:00",
+            ]
+        );
+    }
+
+    #[test]
+    fn owl_trips_belong_to_previous_service_day() {
+        let owl = instant("2024-06-15T01:30:00-07:00");
+        let morning = instant("2024-06-15T04:30:00-07:00");
+
+        assert_eq!(service_day(owl, Los_Angeles, hm(4, 0)), date(2024, 6, 14));
+        assert_eq!(
+            service_day(morning, Los_Angeles, hm(4, 0)),
+            date(2024, 6, 15)
+        );
+    }
+
+    #[test]
+    fn service_day_start_in_spring_forward_gap() {
+        // 02:30 doesn't exist on 2024-03-10; the day begins at 03:30 PDT.
+        let begins = service_day_begins(date(2024, 3, 10), Los_Angeles, hm(2, 30));
+        assert_eq!(begins, instant("2024-03-10T03:30:00-07:00"));
+
+        let before = instant("2024-03-10T03:20:00-07:00");
+        assert_eq!(
+            service_day(before, Los_Angeles, hm(2, 30)),
+            date(2024, 3, 9)
+        );
+    }
+
+    #[test]
+    fn service_day_start_in_fall_back_fold() {
+        // 01:30 happens twice on 2024-11-03; the day begins at the first one.
+        let begins = service_day_begins(date(2024, 11, 3), Los_Angeles, hm(1, 30));
+        assert_eq!(begins, instant("2024-11-03T01:30:00-07:00"));
+
+        let second_pass = instant("2024-11-03T01:10:00-08:00");
+        assert_eq!(
+            service_day(second_pass, Los_Angeles, hm(1, 30)),
+            date(2024, 11, 3)
+        );
+    }
+
+    #[test]
+    fn service_day_across_fall_back_with_default_start() {
+        let late = instant("2024-11-03T03:59:00-08:00");
+        let early = instant("2024-11-03T04:00:00-08:00");
+
+        assert_eq!(service_day(late, Los_Angeles, hm(4, 0)), date(2024, 11, 2));
+        assert_eq!(service_day(early, Los_Angeles, hm(4, 0)), date(2024, 11, 3));
+    }
+
+    #[test]
+    fn within_window_past_midnight() {
+        let (start, end) = (hm(23, 0), hm(6, 0));
+
+        assert!(within(hm(23, 0), start, end));
+        assert!(within(hm(2, 0), start, end));
+        assert!(!within(hm(6, 0), start, end));
+        assert!(!within(hm(12, 0), start, end));
+    }
+}
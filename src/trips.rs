@@ -0,0 +1,52 @@
+use std::{collections::HashMap, path::Path};
+
+use eyre::{ensure, eyre};
+
+use crate::routes::fields;
+
+/// The `direction_id` of each trip in a GTFS `trips.txt`, by `trip_id`.
+/// Trips without a direction are left out.
+pub fn parse(body: &str) -> eyre::Result<HashMap<String, String>> {
+    let mut lines = body.trim_start_matches('\u{feff}').lines();
+    let header = fields(lines.next().ok_or_else(|| eyre!("trips.txt is empty"))?);
+    let column = |name: &str| header.iter().position(|field| field.trim() == name);
+
+    let (Some(id), Some(direction)) = (column("trip_id"), column("direction_id")) else {
+        return Err(eyre!("trips.txt needs trip_id and direction_id columns"));
+    };
+
+    let mut directions = HashMap::new();
+    for line in lines.filter(|line| !line.trim().is_empty()) {
+        let fields = fields(line);
+        let field = |column: usize| fields.get(column).map(|field| field.trim());
+        if let (Some(id), Some(direction)) = (field(id), field(direction)) {
+            if !id.is_empty() && !direction.is_empty() {
+                directions.insert(id.to_owned(), direction.to_owned());
+            }
+        }
+    }
+    ensure!(
+        !directions.is_empty(),
+        "trips.txt has no trips with a direction"
+    );
+
+    Ok(directions)
+}
+
+/// Each trip's GTFS direction from the configured `trips.txt`, for placing
+/// journeys whose realtime feed leaves out the direction.
+#[derive(Debug, Clone, Default)]
+pub struct Trips(pub HashMap<String, String>);
+
+impl Trips {
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+
+        Ok(Self(parse(&contents)?))
+    }
+
+    /// The GTFS `direction_id` of the trip with `trip_id`.
+    pub fn direction(&self, trip_id: &str) -> Option<&str> {
+        self.0.get(trip_id).map(String::as_str)
+    }
+}
@@ -0,0 +1,72 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::departures::Departure;
+
+/// How many updates a slow subscriber may fall behind before it skips ahead.
+const BACKLOG: usize = 16;
+
+/// Something live clients may want to hear about, sent as JSON tagged with its
+/// `type`.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Update {
+    /// Departures were fetched for a board.
+    Departures { departures: Vec<Departure> },
+    /// A view finished drawing a new frame.
+    Rendered { view: String, at: DateTime<Utc> },
+}
+
+impl Update {
+    /// The update's `type`, e.g. for naming server-sent events.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Update::Departures { .. } => "departures",
+            Update::Rendered { .. } => "rendered",
+        }
+    }
+}
+
+/// Fans updates out to whoever is subscribed, dropping them when nobody is.
+#[derive(Debug)]
+pub struct Updates(broadcast::Sender<Update>);
+
+impl Default for Updates {
+    fn default() -> Self {
+        Self(broadcast::channel(BACKLOG).0)
+    }
+}
+
+impl Updates {
+    pub fn publish(&self, update: Update) {
+        // Sending only fails when there are no subscribers to miss it.
+        let _ = self.0.send(update);
+    }
+
+    pub fn subscribe(&self) -> Subscription {
+        Subscription(self.0.subscribe())
+    }
+
+    /// Whether anyone is listening.
+    pub fn has_subscribers(&self) -> bool {
+        self.0.receiver_count() > 0
+    }
+}
+
+pub struct Subscription(broadcast::Receiver<Update>);
+
+impl Subscription {
+    /// The next update, or `None` once the server is shutting down. A
+    /// subscriber that fell too far behind skips to the oldest update still
+    /// held.
+    pub async fn next(&mut self) -> Option<Update> {
+        loop {
+            match self.0.recv().await {
+                Ok(update) => return Some(update),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
@@ -0,0 +1,415 @@
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Write},
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use eyre::{ensure, eyre};
+use futures_util::future::BoxFuture;
+use reqwest::{header::HeaderMap, Client, StatusCode};
+use tracing::warn;
+
+use crate::{api_keys::KeyPool, config::Config, siri::SiriFormat};
+
+/// The 511 API's default rate limit for each key.
+const DEFAULT_REQUESTS_PER_KEY_PER_HOUR: u32 = 60;
+
+const BUDGET_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// The 511 operator code for Muni, which stops belong to unless they say
+/// otherwise.
+pub const DEFAULT_AGENCY: &str = "SF";
+
+/// What the API answered.
+#[derive(Debug, Clone)]
+pub struct Reply {
+    pub status: StatusCode,
+    /// Read for the rate limits each key has left.
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+/// Sends requests to the transit API: a `reqwest::Client` normally, canned
+/// replies in tests that exercise parsing and error handling offline.
+pub trait Transport: Debug + Send + Sync {
+    fn get<'a>(
+        &'a self,
+        url: &'a str,
+        query: &'a [(&'a str, &'a str)],
+    ) -> BoxFuture<'a, eyre::Result<Reply>>;
+}
+
+impl Transport for Client {
+    fn get<'a>(
+        &'a self,
+        url: &'a str,
+        query: &'a [(&'a str, &'a str)],
+    ) -> BoxFuture<'a, eyre::Result<Reply>> {
+        Box::pin(async move {
+            let response = Client::get(self, url).query(query).send().await?;
+
+            Ok(Reply {
+                status: response.status(),
+                headers: response.headers().clone(),
+                body: response.text().await?,
+            })
+        })
+    }
+}
+
+/// Everything shared by code that calls out to the transit API.
+#[derive(Debug)]
+pub struct Upstream {
+    pub keys: KeyPool,
+    pub budget: RequestBudget,
+    format: SiriFormat,
+    whole_agency_feed: Option<bool>,
+    timeout: Duration,
+    tape: Option<Tape>,
+    /// Recent response bodies by request, so several devices and endpoints
+    /// polling the same stops share one fetch.
+    recent: Mutex<HashMap<PathBuf, (Instant, String)>>,
+    max_age: Duration,
+}
+
+impl Upstream {
+    pub fn new(config: &Config) -> Self {
+        let requests_per_hour = config
+            .requests_per_hour
+            .unwrap_or(DEFAULT_REQUESTS_PER_KEY_PER_HOUR * config.api_keys.len().max(1) as u32);
+
+        Self {
+            keys: KeyPool::new(config.api_keys.clone()),
+            budget: RequestBudget::new(requests_per_hour),
+            format: config.siri_format,
+            whole_agency_feed: config.whole_agency_feed,
+            timeout: Duration::from_secs(config.upstream_timeout_secs),
+            tape: None,
+            recent: Mutex::default(),
+            max_age: Duration::from_secs(config.upstream_cache_secs),
+        }
+    }
+
+    /// Whether StopMonitoring is fetched for a whole agency at once rather
+    /// than per stop, when `stops` of its stops are wanted. Unless configured
+    /// otherwise, only a lone stop is asked for on its own: it costs the same
+    /// one request as the whole feed, for a far smaller response.
+    pub fn whole_agency_feed(&self, stops: usize) -> bool {
+        self.whole_agency_feed.unwrap_or(stops > 1)
+    }
+
+    /// Record responses to, or replay them from, `tape`.
+    pub fn with_tape(mut self, tape: Option<Tape>) -> Self {
+        self.tape = tape;
+        self
+    }
+
+    pub fn metrics(&self) -> String {
+        self.keys.metrics() + &self.budget.metrics()
+    }
+
+    /// Fetch the body of a 511 endpoint for Muni.
+    pub async fn get(
+        &self,
+        client: &dyn Transport,
+        url: &str,
+        priority: Priority,
+    ) -> eyre::Result<String> {
+        self.get_for(client, url, DEFAULT_AGENCY, &[], priority)
+            .await
+    }
+
+    /// Fetch the body of a 511 endpoint for `agency`, e.g. "CT" for Caltrain,
+    /// with the extra `params`, trying each API key until one isn't rate
+    /// limited or rejected.
+    pub async fn get_for(
+        &self,
+        client: &dyn Transport,
+        url: &str,
+        agency: &str,
+        params: &[(&str, &str)],
+        priority: Priority,
+    ) -> eyre::Result<String> {
+        self.request(client, url, agency, params, None, priority)
+            .await
+    }
+
+    /// Like [`Upstream::get_for`], for the SIRI endpoints (StopMonitoring,
+    /// VehicleMonitoring and stops), which answer in the configured
+    /// `siri_format`. The GTFS-realtime feeds only speak JSON.
+    pub async fn get_siri_for(
+        &self,
+        client: &dyn Transport,
+        url: &str,
+        agency: &str,
+        params: &[(&str, &str)],
+        priority: Priority,
+    ) -> eyre::Result<String> {
+        self.request(client, url, agency, params, Some(self.format), priority)
+            .await
+    }
+
+    async fn request(
+        &self,
+        client: &dyn Transport,
+        url: &str,
+        agency: &str,
+        params: &[(&str, &str)],
+        format: Option<SiriFormat>,
+        priority: Priority,
+    ) -> eyre::Result<String> {
+        if let Some(Tape::Replay(dir)) = &self.tape {
+            let file = Tape::file(dir, url, agency, params);
+            return fs::read_to_string(&file)
+                .map_err(|e| eyre!("no recorded response at {}: {e}", file.display()));
+        }
+
+        let request = Tape::file(Path::new(""), url, agency, params);
+        if let Some((_, body)) = self
+            .recent
+            .lock()
+            .unwrap()
+            .get(&request)
+            .filter(|(fetched, _)| fetched.elapsed() < self.max_age)
+        {
+            return Ok(body.clone());
+        }
+
+        let mut last_error = None;
+
+        for (index, key) in self.keys.rotation() {
+            self.budget.acquire(priority)?;
+
+            let mut query = vec![("api_key", key.as_str()), ("agency", agency)];
+            if let Some(format) = format {
+                query.push(("format", format.query_value()));
+            }
+            query.extend_from_slice(params);
+            let reply = tokio::time::timeout(self.timeout, client.get(url, &query))
+                .await
+                .map_err(|_| eyre!("511 API didn't answer within {:?}", self.timeout))??;
+
+            let status = reply.status;
+            self.keys.record(index, status, &reply.headers);
+
+            match status {
+                StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::UNAUTHORIZED
+                | StatusCode::FORBIDDEN => {
+                    warn!(%status, "511 API key refused, trying the next one");
+                    last_error = Some(eyre!("511 API returned {status}"));
+                }
+                _ => {
+                    ensure!(status.is_success(), "511 API returned {status}");
+                    if let Some(Tape::Record(dir)) = &self.tape {
+                        Tape::record(dir, url, agency, params, &reply.body);
+                    }
+                    let mut recent = self.recent.lock().unwrap();
+                    recent.retain(|_, (fetched, _)| fetched.elapsed() < self.max_age);
+                    recent.insert(request, (Instant::now(), reply.body.clone()));
+                    return Ok(reply.body);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| eyre!("no 511 API keys configured")))
+    }
+}
+
+/// A directory of raw 511 responses, one file per request, named for the
+/// endpoint, the agency unless it's Muni, and any extra parameters, e.g.
+/// `StopMonitoring-15419.json` or `StopMonitoring-CT-70012.json`. Recording
+/// one and replaying it later allows working offline, or reproducing a
+/// parsing bug from a payload a user captured.
+#[derive(Debug, Clone)]
+pub enum Tape {
+    /// Save each response, replacing the last one from the same endpoint.
+    Record(PathBuf),
+    /// Answer from saved responses instead of calling the API.
+    Replay(PathBuf),
+}
+
+impl Tape {
+    fn file(dir: &Path, url: &str, agency: &str, params: &[(&str, &str)]) -> PathBuf {
+        let path = url.split('?').next().unwrap_or(url);
+        let mut name = path.rsplit('/').next().unwrap_or(path).to_owned();
+        if agency != DEFAULT_AGENCY {
+            name = format!("{name}-{agency}");
+        }
+        for (_, value) in params {
+            name = format!("{name}-{value}");
+        }
+        dir.join(format!("{name}.json"))
+    }
+
+    /// Failing to save a response is logged rather than failing the request
+    /// it answered.
+    fn record(dir: &Path, url: &str, agency: &str, params: &[(&str, &str)], body: &str) {
+        let file = Self::file(dir, url, agency, params);
+        if let Err(e) = fs::create_dir_all(dir).and_then(|()| fs::write(&file, body)) {
+            warn!("couldn't record the response to {}: {e}", file.display());
+        }
+    }
+}
+
+/// How important an outbound request is. Lower priorities may only spend part
+/// of each hour's budget, so however busy they get there is always quota left
+/// for the board's own refreshes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Realtime predictions for the board.
+    High,
+    Normal,
+    Low,
+}
+
+impl Priority {
+    /// The fraction of each hour's budget requests at this priority may use.
+    fn share(self) -> f32 {
+        match self {
+            Priority::High => 1.0,
+            Priority::Normal => 0.75,
+            Priority::Low => 0.5,
+        }
+    }
+}
+
+/// A cap on outbound requests per hour, shared by every feature that calls
+/// the transit API.
+#[derive(Debug)]
+pub struct RequestBudget {
+    per_hour: u32,
+    window: Mutex<Window>,
+}
+
+#[derive(Debug)]
+struct Window {
+    started: Instant,
+    used: u32,
+}
+
+impl RequestBudget {
+    pub fn new(per_hour: u32) -> Self {
+        Self {
+            per_hour,
+            window: Mutex::new(Window {
+                started: Instant::now(),
+                used: 0,
+            }),
+        }
+    }
+
+    /// Spend one request from the budget, or fail if requests at `priority`
+    /// have used up their share of this hour.
+    pub fn acquire(&self, priority: Priority) -> eyre::Result<()> {
+        self.acquire_at(priority, Instant::now())
+    }
+
+    fn acquire_at(&self, priority: Priority, now: Instant) -> eyre::Result<()> {
+        let mut window = self.window.lock().unwrap();
+        if now.duration_since(window.started) >= BUDGET_WINDOW {
+            *window = Window {
+                started: now,
+                used: 0,
+            };
+        }
+
+        let limit = (self.per_hour as f32 * priority.share()).floor() as u32;
+        ensure!(
+            window.used < limit,
+            "outbound request budget used up for {priority:?} priority ({} of {} this hour)",
+            window.used,
+            self.per_hour
+        );
+
+        window.used += 1;
+        Ok(())
+    }
+
+    pub fn metrics(&self) -> String {
+        let window = self.window.lock().unwrap();
+        let mut metrics = String::new();
+
+        let _ = writeln!(
+            metrics,
+            "# HELP transit_kindle_upstream_budget_requests Outbound requests allowed per hour."
+        );
+        let _ = writeln!(
+            metrics,
+            "# TYPE transit_kindle_upstream_budget_requests gauge"
+        );
+        let _ = writeln!(
+            metrics,
+            "transit_kindle_upstream_budget_requests {}",
+            self.per_hour
+        );
+        let _ = writeln!(
+            metrics,
+            "# HELP transit_kindle_upstream_budget_used Outbound requests made in the current hour."
+        );
+        let _ = writeln!(metrics, "# TYPE transit_kindle_upstream_budget_used gauge");
+        let _ = writeln!(
+            metrics,
+            "transit_kindle_upstream_budget_used {}",
+            window.used
+        );
+
+        metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spend(budget: &RequestBudget, priority: Priority, now: Instant) -> u32 {
+        let mut spent = 0;
+        while budget.acquire_at(priority, now).is_ok() {
+            spent += 1;
+        }
+        spent
+    }
+
+    #[test]
+    fn each_priority_gets_its_share() {
+        let now = Instant::now();
+
+        assert_eq!(spend(&RequestBudget::new(100), Priority::High, now), 100);
+        assert_eq!(spend(&RequestBudget::new(100), Priority::Normal, now), 75);
+        assert_eq!(spend(&RequestBudget::new(100), Priority::Low, now), 50);
+    }
+
+    #[test]
+    fn lower_priorities_leave_the_rest_for_higher_ones() {
+        let budget = RequestBudget::new(100);
+        let now = Instant::now();
+
+        assert_eq!(spend(&budget, Priority::Low, now), 50);
+        assert_eq!(spend(&budget, Priority::Normal, now), 25);
+        assert_eq!(spend(&budget, Priority::High, now), 25);
+    }
+
+    #[test]
+    fn shares_round_down() {
+        assert_eq!(
+            spend(&RequestBudget::new(3), Priority::Low, Instant::now()),
+            1
+        );
+    }
+
+    #[test]
+    fn budget_resets_each_hour() {
+        let budget = RequestBudget::new(10);
+        let now = Instant::now();
+        assert_eq!(spend(&budget, Priority::High, now), 10);
+
+        let almost = now + BUDGET_WINDOW - Duration::from_secs(1);
+        assert!(budget.acquire_at(Priority::High, almost).is_err());
+
+        let next_hour = now + BUDGET_WINDOW;
+        assert_eq!(spend(&budget, Priority::High, next_hour), 10);
+    }
+}
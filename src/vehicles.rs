@@ -0,0 +1,236 @@
+use std::{collections::HashMap, time::Duration};
+
+use serde::Deserialize;
+
+use crate::{
+    cache::Refreshed,
+    departures::LinesDestinationsToJourneys,
+    quirks,
+    siri::{FramedVehicleJourneyRef, MonitoredVehicleJourney},
+    upstream::{Priority, Transport, Upstream, DEFAULT_AGENCY},
+};
+
+const VEHICLE_MONITORING_URL: &str = "http://api.511.org/transit/VehicleMonitoring";
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct VehiclesConfig {
+    /// How long vehicle positions are reused before asking 511 again. Each
+    /// fetch spends from the hourly request budget.
+    pub refresh_secs: u64,
+}
+
+impl Default for VehiclesConfig {
+    fn default() -> Self {
+        Self { refresh_secs: 120 }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Response {
+    service_delivery: ServiceDelivery,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ServiceDelivery {
+    vehicle_monitoring_delivery: VehicleMonitoringDelivery,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct VehicleMonitoringDelivery {
+    #[serde(default, deserialize_with = "quirks::skip_malformed")]
+    vehicle_activity: Vec<VehicleActivity>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct VehicleActivity {
+    monitored_vehicle_journey: VehicleJourney,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct VehicleJourney {
+    #[serde(default, deserialize_with = "quirks::opt_string")]
+    line_ref: Option<String>,
+    #[serde(default)]
+    framed_vehicle_journey_ref: Option<FramedVehicleJourneyRef>,
+    #[serde(default, deserialize_with = "quirks::opt_string")]
+    vehicle_ref: Option<String>,
+    #[serde(default)]
+    vehicle_location: Option<VehicleLocation>,
+    #[serde(default)]
+    monitored_call: Option<Call>,
+    #[serde(default)]
+    onward_calls: Option<OnwardCalls>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct VehicleLocation {
+    #[serde(deserialize_with = "coordinate")]
+    longitude: f64,
+    #[serde(deserialize_with = "coordinate")]
+    latitude: f64,
+}
+
+/// 511 sends coordinates as strings.
+fn coordinate<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    use serde::de::Error;
+
+    let text = quirks::string(deserializer)?;
+    text.trim().parse().map_err(D::Error::custom)
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Call {
+    #[serde(deserialize_with = "quirks::string")]
+    stop_point_ref: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct OnwardCalls {
+    #[serde(default, deserialize_with = "quirks::skip_malformed")]
+    onward_call: Vec<Call>,
+}
+
+/// Where a vehicle is and the stops it will call at next.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vehicle {
+    pub line: Option<String>,
+    /// The same as [`MonitoredVehicleJourney::trip`] for its journey.
+    pub trip: Option<String>,
+    pub vehicle: Option<String>,
+    /// Latitude and longitude.
+    pub location: Option<(f64, f64)>,
+    /// The stop it's heading for, then the rest in order.
+    pub stops: Vec<String>,
+}
+
+impl Vehicle {
+    /// How many stops it calls at before and including `stop`: 1 if `stop`
+    /// is the next one. `None` if it isn't going there, or the feed didn't
+    /// list that far ahead.
+    pub fn stops_away(&self, stop: &str) -> Option<u32> {
+        let index = self.stops.iter().position(|s| s == stop)?;
+        Some(index as u32 + 1)
+    }
+
+    /// Whether this is the vehicle running `journey`.
+    fn runs(&self, journey: &MonitoredVehicleJourney) -> bool {
+        match (&self.trip, journey.trip()) {
+            (Some(trip), Some(journey_trip)) => *trip == journey_trip,
+            _ => {
+                self.vehicle.is_some()
+                    && self.vehicle == journey.vehicle_ref
+                    && self.line == journey.line_ref
+            }
+        }
+    }
+}
+
+/// Parse 511's VehicleMonitoring feed.
+pub fn parse(body: &str) -> eyre::Result<Vec<Vehicle>> {
+    let response: Response = quirks::from_json_or_xml(body)?;
+
+    Ok(response
+        .service_delivery
+        .vehicle_monitoring_delivery
+        .vehicle_activity
+        .into_iter()
+        .map(|activity| {
+            let journey = activity.monitored_vehicle_journey;
+            // Built the way `MonitoredVehicleJourney::trip` builds it, so the
+            // two can be matched up.
+            let trip = journey
+                .framed_vehicle_journey_ref
+                .as_ref()
+                .and_then(|framed| {
+                    let dated = framed.dated_vehicle_journey_ref.as_deref()?;
+                    Some(format!(
+                        "{}/{dated}",
+                        framed.data_frame_ref.as_deref().unwrap_or_default()
+                    ))
+                });
+            let stops = journey
+                .monitored_call
+                .into_iter()
+                .chain(
+                    journey
+                        .onward_calls
+                        .into_iter()
+                        .flat_map(|calls| calls.onward_call),
+                )
+                .map(|call| call.stop_point_ref)
+                .collect();
+
+            Vehicle {
+                line: journey.line_ref,
+                trip,
+                vehicle: journey.vehicle_ref,
+                location: journey
+                    .vehicle_location
+                    .map(|location| (location.latitude, location.longitude)),
+                stops,
+            }
+        })
+        .collect())
+}
+
+/// Note on each journey how many stops away its vehicle is from the stop
+/// it's listed for, where `vehicles` knows.
+pub fn locate(departures: &mut HashMap<String, LinesDestinationsToJourneys>, vehicles: &[Vehicle]) {
+    let journeys = departures
+        .values_mut()
+        .flat_map(|by_line| by_line.values_mut())
+        .flatten();
+    for journey in journeys {
+        journey.stops_away = vehicles
+            .iter()
+            .find(|vehicle| vehicle.runs(journey))
+            .and_then(|vehicle| vehicle.stops_away(&journey.monitored_call.stop_point_ref));
+    }
+}
+
+/// Vehicle positions from 511's VehicleMonitoring feed, refreshed at most
+/// every `refresh_secs`.
+#[derive(Debug)]
+pub struct VehicleTracker(Option<Refreshed<Vec<Vehicle>>>);
+
+impl VehicleTracker {
+    /// Tracks nothing if `config` is `None`.
+    pub fn new(config: Option<VehiclesConfig>) -> Self {
+        Self(config.map(|config| Refreshed::new(Duration::from_secs(config.refresh_secs))))
+    }
+
+    /// Every vehicle in the feed, or `None` if vehicles aren't tracked.
+    pub async fn get(
+        &self,
+        client: &dyn Transport,
+        upstream: &Upstream,
+    ) -> Option<eyre::Result<Vec<Vehicle>>> {
+        let cache = self.0.as_ref()?;
+
+        Some(
+            cache
+                .get("vehicle positions", async {
+                    let body = upstream
+                        .get_siri_for(
+                            client,
+                            VEHICLE_MONITORING_URL,
+                            DEFAULT_AGENCY,
+                            &[],
+                            Priority::Normal,
+                        )
+                        .await?;
+                    parse(&body)
+                })
+                .await,
+        )
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    collections::HashMap,
+    io::{stdout, Stdout},
+    time::{Duration, Instant},
+};
+
+use chrono::prelude::*;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind},
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    ExecutableCommand,
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout as TuiLayout},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, Paragraph, Row, Table},
+    Frame, Terminal,
+};
+use reqwest::Client;
+
+use crate::{
+    config::Config,
+    departures::{self, LinesDestinationsToJourneys, Upcoming, WalkTimes},
+    layout::{Layout, Panel},
+    upstream::Upstream,
+};
+
+/// How long to wait for a keypress before redrawing, so the minute counts
+/// stay current between fetches.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(500);
+
+type Directions = HashMap<String, LinesDestinationsToJourneys>;
+
+/// Show a live-updating board in the terminal until `q` or Esc is pressed.
+pub async fn run(
+    config: &Config,
+    layout: &Layout,
+    upstream: &Upstream,
+    fetch_interval: Duration,
+) -> eyre::Result<()> {
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?;
+
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+    let result = watch(&mut terminal, config, layout, upstream, fetch_interval).await;
+
+    disable_raw_mode()?;
+    stdout().execute(LeaveAlternateScreen)?;
+
+    result
+}
+
+async fn watch(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    config: &Config,
+    layout: &Layout,
+    upstream: &Upstream,
+    fetch_interval: Duration,
+) -> eyre::Result<()> {
+    let client = Client::new();
+
+    // Columns follow the departures panels of the configured board.
+    let directions: Vec<(&str, Upcoming)> = layout
+        .panels
+        .iter()
+        .filter_map(|panel| match panel {
+            Panel::Departures { direction, .. } => {
+                Some((direction.as_str(), layout.upcoming(panel)))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let walk_times = config.walk_times();
+    let trips = config.trips()?;
+    let mut board = Directions::new();
+    let mut status = String::from("Fetching…");
+    let mut last_fetch: Option<Instant> = None;
+
+    loop {
+        if last_fetch.is_none_or(|fetched| fetched.elapsed() >= fetch_interval) {
+            match departures::fetch(&client, upstream, &config.stops, &config.grouping, &trips)
+                .await
+            {
+                Ok(fetched) => {
+                    board = fetched;
+                    status = format!(
+                        "Updated {}",
+                        Utc::now()
+                            .with_timezone(&config.timezone)
+                            .format("%H:%M:%S")
+                    );
+                }
+                Err(e) => status = format!("Fetch failed: {e}"),
+            }
+            last_fetch = Some(Instant::now());
+        }
+
+        terminal.draw(|frame| {
+            draw(
+                frame,
+                &directions,
+                &board,
+                &walk_times,
+                &config.grouping.line_order,
+                &status,
+            )
+        })?;
+
+        if event::poll(REDRAW_INTERVAL)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press
+                    && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+                {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn draw(
+    frame: &mut Frame,
+    directions: &[(&str, Upcoming)],
+    board: &Directions,
+    walk_times: &WalkTimes,
+    line_order: &[String],
+    status: &str,
+) {
+    let rows = TuiLayout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(frame.size());
+    let (main, footer) = (rows[0], rows[1]);
+
+    let columns = TuiLayout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![
+            Constraint::Ratio(1, directions.len().max(1) as u32);
+            directions.len()
+        ])
+        .split(main);
+
+    let now = Utc::now();
+    let no_journeys = LinesDestinationsToJourneys::new();
+
+    for ((direction, upcoming), area) in directions.iter().zip(columns.iter()) {
+        let lines_destinations_to_journeys = board.get(*direction).unwrap_or(&no_journeys);
+
+        let mut rows: Vec<_> = lines_destinations_to_journeys.iter().collect();
+        rows.sort_by(|(a, _), (b, _)| {
+            departures::row_key(a, line_order).cmp(&departures::row_key(b, line_order))
+        });
+
+        let rows = rows.into_iter().map(|((line_id, destination), journeys)| {
+            Row::new(vec![
+                line_id.clone(),
+                destination.clone(),
+                departures::times_str(&departures::arrivals(journeys, now, upcoming, walk_times)),
+            ])
+        });
+
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(4),
+                Constraint::Min(10),
+                Constraint::Length(14),
+            ],
+        )
+        .header(
+            Row::new(vec!["Line", "Destination", "Arriving"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(direction.to_string()),
+        );
+
+        frame.render_widget(table, *area);
+    }
+
+    frame.render_widget(
+        Paragraph::new(Line::from(format!("{status} · q to quit"))),
+        footer,
+    );
+}
@@ -0,0 +1,261 @@
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use reqwest::Client;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{cache::Refreshed, time};
+
+const OPEN_METEO_URL: &str = "https://api.open-meteo.com/v1/forecast";
+const OPEN_METEO_AIR_QUALITY_URL: &str = "https://air-quality-api.open-meteo.com/v1/air-quality";
+
+/// Hours ahead the chance of precipitation is taken over.
+const PRECIPITATION_HOURS: usize = 2;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TemperatureUnit {
+    #[default]
+    Fahrenheit,
+    Celsius,
+}
+
+impl TemperatureUnit {
+    fn query(self) -> &'static str {
+        match self {
+            TemperatureUnit::Fahrenheit => "fahrenheit",
+            TemperatureUnit::Celsius => "celsius",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WeatherConfig {
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default)]
+    pub unit: TemperatureUnit,
+    /// How long a forecast is reused before asking Open-Meteo again.
+    #[serde(default = "default_refresh_secs")]
+    pub refresh_secs: u64,
+}
+
+fn default_refresh_secs() -> u64 {
+    900
+}
+
+#[derive(Deserialize, Debug)]
+struct Forecast {
+    current: Current,
+    hourly: Hourly,
+    daily: Option<Daily>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Current {
+    temperature_2m: f64,
+    weather_code: u8,
+}
+
+#[derive(Deserialize, Debug)]
+struct Hourly {
+    #[serde(default)]
+    precipitation_probability: Vec<Option<u8>>,
+}
+
+/// Today's sunrise and sunset, in the requested timezone without an offset,
+/// e.g. "2024-02-01T07:12".
+#[derive(Deserialize, Debug)]
+struct Daily {
+    #[serde(default)]
+    sunrise: Vec<String>,
+    #[serde(default)]
+    sunset: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AirQuality {
+    current: AirQualityCurrent,
+}
+
+#[derive(Deserialize, Debug)]
+struct AirQualityCurrent {
+    us_aqi: Option<f64>,
+}
+
+/// What the sky is doing, for the weather icon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sky {
+    Clear,
+    PartlyCloudy,
+    Cloudy,
+    Fog,
+    Rain,
+    Snow,
+    Storm,
+}
+
+impl Sky {
+    /// The sky for a WMO weather interpretation code, as Open-Meteo reports.
+    pub fn from_wmo(code: u8) -> Self {
+        match code {
+            0 | 1 => Sky::Clear,
+            2 => Sky::PartlyCloudy,
+            3 => Sky::Cloudy,
+            45 | 48 => Sky::Fog,
+            71..=77 | 85 | 86 => Sky::Snow,
+            95..=99 => Sky::Storm,
+            51..=67 | 80..=82 => Sky::Rain,
+            _ => Sky::Cloudy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Weather {
+    /// In the configured unit.
+    pub temperature: f64,
+    pub sky: Sky,
+    /// Highest chance of precipitation over the next couple of hours, in
+    /// percent.
+    pub precipitation_chance: u8,
+    pub sunrise: Option<DateTime<Utc>>,
+    pub sunset: Option<DateTime<Utc>>,
+    /// US air quality index, if it could be fetched.
+    pub aqi: Option<u16>,
+}
+
+impl Weather {
+    /// e.g. "62° · 20% rain".
+    pub fn label(&self) -> String {
+        format!(
+            "{:.0}° · {}% rain",
+            self.temperature, self.precipitation_chance
+        )
+    }
+}
+
+/// Parse an Open-Meteo forecast whose hourly series starts at the current
+/// hour, and whose daily times were asked for in `tz`.
+pub fn parse(body: &str, tz: Tz) -> eyre::Result<Weather> {
+    let forecast: Forecast = serde_json::from_str(body)?;
+    let today = |times: &[String]| {
+        let time = NaiveDateTime::parse_from_str(times.first()?, "%Y-%m-%dT%H:%M").ok()?;
+        Some(time::local_datetime(tz, time).with_timezone(&Utc))
+    };
+
+    Ok(Weather {
+        temperature: forecast.current.temperature_2m,
+        sky: Sky::from_wmo(forecast.current.weather_code),
+        precipitation_chance: forecast
+            .hourly
+            .precipitation_probability
+            .iter()
+            .take(PRECIPITATION_HOURS)
+            .flatten()
+            .copied()
+            .max()
+            .unwrap_or(0),
+        sunrise: forecast
+            .daily
+            .as_ref()
+            .and_then(|daily| today(&daily.sunrise)),
+        sunset: forecast
+            .daily
+            .as_ref()
+            .and_then(|daily| today(&daily.sunset)),
+        aqi: None,
+    })
+}
+
+/// Parse the US AQI from an Open-Meteo air quality response.
+pub fn parse_aqi(body: &str) -> eyre::Result<Option<u16>> {
+    let air_quality: AirQuality = serde_json::from_str(body)?;
+
+    Ok(air_quality.current.us_aqi.map(|aqi| aqi.round() as u16))
+}
+
+/// The current weather and air quality, refetched at most every
+/// `refresh_secs`.
+#[derive(Debug)]
+pub struct WeatherSource {
+    config: Option<WeatherConfig>,
+    /// Where "today" is, for sunrise and sunset.
+    timezone: Tz,
+    cache: Refreshed<Weather>,
+}
+
+impl WeatherSource {
+    /// Reports nothing if `config` is `None`.
+    pub fn new(config: Option<WeatherConfig>, timezone: Tz) -> Self {
+        let refresh_secs = config.as_ref().map_or(0, |config| config.refresh_secs);
+
+        Self {
+            config,
+            timezone,
+            cache: Refreshed::new(Duration::from_secs(refresh_secs)),
+        }
+    }
+
+    /// The current weather, or `None` if it isn't configured.
+    pub async fn get(&self, client: &Client) -> Option<eyre::Result<Weather>> {
+        let config = self.config.as_ref()?;
+
+        let weather = self
+            .cache
+            .get("weather", async {
+                let latitude = config.latitude.to_string();
+                let longitude = config.longitude.to_string();
+
+                let body = client
+                    .get(OPEN_METEO_URL)
+                    .query(&[
+                        ("latitude", latitude.as_str()),
+                        ("longitude", longitude.as_str()),
+                        ("current", "temperature_2m,weather_code"),
+                        ("hourly", "precipitation_probability"),
+                        ("forecast_hours", PRECIPITATION_HOURS.to_string().as_str()),
+                        ("daily", "sunrise,sunset"),
+                        ("forecast_days", "1"),
+                        ("timezone", self.timezone.name()),
+                        ("temperature_unit", config.unit.query()),
+                    ])
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await?;
+                let mut weather = parse(&body, self.timezone)?;
+
+                // Air quality is a nice-to-have, so a failure leaves it out
+                // rather than losing the forecast.
+                let aqi = async {
+                    let body = client
+                        .get(OPEN_METEO_AIR_QUALITY_URL)
+                        .query(&[
+                            ("latitude", latitude.as_str()),
+                            ("longitude", longitude.as_str()),
+                            ("current", "us_aqi"),
+                        ])
+                        .send()
+                        .await?
+                        .error_for_status()?
+                        .text()
+                        .await?;
+                    parse_aqi(&body)
+                };
+                match aqi.await {
+                    Ok(aqi) => weather.aqi = aqi,
+                    Err(e) => warn!("couldn't fetch air quality: {e:#}"),
+                }
+
+                Ok(weather)
+            })
+            .await;
+
+        Some(weather)
+    }
+}
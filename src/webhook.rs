@@ -0,0 +1,167 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+fn default_after_failures() -> u32 {
+    3
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// Receives a JSON `POST` when a view keeps failing.
+    pub url: String,
+    /// Consecutive failures before the webhook fires. 0 fires on the first,
+    /// the same as 1.
+    #[serde(default = "default_after_failures")]
+    pub after_failures: u32,
+}
+
+/// Sent to the webhook once per run of failures.
+#[derive(Serialize, Debug, Clone)]
+pub struct FailureReport {
+    pub view: String,
+    pub error: String,
+    pub consecutive_failures: u32,
+    /// When the view last drew without errors, if it has since startup.
+    pub last_good: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default)]
+struct Streak {
+    failures: u32,
+    last_good: Option<DateTime<Utc>>,
+}
+
+/// Tracks failures of each view and calls the webhook when one keeps failing,
+/// so whoever looks after a display hears about it before its viewers do.
+#[derive(Debug)]
+pub struct FailureWebhook {
+    config: Option<WebhookConfig>,
+    client: Client,
+    streaks: Mutex<HashMap<String, Streak>>,
+}
+
+impl FailureWebhook {
+    pub fn new(config: Option<WebhookConfig>) -> Self {
+        Self {
+            config,
+            client: Client::new(),
+            streaks: Mutex::default(),
+        }
+    }
+
+    pub fn success(&self, view: &str, now: DateTime<Utc>) {
+        let mut streaks = self.streaks.lock().unwrap();
+        let streak = streaks.entry(view.to_owned()).or_default();
+        streak.failures = 0;
+        streak.last_good = Some(now);
+    }
+
+    /// Count a failure of `view`, calling the webhook in the background when
+    /// it reaches the configured number in a row.
+    pub fn failure(&self, view: &str, error: &str) {
+        let Some(config) = &self.config else {
+            return;
+        };
+        let Some(report) = self.count_failure(config, view, error) else {
+            return;
+        };
+
+        let request = self.client.post(&config.url).json(&report);
+        tokio::spawn(async move {
+            if let Err(e) = request.send().await.and_then(|r| r.error_for_status()) {
+                warn!("failure webhook: {e}");
+            }
+        });
+    }
+
+    /// Count a failure of `view`, returning the report to send if this one
+    /// reaches the threshold. Only the failure that reaches it reports, so a
+    /// view that stays down isn't reported on every refresh.
+    fn count_failure(
+        &self,
+        config: &WebhookConfig,
+        view: &str,
+        error: &str,
+    ) -> Option<FailureReport> {
+        let mut streaks = self.streaks.lock().unwrap();
+        let streak = streaks.entry(view.to_owned()).or_default();
+        streak.failures += 1;
+
+        (streak.failures == config.after_failures.max(1)).then(|| FailureReport {
+            view: view.to_owned(),
+            error: error.to_owned(),
+            consecutive_failures: streak.failures,
+            last_good: streak.last_good,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn webhook(after_failures: u32) -> (FailureWebhook, WebhookConfig) {
+        let config = WebhookConfig {
+            url: "http://localhost/hook".to_owned(),
+            after_failures,
+        };
+        (FailureWebhook::new(Some(config.clone())), config)
+    }
+
+    /// Which of `count` failures in a row would call the webhook, 1-based.
+    fn reported(webhook: &FailureWebhook, config: &WebhookConfig, count: u32) -> Vec<u32> {
+        (1..=count)
+            .filter(|_| {
+                webhook
+                    .count_failure(config, "default", "511 down")
+                    .is_some()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fires_once_on_reaching_the_threshold() {
+        let (webhook, config) = webhook(3);
+
+        assert_eq!(reported(&webhook, &config, 6), [3]);
+    }
+
+    #[test]
+    fn zero_fires_on_the_first_failure() {
+        let (webhook, config) = webhook(0);
+
+        assert_eq!(reported(&webhook, &config, 3), [1]);
+    }
+
+    #[test]
+    fn recovery_starts_a_new_streak() {
+        let (webhook, config) = webhook(2);
+        let recovered = "2024-02-01T18:00:00Z".parse().unwrap();
+
+        assert_eq!(reported(&webhook, &config, 2), [2]);
+        webhook.success("default", recovered);
+        assert!(webhook
+            .count_failure(&config, "default", "511 down")
+            .is_none());
+
+        let report = webhook
+            .count_failure(&config, "default", "511 down")
+            .expect("second failure after recovery reports");
+        assert_eq!(report.consecutive_failures, 2);
+        assert_eq!(report.last_good, Some(recovered));
+    }
+
+    #[test]
+    fn views_are_counted_apart() {
+        let (webhook, config) = webhook(2);
+
+        assert!(webhook.count_failure(&config, "default", "down").is_none());
+        assert!(webhook.count_failure(&config, "lobby", "down").is_none());
+        assert!(webhook.count_failure(&config, "default", "down").is_some());
+    }
+}
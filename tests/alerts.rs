@@ -0,0 +1,78 @@
+//! Service alerts from a hand-made 511 GTFS-realtime payload, and which of
+//! them a board shows.
+
+use chrono::{DateTime, Utc};
+use transit_kindle_playground::alerts::{self, ServiceAlert};
+
+/// Shortly after 10am Pacific, while the 24 detour is on.
+const NOW: &str = "2024-02-01T18:03:00Z";
+
+fn now() -> DateTime<Utc> {
+    NOW.parse().unwrap()
+}
+
+fn parse() -> Vec<ServiceAlert> {
+    alerts::parse(include_str!("fixtures/511_service_alerts.json")).expect("fixture parses")
+}
+
+fn texts(alerts: &[ServiceAlert]) -> Vec<&str> {
+    alerts.iter().map(|alert| alert.text.as_str()).collect()
+}
+
+#[test]
+fn alerts_without_text_are_left_out() {
+    assert_eq!(
+        texts(&parse()),
+        [
+            "24 Divisadero detour at Castro & 26th St",
+            "Stop closed: 24th St & Noe St",
+            "J Church shuttle buses",
+            "48 Quintara reroute from Saturday",
+        ]
+    );
+}
+
+#[test]
+fn informed_lines_and_stops_are_kept() {
+    let alerts = parse();
+
+    assert_eq!(alerts[0].lines, ["24"]);
+    assert!(alerts[0].stops.is_empty());
+    // Sent as a number.
+    assert_eq!(alerts[1].stops, ["15692"]);
+    assert!(alerts[1].lines.is_empty());
+}
+
+#[test]
+fn active_periods_bound_when_an_alert_applies() {
+    let alerts = parse();
+    let active: Vec<bool> = alerts.iter().map(|alert| alert.is_active(now())).collect();
+
+    // On now, no periods given, ended yesterday, starts on Saturday.
+    assert_eq!(active, [true, true, false, false]);
+
+    let ends: DateTime<Utc> = "2024-02-01T23:00:00Z".parse().unwrap();
+    assert!(alerts[0].is_active(ends - chrono::Duration::seconds(1)));
+    assert!(!alerts[0].is_active(ends));
+    assert!(alerts[3].is_active("2024-02-03T12:00:00Z".parse().unwrap()));
+}
+
+#[test]
+fn relevant_alerts_match_a_line_or_a_stop() {
+    let relevant = alerts::relevant(parse(), &["24", "J", "48"], &["15692"], now());
+
+    assert_eq!(
+        texts(&relevant),
+        [
+            "24 Divisadero detour at Castro & 26th St",
+            "Stop closed: 24th St & Noe St",
+        ]
+    );
+}
+
+#[test]
+fn alerts_for_other_lines_and_stops_are_not_relevant() {
+    let relevant = alerts::relevant(parse(), &["N"], &["15419"], now());
+
+    assert!(relevant.is_empty());
+}
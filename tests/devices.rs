@@ -0,0 +1,62 @@
+//! The per-device polling statistics behind `/api/devices`.
+
+use chrono::{DateTime, Duration, Utc};
+use transit_kindle_playground::devices::{DeviceRegistry, MAX_DEVICES};
+
+fn at(minutes: i64) -> DateTime<Utc> {
+    "2024-02-01T18:00:00Z".parse::<DateTime<Utc>>().unwrap() + Duration::minutes(minutes)
+}
+
+fn record(registry: &DeviceRegistry, id: &str, now: DateTime<Utc>) {
+    registry.record(id, now, now.date_naive(), false);
+}
+
+fn ids(registry: &DeviceRegistry) -> Vec<String> {
+    registry
+        .summaries()
+        .into_iter()
+        .map(|summary| summary.id)
+        .collect()
+}
+
+#[test]
+fn the_device_seen_longest_ago_makes_room() {
+    let registry = DeviceRegistry::default();
+    for i in 0..MAX_DEVICES {
+        record(&registry, &format!("kindle-{i}"), at(i as i64));
+    }
+    // Seen again, so no longer the oldest.
+    record(&registry, "kindle-0", at(100));
+
+    record(&registry, "newcomer", at(101));
+
+    let ids = ids(&registry);
+    assert_eq!(ids.len(), MAX_DEVICES);
+    assert_eq!(ids[0], "newcomer");
+    assert!(ids.contains(&"kindle-0".to_owned()));
+    assert!(!ids.contains(&"kindle-1".to_owned()));
+}
+
+#[test]
+fn idle_devices_are_forgotten() {
+    let registry = DeviceRegistry::default();
+    record(&registry, "old", at(0));
+    record(&registry, "recent", at(60 * 24 * 6));
+
+    record(&registry, "new", at(60 * 24 * 8));
+
+    assert_eq!(ids(&registry), ["new", "recent"]);
+}
+
+#[test]
+fn known_devices_are_counted_without_evicting() {
+    let registry = DeviceRegistry::default();
+    record(&registry, "kitchen", at(0));
+    record(&registry, "kitchen", at(1));
+    registry.record("kitchen", at(2), at(2).date_naive(), true);
+
+    let summaries = registry.summaries();
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(summaries[0].average_interval_secs, Some(60.0));
+    assert!((summaries[0].not_modified_ratio - 1.0 / 3.0).abs() < 1e-9);
+}
@@ -0,0 +1,444 @@
+//! The fetch path run against canned 511 replies instead of the network, so
+//! key rotation and error handling are covered along with parsing.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use futures_util::future::BoxFuture;
+use reqwest::{header::HeaderMap, StatusCode};
+use transit_kindle_playground::{
+    alerts::{AlertsCache, AlertsConfig},
+    config::Config,
+    departures::{self, Grouping, Stop},
+    siri::SiriFormat,
+    trips::Trips,
+    upstream::{Reply, Transport, Upstream},
+};
+
+const STOPS: [&str; 4] = ["15419", "16996", "15692", "15696"];
+
+fn stops() -> Vec<Stop> {
+    STOPS
+        .iter()
+        .map(|code| Stop {
+            code: (*code).to_owned(),
+            walk_minutes: 0,
+            agency: None,
+        })
+        .collect()
+}
+
+/// The Muni stops, one of them twice, and a Caltrain stop.
+fn mixed_stops() -> Vec<Stop> {
+    let mut stops = stops();
+    stops.push(stops[0].clone());
+    stops.push(Stop {
+        code: "70012".to_owned(),
+        walk_minutes: 0,
+        agency: Some("CT".to_owned()),
+    });
+    stops
+}
+
+/// Answers every request with `status` and `body` after `delay`, except that
+/// keys in `refused` get a 429. Remembers which key, agency, stop code and
+/// format each request used, and the most requests it had in flight at once.
+#[derive(Debug)]
+struct Canned {
+    status: StatusCode,
+    body: &'static str,
+    refused: Vec<&'static str>,
+    keys_used: Mutex<Vec<String>>,
+    queried: Mutex<Vec<(String, String)>>,
+    formats: Mutex<Vec<Option<String>>>,
+    delay: Duration,
+    in_flight: AtomicUsize,
+    peak_in_flight: AtomicUsize,
+}
+
+impl Canned {
+    fn new(status: StatusCode, body: &'static str) -> Self {
+        Self {
+            status,
+            body,
+            refused: Vec::new(),
+            keys_used: Mutex::default(),
+            queried: Mutex::default(),
+            formats: Mutex::default(),
+            delay: Duration::ZERO,
+            in_flight: AtomicUsize::new(0),
+            peak_in_flight: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Transport for Canned {
+    fn get<'a>(
+        &'a self,
+        _url: &'a str,
+        query: &'a [(&'a str, &'a str)],
+    ) -> BoxFuture<'a, eyre::Result<Reply>> {
+        Box::pin(async move {
+            let param = |wanted: &str| {
+                query
+                    .iter()
+                    .find(|(name, _)| *name == wanted)
+                    .map(|(_, value)| *value)
+                    .unwrap_or_default()
+            };
+            let key = param("api_key");
+            self.keys_used.lock().unwrap().push(key.to_owned());
+            self.queried
+                .lock()
+                .unwrap()
+                .push((param("agency").to_owned(), param("stopcode").to_owned()));
+            self.formats.lock().unwrap().push(
+                query
+                    .iter()
+                    .find(|(name, _)| *name == "format")
+                    .map(|(_, value)| value.to_string()),
+            );
+
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            if !self.delay.is_zero() {
+                tokio::time::sleep(self.delay).await;
+            }
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            let status = match self.refused.contains(&key) {
+                true => StatusCode::TOO_MANY_REQUESTS,
+                false => self.status,
+            };
+
+            Ok(Reply {
+                status,
+                headers: HeaderMap::new(),
+                body: self.body.to_owned(),
+            })
+        })
+    }
+}
+
+fn upstream(keys: &[&str]) -> Upstream {
+    Upstream::new(&Config {
+        api_keys: keys.iter().map(|key| key.to_string()).collect(),
+        ..Config::default()
+    })
+}
+
+/// Check that `transport` was asked for exactly `expected`, as (agency, stop
+/// code) pairs in any order, with an empty code for a whole agency feed.
+fn assert_queried(transport: &Canned, expected: &[(&str, &str)]) {
+    let queried = transport.queried.lock().unwrap();
+    let mut queried: Vec<_> = queried
+        .iter()
+        .map(|(agency, code)| (agency.as_str(), code.as_str()))
+        .collect();
+    queried.sort_unstable();
+
+    assert_eq!(queried, expected);
+}
+
+#[tokio::test]
+async fn groups_canned_departures() {
+    let transport = Canned::new(
+        StatusCode::OK,
+        include_str!("fixtures/511_stop_monitoring_sf.json"),
+    );
+
+    let directions = departures::fetch(
+        &transport,
+        &upstream(&["key"]),
+        &stops(),
+        &Grouping::default(),
+        &Trips::default(),
+    )
+    .await
+    .unwrap();
+
+    let mut names: Vec<_> = directions.keys().map(String::as_str).collect();
+    names.sort_unstable();
+    assert_eq!(names, ["IB", "OB"]);
+}
+
+#[tokio::test]
+async fn each_stop_is_fetched_once() {
+    let transport = Canned::new(
+        StatusCode::OK,
+        include_str!("fixtures/511_stop_monitoring_sf.json"),
+    );
+    let upstream = Upstream::new(&Config {
+        api_keys: vec!["key".to_owned()],
+        whole_agency_feed: Some(false),
+        ..Config::default()
+    });
+
+    departures::fetch(
+        &transport,
+        &upstream,
+        &mixed_stops(),
+        &Grouping::default(),
+        &Trips::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_queried(
+        &transport,
+        &[
+            ("CT", "70012"),
+            ("SF", "15419"),
+            ("SF", "15692"),
+            ("SF", "15696"),
+            ("SF", "16996"),
+        ],
+    );
+}
+
+#[tokio::test]
+async fn repeated_fetches_reuse_recent_responses() {
+    let transport = Canned::new(
+        StatusCode::OK,
+        include_str!("fixtures/511_stop_monitoring_sf.json"),
+    );
+    let upstream = Upstream::new(&Config {
+        api_keys: vec!["key".to_owned()],
+        whole_agency_feed: Some(false),
+        ..Config::default()
+    });
+
+    for _ in 0..3 {
+        departures::fetch(
+            &transport,
+            &upstream,
+            &stops(),
+            &Grouping::default(),
+            &Trips::default(),
+        )
+        .await
+        .unwrap();
+    }
+
+    assert_eq!(transport.queried.lock().unwrap().len(), STOPS.len());
+}
+
+#[tokio::test]
+async fn responses_are_fetched_again_without_a_cache() {
+    let transport = Canned::new(
+        StatusCode::OK,
+        include_str!("fixtures/511_stop_monitoring_sf.json"),
+    );
+    let upstream = Upstream::new(&Config {
+        api_keys: vec!["key".to_owned()],
+        whole_agency_feed: Some(false),
+        upstream_cache_secs: 0,
+        ..Config::default()
+    });
+
+    for _ in 0..2 {
+        departures::fetch(
+            &transport,
+            &upstream,
+            &stops(),
+            &Grouping::default(),
+            &Trips::default(),
+        )
+        .await
+        .unwrap();
+    }
+
+    assert_eq!(transport.queried.lock().unwrap().len(), 2 * STOPS.len());
+}
+
+#[tokio::test]
+async fn whole_agency_feed_fetches_each_agency_once() {
+    let transport = Canned::new(
+        StatusCode::OK,
+        include_str!("fixtures/511_stop_monitoring_sf.json"),
+    );
+    let upstream = Upstream::new(&Config {
+        api_keys: vec!["key".to_owned()],
+        whole_agency_feed: Some(true),
+        ..Config::default()
+    });
+
+    departures::fetch(
+        &transport,
+        &upstream,
+        &mixed_stops(),
+        &Grouping::default(),
+        &Trips::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_queried(&transport, &[("CT", ""), ("SF", "")]);
+}
+
+#[tokio::test]
+async fn only_lone_stops_are_fetched_on_their_own_by_default() {
+    let transport = Canned::new(
+        StatusCode::OK,
+        include_str!("fixtures/511_stop_monitoring_sf.json"),
+    );
+
+    departures::fetch(
+        &transport,
+        &upstream(&["key"]),
+        &mixed_stops(),
+        &Grouping::default(),
+        &Trips::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_queried(&transport, &[("CT", "70012"), ("SF", "")]);
+}
+
+#[tokio::test]
+async fn agencies_are_fetched_concurrently_up_to_the_limit() {
+    let mut transport = Canned::new(
+        StatusCode::OK,
+        include_str!("fixtures/511_stop_monitoring_empty.json"),
+    );
+    transport.delay = Duration::from_millis(20);
+    let stops: Vec<Stop> = ["SF", "CT", "SB", "GF", "BA", "AC"]
+        .iter()
+        .map(|agency| Stop {
+            code: "1".to_owned(),
+            walk_minutes: 0,
+            agency: Some((*agency).to_owned()),
+        })
+        .collect();
+
+    departures::fetch(
+        &transport,
+        &upstream(&["key"]),
+        &stops,
+        &Grouping::default(),
+        &Trips::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(transport.queried.lock().unwrap().len(), 6);
+    assert_eq!(
+        transport.peak_in_flight.load(Ordering::SeqCst),
+        departures::MAX_CONCURRENT_FETCHES
+    );
+}
+
+#[tokio::test]
+async fn rate_limited_key_falls_through_to_the_next() {
+    let mut transport = Canned::new(
+        StatusCode::OK,
+        include_str!("fixtures/511_stop_monitoring_empty.json"),
+    );
+    transport.refused.push("first");
+
+    let directions = departures::fetch(
+        &transport,
+        &upstream(&["first", "second"]),
+        &stops()[..1],
+        &Grouping::default(),
+        &Trips::default(),
+    )
+    .await
+    .unwrap();
+
+    assert!(directions.is_empty());
+    assert_eq!(*transport.keys_used.lock().unwrap(), ["first", "second"]);
+}
+
+#[tokio::test]
+async fn hung_upstream_times_out() {
+    let mut transport = Canned::new(
+        StatusCode::OK,
+        include_str!("fixtures/511_stop_monitoring_empty.json"),
+    );
+    transport.delay = Duration::from_secs(60);
+    let upstream = Upstream::new(&Config {
+        api_keys: vec!["key".to_owned()],
+        upstream_timeout_secs: 1,
+        ..Config::default()
+    });
+
+    let error = departures::fetch(
+        &transport,
+        &upstream,
+        &stops()[..1],
+        &Grouping::default(),
+        &Trips::default(),
+    )
+    .await
+    .unwrap_err();
+
+    assert!(format!("{error}").contains("didn't answer"), "{error}");
+}
+
+#[tokio::test]
+async fn server_error_is_reported() {
+    let transport = Canned::new(StatusCode::INTERNAL_SERVER_ERROR, "oops");
+
+    let error = departures::fetch(
+        &transport,
+        &upstream(&["key"]),
+        &stops(),
+        &Grouping::default(),
+        &Trips::default(),
+    )
+    .await
+    .unwrap_err();
+
+    assert!(format!("{error}").contains("500"), "{error}");
+}
+
+#[tokio::test]
+async fn malformed_body_is_an_error() {
+    let transport = Canned::new(StatusCode::OK, "<html>maintenance</html>");
+
+    let result = departures::fetch(
+        &transport,
+        &upstream(&["key"]),
+        &stops(),
+        &Grouping::default(),
+        &Trips::default(),
+    )
+    .await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn format_is_only_asked_of_siri_endpoints() {
+    let upstream = Upstream::new(&Config {
+        api_keys: vec!["key".to_owned()],
+        siri_format: SiriFormat::Xml,
+        ..Config::default()
+    });
+
+    let siri = Canned::new(StatusCode::OK, "<Siri/>");
+    let _ = departures::fetch(
+        &siri,
+        &upstream,
+        &stops()[..1],
+        &Grouping::default(),
+        &Trips::default(),
+    )
+    .await;
+    assert_eq!(*siri.formats.lock().unwrap(), [Some("xml".to_owned())]);
+
+    let gtfs = Canned::new(StatusCode::OK, r#"{"Entities": []}"#);
+    let alerts = AlertsCache::new(Some(AlertsConfig::default()))
+        .get(&gtfs, &upstream)
+        .await
+        .unwrap();
+    assert!(alerts.is_empty());
+    assert_eq!(*gtfs.formats.lock().unwrap(), [None]);
+}
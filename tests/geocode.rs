@@ -0,0 +1,63 @@
+//! Finding stops near a place, without a geocoder: coordinates are answered
+//! locally whatever the provider.
+
+use transit_kindle_playground::{
+    geocode::{self, Geocoder, Offline, Place},
+    siri::StopPoint,
+};
+
+fn stop(code: &str, lat: f64, lon: f64) -> StopPoint {
+    StopPoint {
+        code: code.to_owned(),
+        name: code.to_owned(),
+        lat,
+        lon,
+    }
+}
+
+#[tokio::test]
+async fn offline_geocoder_reads_coordinates() {
+    let places = Offline.search(" 37.7764, -122.4394 ").await.unwrap();
+
+    assert_eq!(places.len(), 1);
+    assert_eq!((places[0].lat, places[0].lon), (37.7764, -122.4394));
+}
+
+#[tokio::test]
+async fn offline_geocoder_rejects_addresses() {
+    assert!(Offline.search("1 Market St").await.is_err());
+}
+
+#[test]
+fn distance_is_along_the_ground() {
+    let ferry_building = Place {
+        name: "Ferry Building".to_owned(),
+        lat: 37.7955,
+        lon: -122.3937,
+    };
+
+    // Civic Center is about 2.9 km away.
+    let meters = ferry_building.meters_to(37.7793, -122.4193);
+    assert!((2_700.0..3_100.0).contains(&meters), "{meters}");
+    assert_eq!(ferry_building.meters_to(37.7955, -122.3937), 0.0);
+}
+
+#[test]
+fn nearest_stops_come_first() {
+    let place = Place {
+        name: "here".to_owned(),
+        lat: 37.7764,
+        lon: -122.4394,
+    };
+    let stops = vec![
+        stop("far", 37.80, -122.40),
+        stop("near", 37.7765, -122.4394),
+        stop("middle", 37.78, -122.44),
+    ];
+
+    let codes: Vec<_> = geocode::nearest(&place, stops)
+        .into_iter()
+        .map(|nearby| nearby.stop.code)
+        .collect();
+    assert_eq!(codes, ["near", "middle", "far"]);
+}
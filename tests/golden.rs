@@ -0,0 +1,140 @@
+//! Snapshot tests that draw boards from hand-made payloads at a pinned time
+//! and compare them with the reference images in `tests/golden/`, so a layout
+//! or rendering change can't shift the board unnoticed.
+//!
+//! Run with `UPDATE_GOLDEN=1` to write the current output as the new
+//! references after checking it by eye. A missing reference fails the test
+//! otherwise, so a checkout without the images can't pass by writing them.
+
+use std::{fs, path::Path};
+
+use chrono::{DateTime, Utc};
+use transit_kindle_playground::{
+    board::Board,
+    departures::{self, Grouping},
+    layout::Layout,
+    render::{self, Output},
+    siri::StopMonitoringResponse,
+    trips::Trips,
+};
+
+const STOPS: [&str; 4] = ["15419", "16996", "15692", "15696"];
+
+/// Channel values may differ this much before a pixel counts as changed, to
+/// absorb antialiasing differences between skia builds.
+const PIXEL_TOLERANCE: u8 = 24;
+
+/// The share of pixels that may change before the images count as different.
+const CHANGED_PIXELS_TOLERANCE: f64 = 0.002;
+
+fn board(payload: &str) -> Board {
+    let response: StopMonitoringResponse =
+        serde_json::from_str(payload).expect("fixture deserializes");
+    let now: DateTime<Utc> = "2024-02-01T18:05:00Z".parse().unwrap();
+
+    Board {
+        departures: departures::group(response, &STOPS, &Grouping::default(), &Trips::default()),
+        timezone: Some(chrono_tz::America::Los_Angeles),
+        now: Some(now),
+        ..Board::default()
+    }
+}
+
+/// Width, height and one gray value per pixel.
+fn decode(png: &[u8]) -> (u32, u32, Vec<u8>) {
+    let mut decoder = png::Decoder::new(png);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().expect("image is a PNG");
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).expect("PNG decodes");
+
+    // Boards are gray, so the first channel of any color type will do.
+    let gray = pixels[..info.buffer_size()]
+        .chunks(info.color_type.samples())
+        .map(|pixel| pixel[0])
+        .collect();
+
+    (info.width, info.height, gray)
+}
+
+/// Draw `board` with the layout at `layout_path` and compare it with
+/// `tests/golden/<name>.png`. On a mismatch the output is written next to the
+/// reference as `<name>.actual.png`.
+fn assert_golden(name: &str, layout_path: &str, board: &Board) {
+    let layout = Layout::load(Path::new(layout_path)).expect("layout loads");
+    let actual = render::draw_image(&layout, board, &Output::default()).expect("board draws");
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let reference_path = dir.join(format!("{name}.png"));
+    let actual_path = dir.join(format!("{name}.actual.png"));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&reference_path, &actual).unwrap();
+        let _ = fs::remove_file(&actual_path);
+        eprintln!("wrote {}", reference_path.display());
+        return;
+    }
+
+    let reference = match fs::read(&reference_path) {
+        Ok(reference) => reference,
+        Err(e) => {
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(&actual_path, &actual).unwrap();
+            panic!(
+                "{name}: couldn't read {} ({e}), wrote {}; \
+                 rerun with UPDATE_GOLDEN=1 to make it the reference",
+                reference_path.display(),
+                actual_path.display()
+            );
+        }
+    };
+    let (width, height, expected) = decode(&reference);
+    let (actual_width, actual_height, got) = decode(&actual);
+
+    let changed = if (width, height) == (actual_width, actual_height) {
+        expected
+            .iter()
+            .zip(&got)
+            .filter(|(expected, got)| expected.abs_diff(**got) > PIXEL_TOLERANCE)
+            .count()
+    } else {
+        expected.len().max(got.len())
+    };
+    let allowed = (expected.len() as f64 * CHANGED_PIXELS_TOLERANCE) as usize;
+
+    if changed > allowed {
+        fs::write(&actual_path, &actual).unwrap();
+        panic!(
+            "{name}: {changed} pixels differ from {} (at most {allowed} may), wrote {}; \
+             rerun with UPDATE_GOLDEN=1 if the change is intended",
+            reference_path.display(),
+            actual_path.display()
+        );
+    }
+    let _ = fs::remove_file(&actual_path);
+}
+
+#[test]
+#[ignore = "no reference image checked in yet; generate it with UPDATE_GOLDEN=1"]
+fn sf_landscape() {
+    let board = board(include_str!("fixtures/511_stop_monitoring_sf.json"));
+
+    assert_golden("sf_landscape", "layouts/default.toml", &board);
+}
+
+#[test]
+#[ignore = "no reference image checked in yet; generate it with UPDATE_GOLDEN=1"]
+fn sf_portrait() {
+    let board = board(include_str!("fixtures/511_stop_monitoring_sf.json"));
+
+    assert_golden("sf_portrait", "layouts/portrait.toml", &board);
+}
+
+#[test]
+#[ignore = "no reference image checked in yet; generate it with UPDATE_GOLDEN=1"]
+fn empty_landscape() {
+    let board = board(include_str!("fixtures/511_stop_monitoring_empty.json"));
+
+    assert_golden("empty_landscape", "layouts/default.toml", &board);
+}
@@ -0,0 +1,82 @@
+//! Reading GTFS `shapes.txt` for the map panel, and the map edges a layout
+//! may give.
+
+use std::fs;
+
+use transit_kindle_playground::{layout::Layout, shapes};
+
+#[test]
+fn columns_are_found_by_name() {
+    let shapes = shapes::parse(
+        "shape_pt_sequence,shape_pt_lon,shape_id,shape_pt_lat\n\
+         1,-122.40,A,37.70\n\
+         2,-122.41,A,37.71\n",
+    )
+    .unwrap();
+
+    assert_eq!(shapes.len(), 1);
+    assert_eq!(shapes[0].points, [(37.70, -122.40), (37.71, -122.41)]);
+    assert_eq!(shapes[0].extent, (37.70, -122.41, 37.71, -122.40));
+}
+
+#[test]
+fn points_are_ordered_by_sequence() {
+    let shapes = shapes::parse(
+        "\u{feff}shape_id,shape_pt_lat,shape_pt_lon,shape_pt_sequence\n\
+         A,37.72,-122.42,30\n\
+         B,37.80,-122.30,1\n\
+         A,37.70,-122.40,10\n\
+         \n\
+         A,37.71,-122.41,20\n",
+    )
+    .unwrap();
+
+    assert_eq!(shapes.len(), 2);
+    assert_eq!(
+        shapes[0].points,
+        [(37.70, -122.40), (37.71, -122.41), (37.72, -122.42)]
+    );
+    assert_eq!(shapes[1].points, [(37.80, -122.30)]);
+}
+
+#[test]
+fn malformed_lines_are_reported_by_line_number() {
+    let error = shapes::parse(
+        "shape_id,shape_pt_lat,shape_pt_lon,shape_pt_sequence\n\
+         A,37.70,-122.40,1\n\
+         A,north,-122.41,2\n",
+    )
+    .unwrap_err();
+
+    assert!(format!("{error}").contains("line 3"), "{error}");
+}
+
+#[test]
+fn missing_columns_are_an_error() {
+    assert!(shapes::parse("shape_id,shape_pt_lat,shape_pt_lon\nA,37.7,-122.4\n").is_err());
+    assert!(shapes::parse("").is_err());
+    assert!(shapes::parse("shape_id,shape_pt_lat,shape_pt_lon,shape_pt_sequence\n").is_err());
+}
+
+#[test]
+fn map_edges_must_enclose_an_area() {
+    let layout = |north: f64, south: f64| {
+        format!(
+            "width = 800.0\nheight = 600.0\nfont = {{ family = \"DejaVu Sans\", size = 20.0 }}\n\n\
+             [[panels]]\ntype = \"map\"\nrect = {{ x = 0.0, y = 0.0, width = 800.0, height = 600.0 }}\n\
+             north = {north}\nsouth = {south}\neast = -122.3\nwest = -122.5\n"
+        )
+    };
+    let dir = std::env::temp_dir().join(format!("transit-kindle-map-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (valid, inverted) = (dir.join("valid.toml"), dir.join("inverted.toml"));
+    fs::write(&valid, layout(37.8, 37.7)).unwrap();
+    fs::write(&inverted, layout(37.7, 37.8)).unwrap();
+
+    let loaded = Layout::load(&valid);
+    let error = Layout::load(&inverted).unwrap_err();
+    fs::remove_dir_all(&dir).unwrap();
+
+    loaded.unwrap();
+    assert!(format!("{error}").contains("north"), "{error}");
+}
@@ -0,0 +1,302 @@
+//! Golden tests over hand-made 511 StopMonitoring payloads, so changes to the
+//! SIRI types or their serde attributes can't silently drop arrival fields.
+
+use std::collections::BTreeMap;
+
+use transit_kindle_playground::{
+    departures::{self, Grouping},
+    quirks,
+    siri::StopMonitoringResponse,
+    trips::Trips,
+};
+
+const STOPS: [&str; 4] = ["15419", "16996", "15692", "15696"];
+
+fn parse(payload: &str) -> StopMonitoringResponse {
+    serde_json::from_str(payload).expect("fixture deserializes")
+}
+
+/// (direction, line, destination) to the expected arrival times of each group,
+/// in board order.
+fn extract(response: StopMonitoringResponse) -> BTreeMap<(String, String, String), Vec<String>> {
+    let mut extracted = BTreeMap::new();
+
+    for (direction, lines_destinations_to_journeys) in
+        departures::group(response, &STOPS, &Grouping::default(), &Trips::default())
+    {
+        for ((line, destination), journeys) in lines_destinations_to_journeys {
+            let times = journeys
+                .iter()
+                .map(|j| {
+                    j.monitored_call
+                        .expected_arrival_time
+                        .clone()
+                        .unwrap_or_else(|| "-".to_owned())
+                })
+                .collect();
+
+            extracted.insert((direction.clone(), line, destination), times);
+        }
+    }
+
+    extracted
+}
+
+fn key(direction: &str, line: &str, destination: &str) -> (String, String, String) {
+    (
+        direction.to_owned(),
+        line.to_owned(),
+        destination.to_owned(),
+    )
+}
+
+#[test]
+fn sf_stop_monitoring_fields() {
+    let response = parse(include_str!("fixtures/511_stop_monitoring_sf.json"));
+    let visits = &response
+        .service_delivery
+        .stop_monitoring_delivery
+        .monitored_stop_visit;
+
+    assert_eq!(visits.len(), 7);
+
+    let journey = &visits[0].monitored_vehicle_journey;
+    assert_eq!(journey.line_ref.as_deref(), Some("24"));
+    assert_eq!(journey.direction_ref.as_deref(), Some("IB"));
+    assert_eq!(
+        journey.destination_name.as_deref(),
+        Some("Divisadero St & Jackson St")
+    );
+    assert_eq!(journey.monitored_call.stop_point_ref, "15419");
+    assert_eq!(
+        journey.monitored_call.destination_display.as_deref(),
+        Some("Divisadero + Jackson")
+    );
+    assert_eq!(
+        journey.monitored_call.expected_arrival_time.as_deref(),
+        Some("2024-02-01T18:10:37Z")
+    );
+
+    assert_eq!(
+        journey.monitored_call.aimed_arrival_time.as_deref(),
+        Some("2024-02-01T18:09:54Z")
+    );
+    assert_eq!(journey.monitored, Some(true));
+    assert_eq!(journey.occupancy.as_deref(), Some("seatsAvailable"));
+
+    let unpredicted = &visits[4].monitored_vehicle_journey;
+    assert_eq!(unpredicted.monitored_call.expected_arrival_time, None);
+    assert_eq!(unpredicted.monitored, Some(false));
+
+    let no_direction = &visits[5].monitored_vehicle_journey;
+    assert_eq!(no_direction.direction_ref, None);
+}
+
+#[test]
+fn sf_stop_monitoring_extracted_arrivals() {
+    let response = parse(include_str!("fixtures/511_stop_monitoring_sf.json"));
+
+    let expected = BTreeMap::from([
+        (
+            key("IB", "24", "Divisadero + Jackson"),
+            vec![
+                "2024-02-01T18:10:37Z".to_owned(),
+                "2024-02-01T18:22:05Z".to_owned(),
+            ],
+        ),
+        (
+            key("IB", "J", "Embarcadero Station"),
+            vec!["2024-02-01T18:08:00Z".to_owned()],
+        ),
+        (key("OB", "24", "Third + Palou"), vec!["-".to_owned()]),
+        // Has no DirectionRef, but stop 15692 is outbound for the 48.
+        (
+            key("OB", "35", "Diamond Heights"),
+            vec!["2024-02-01T18:12:41Z".to_owned()],
+        ),
+        (
+            key("OB", "48", "West Portal"),
+            vec!["2024-02-01T18:06:12Z".to_owned()],
+        ),
+    ]);
+
+    assert_eq!(extract(response), expected);
+}
+
+#[test]
+fn empty_stop_monitoring() {
+    let response = parse(include_str!("fixtures/511_stop_monitoring_empty.json"));
+
+    assert!(extract(response).is_empty());
+}
+
+#[test]
+fn malformed_visit_is_skipped() {
+    let response = parse(
+        r#"{
+          "ServiceDelivery": {
+            "StopMonitoringDelivery": {
+              "MonitoredStopVisit": [
+                {
+                  "MonitoredVehicleJourney": {
+                    "LineRef": "N",
+                    "DirectionRef": "IB",
+                    "DestinationName": "Caltrain",
+                    "MonitoredCall": {
+                      "StopPointRef": "15419",
+                      "ExpectedArrivalTime": "2024-02-01T18:10:00Z",
+                      "DestinationDisplay": "Caltrain"
+                    }
+                  }
+                },
+                { "MonitoredVehicleJourney": { "LineRef": 7 } }
+              ]
+            }
+          }
+        }"#,
+    );
+
+    assert_eq!(
+        extract(response),
+        BTreeMap::from([(
+            key("IB", "N", "Caltrain"),
+            vec!["2024-02-01T18:10:00Z".to_owned()]
+        )])
+    );
+}
+
+#[test]
+fn byte_order_mark_and_numeric_refs() {
+    let response: StopMonitoringResponse = quirks::from_json(
+        "\u{feff}{
+          \"ServiceDelivery\": {
+            \"StopMonitoringDelivery\": {
+              \"MonitoredStopVisit\": [
+                {
+                  \"MonitoredVehicleJourney\": {
+                    \"LineRef\": 14,
+                    \"DirectionRef\": \"OB\",
+                    \"Monitored\": \"true\",
+                    \"MonitoredCall\": {
+                      \"StopPointRef\": 15419,
+                      \"ExpectedArrivalTime\": \"2024-02-01T18:10:00Z\",
+                      \"DestinationDisplay\": \"Daly City\"
+                    }
+                  }
+                }
+              ]
+            }
+          }
+        }",
+    )
+    .expect("quirky payload deserializes");
+
+    let journey = &response
+        .service_delivery
+        .stop_monitoring_delivery
+        .monitored_stop_visit[0]
+        .monitored_vehicle_journey;
+    assert_eq!(journey.monitored, Some(true));
+    assert_eq!(
+        extract(response),
+        BTreeMap::from([(
+            key("OB", "14", "Daly City"),
+            vec!["2024-02-01T18:10:00Z".to_owned()]
+        )])
+    );
+}
+
+#[test]
+fn siri_xml() {
+    let response: StopMonitoringResponse = quirks::from_json_or_xml(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+        <Siri xmlns="http://www.siri.org.uk/siri" version="1.4">
+          <ServiceDelivery>
+            <StopMonitoringDelivery version="1.4">
+              <MonitoredStopVisit>
+                <RecordedAtTime>2024-02-01T18:05:00Z</RecordedAtTime>
+                <MonitoredVehicleJourney>
+                  <LineRef>24</LineRef>
+                  <DirectionRef>IB</DirectionRef>
+                  <DestinationName xml:lang="en">Divisadero St &amp; Jackson St</DestinationName>
+                  <Monitored>true</Monitored>
+                  <MonitoredCall>
+                    <StopPointRef>15419</StopPointRef>
+                    <ExpectedArrivalTime>2024-02-01T18:10:37Z</ExpectedArrivalTime>
+                    <DestinationDisplay>Divisadero + Jackson</DestinationDisplay>
+                  </MonitoredCall>
+                </MonitoredVehicleJourney>
+              </MonitoredStopVisit>
+            </StopMonitoringDelivery>
+          </ServiceDelivery>
+        </Siri>"#,
+    )
+    .expect("XML deserializes");
+
+    let journey = &response
+        .service_delivery
+        .stop_monitoring_delivery
+        .monitored_stop_visit[0]
+        .monitored_vehicle_journey;
+    assert_eq!(
+        journey.destination_name.as_deref(),
+        Some("Divisadero St & Jackson St")
+    );
+    assert_eq!(journey.monitored, Some(true));
+    assert_eq!(
+        extract(response),
+        BTreeMap::from([(
+            key("IB", "24", "Divisadero + Jackson"),
+            vec!["2024-02-01T18:10:37Z".to_owned()]
+        )])
+    );
+}
+
+#[test]
+fn caltrain_platform() {
+    let response = parse(
+        r#"{
+          "ServiceDelivery": {
+            "StopMonitoringDelivery": {
+              "MonitoredStopVisit": [
+                {
+                  "MonitoredVehicleJourney": {
+                    "LineRef": "Local Weekday",
+                    "DirectionRef": "S",
+                    "Monitored": true,
+                    "MonitoredCall": {
+                      "StopPointRef": "70012",
+                      "ExpectedArrivalTime": "2024-02-01T18:10:00Z",
+                      "DestinationDisplay": "San Jose Diridon",
+                      "ArrivalPlatformName": "2"
+                    }
+                  }
+                },
+                {
+                  "MonitoredVehicleJourney": {
+                    "LineRef": "Local Weekday",
+                    "DirectionRef": "S",
+                    "Monitored": true,
+                    "MonitoredCall": {
+                      "StopPointRef": "70012",
+                      "ExpectedArrivalTime": "2024-02-01T18:40:00Z",
+                      "DestinationDisplay": "San Jose Diridon"
+                    }
+                  }
+                }
+              ]
+            }
+          }
+        }"#,
+    );
+
+    let directions = departures::group(
+        response,
+        &["70012"],
+        &Grouping::default(),
+        &Trips::default(),
+    );
+    let journeys = &directions["S"][&("Local Weekday".to_owned(), "San Jose Diridon".to_owned())];
+    assert_eq!(journeys.len(), 2);
+    assert_eq!(departures::platform(journeys), Some("2"));
+}
@@ -0,0 +1,32 @@
+//! Reading GTFS `trips.txt` for the direction of each trip.
+
+use transit_kindle_playground::trips;
+
+#[test]
+fn columns_are_found_by_name() {
+    let trips = trips::parse(
+        "\u{feff}route_id,direction_id,service_id,trip_id\n\
+         N,1,weekday,\"11437085\"\n\
+         N,0,weekday,11437086\n\
+         \n\
+         J,,weekday,11437087\n",
+    )
+    .unwrap();
+
+    assert_eq!(trips.len(), 2);
+    assert_eq!(trips["11437085"], "1");
+    assert_eq!(trips["11437086"], "0");
+}
+
+#[test]
+fn missing_columns_are_an_error() {
+    let error = trips::parse("route_id,trip_id\nN,1\n").unwrap_err();
+
+    assert!(format!("{error}").contains("direction_id"));
+}
+
+#[test]
+fn no_directions_is_an_error() {
+    assert!(trips::parse("trip_id,direction_id\n1,\n").is_err());
+    assert!(trips::parse("").is_err());
+}
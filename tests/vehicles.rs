@@ -0,0 +1,116 @@
+//! Vehicle positions from a hand-made 511 VehicleMonitoring payload, matched
+//! up with the journeys of a hand-made StopMonitoring payload.
+
+use std::collections::HashMap;
+
+use transit_kindle_playground::{
+    departures::{self, Grouping},
+    siri::StopMonitoringResponse,
+    trips::Trips,
+    vehicles::{self, Vehicle},
+};
+
+const STOPS: [&str; 4] = ["15419", "16996", "15692", "15696"];
+
+fn vehicles() -> Vec<Vehicle> {
+    vehicles::parse(include_str!("fixtures/511_vehicle_monitoring_sf.json"))
+        .expect("fixture parses")
+}
+
+/// How many stops away each journey's vehicle is, by line and stop.
+fn located() -> HashMap<(String, String), Vec<Option<u32>>> {
+    let response: StopMonitoringResponse =
+        serde_json::from_str(include_str!("fixtures/511_stop_monitoring_sf.json"))
+            .expect("fixture deserializes");
+    let mut departures =
+        departures::group(response, &STOPS, &Grouping::default(), &Trips::default());
+    vehicles::locate(&mut departures, &vehicles());
+
+    let mut located: HashMap<_, Vec<_>> = HashMap::new();
+    for journeys in departures.values().flat_map(|by_line| by_line.values()) {
+        for journey in journeys {
+            let key = (
+                journey.line_ref.clone().unwrap_or_default(),
+                journey.monitored_call.stop_point_ref.clone(),
+            );
+            located.entry(key).or_default().push(journey.stops_away);
+        }
+    }
+    located
+}
+
+fn key(line: &str, stop: &str) -> (String, String) {
+    (line.to_owned(), stop.to_owned())
+}
+
+#[test]
+fn malformed_vehicles_are_skipped() {
+    let vehicles = vehicles();
+
+    let refs: Vec<_> = vehicles
+        .iter()
+        .map(|vehicle| vehicle.vehicle.as_deref().unwrap_or_default())
+        .collect();
+    assert_eq!(refs, ["5822", "8719", "2039", "5501"]);
+}
+
+#[test]
+fn vehicles_carry_their_trip_location_and_calls() {
+    let vehicles = vehicles();
+
+    assert_eq!(
+        vehicles[0],
+        Vehicle {
+            line: Some("24".to_owned()),
+            trip: Some("2024-02-01/11446315_M11".to_owned()),
+            vehicle: Some("5822".to_owned()),
+            location: Some((37.7389, -122.41913)),
+            stops: vec!["15417".to_owned(), "15418".to_owned(), "15419".to_owned()],
+        }
+    );
+    // Numbers where 511 usually sends strings.
+    assert_eq!(vehicles[1].location, Some((37.75211, -122.43021)));
+    assert_eq!(vehicles[1].stops, ["15692"]);
+    assert_eq!(vehicles[3].location, None);
+    assert!(vehicles[3].stops.is_empty());
+}
+
+#[test]
+fn stops_away_counts_the_stop_itself() {
+    let vehicles = vehicles();
+
+    assert_eq!(vehicles[0].stops_away("15417"), Some(1));
+    assert_eq!(vehicles[0].stops_away("15419"), Some(3));
+    assert_eq!(vehicles[0].stops_away("15696"), None);
+}
+
+#[test]
+fn a_lone_vehicle_is_a_list_of_one() {
+    let vehicles = vehicles::parse(
+        r#"{"ServiceDelivery": {"VehicleMonitoringDelivery": {"VehicleActivity": {
+            "MonitoredVehicleJourney": {"LineRef": "N", "VehicleRef": "1400",
+            "MonitoredCall": {"StopPointRef": "15419"}}}}}}"#,
+    )
+    .unwrap();
+
+    assert_eq!(vehicles.len(), 1);
+    assert_eq!(vehicles[0].stops, ["15419"]);
+}
+
+#[test]
+fn journeys_are_matched_by_trip() {
+    let located = located();
+
+    // The first 24 inbound is three stops out; the others aren't in the feed.
+    assert_eq!(located[&key("24", "15419")], [Some(3), None]);
+}
+
+#[test]
+fn journeys_without_a_trip_in_the_feed_match_by_vehicle_and_line() {
+    assert_eq!(located()[&key("48", "15692")], [Some(1)]);
+}
+
+#[test]
+fn a_different_trip_on_the_same_vehicle_does_not_match() {
+    assert_eq!(located()[&key("J", "16996")], [None]);
+}
@@ -0,0 +1,37 @@
+//! Reading Open-Meteo forecasts, whose daily times come in the board's
+//! timezone.
+
+use chrono::{DateTime, Utc};
+use chrono_tz::America::Los_Angeles;
+use transit_kindle_playground::weather::{self, Sky};
+
+const FORECAST: &str = r#"{
+    "latitude": 37.76,
+    "longitude": -122.43,
+    "utc_offset_seconds": -28800,
+    "timezone": "America/Los_Angeles",
+    "current": {"time": "2024-02-01T17:45", "temperature_2m": 54.3, "weather_code": 61},
+    "hourly": {"time": ["2024-02-01T17:00", "2024-02-01T18:00"], "precipitation_probability": [40, 65]},
+    "daily": {"time": ["2024-02-01"], "sunrise": ["2024-02-01T07:12"], "sunset": ["2024-02-01T17:34"]}
+}"#;
+
+fn instant(text: &str) -> DateTime<Utc> {
+    text.parse().unwrap()
+}
+
+#[test]
+fn sunrise_and_sunset_are_read_in_the_board_timezone() {
+    let weather = weather::parse(FORECAST, Los_Angeles).unwrap();
+
+    assert_eq!(weather.sunrise, Some(instant("2024-02-01T15:12:00Z")));
+    assert_eq!(weather.sunset, Some(instant("2024-02-02T01:34:00Z")));
+}
+
+#[test]
+fn rain_chance_is_the_highest_in_the_next_hours() {
+    let weather = weather::parse(FORECAST, Los_Angeles).unwrap();
+
+    assert_eq!(weather.sky, Sky::Rain);
+    assert_eq!(weather.precipitation_chance, 65);
+    assert_eq!(weather.label(), "54° · 65% rain");
+}